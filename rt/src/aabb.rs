@@ -0,0 +1,102 @@
+use crate::ray::Ray;
+use vec3::{Point3, Vec3};
+
+/// An axis-aligned bounding box, stored as its minimum and maximum corner.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Self {
+        Aabb { minimum, maximum }
+    }
+
+    /// Slab test: returns true when the ray overlaps the box somewhere in (t_min, t_max).
+    pub fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        for a in 0..3 {
+            let inv_d = 1.0 / axis(&r.direction, a);
+            let mut t0 = (axis(&self.minimum, a) - axis(&r.origin, a)) * inv_d;
+            let mut t1 = (axis(&self.maximum, a) - axis(&r.origin, a)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the smallest box enclosing both boxes.
+    pub fn surrounding_box(box0: &Aabb, box1: &Aabb) -> Aabb {
+        let small = Point3::new(
+            box0.minimum.x.min(box1.minimum.x),
+            box0.minimum.y.min(box1.minimum.y),
+            box0.minimum.z.min(box1.minimum.z),
+        );
+        let big = Point3::new(
+            box0.maximum.x.max(box1.maximum.x),
+            box0.maximum.y.max(box1.maximum.y),
+            box0.maximum.z.max(box1.maximum.z),
+        );
+        Aabb::new(small, big)
+    }
+
+    /// The part of (t_min, t_max) where the ray is inside the box, if any.
+    pub fn span(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for a in 0..3 {
+            let inv_d = 1.0 / axis(&r.direction, a);
+            let mut t0 = (axis(&self.minimum, a) - axis(&r.origin, a)) * inv_d;
+            let mut t1 = (axis(&self.maximum, a) - axis(&r.origin, a)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+
+    /// Returns the box both boxes cover, or None when they don't overlap.
+    pub fn intersection(box0: &Aabb, box1: &Aabb) -> Option<Aabb> {
+        let small = Point3::new(
+            box0.minimum.x.max(box1.minimum.x),
+            box0.minimum.y.max(box1.minimum.y),
+            box0.minimum.z.max(box1.minimum.z),
+        );
+        let big = Point3::new(
+            box0.maximum.x.min(box1.maximum.x),
+            box0.maximum.y.min(box1.maximum.y),
+            box0.maximum.z.min(box1.maximum.z),
+        );
+        if small.x > big.x || small.y > big.y || small.z > big.z {
+            return None;
+        }
+        Some(Aabb::new(small, big))
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+}
+
+/// Returns the x, y or z component of a vector for axis 0, 1 or 2.
+pub fn axis(v: &Vec3, a: usize) -> f64 {
+    match a {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
@@ -0,0 +1,106 @@
+use crate::aabb::{axis, Aabb};
+use crate::hit::{HitRecord, Hittable, HittableList, HittablePtr};
+use crate::ray::Ray;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+/// A node of a bounding volume hierarchy. Every node stores the box enclosing both of its
+/// children so a ray that misses the box can skip the whole subtree.
+pub struct BvhNode {
+    left: HittablePtr,
+    right: HittablePtr,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    /// Builds a hierarchy over all objects of the list. Every object needs a bounding box.
+    pub fn new(list: &HittableList, time0: f64, time1: f64) -> Self {
+        let mut objects = list.objects().to_vec();
+        BvhNode::new_from_objects(&mut objects, time0, time1)
+    }
+
+    /// Recursively splits the objects in half along the axis where their centroids are
+    /// spread out the most.
+    pub fn new_from_objects(objects: &mut [HittablePtr], time0: f64, time1: f64) -> Self {
+        assert!(
+            !objects.is_empty(),
+            "Can't build a BvhNode without any objects."
+        );
+
+        let boxes: Vec<Aabb> = objects
+            .iter()
+            .map(|o| BvhNode::box_of(o, time0, time1))
+            .collect();
+        let centroids = boxes.iter().skip(1).fold(
+            Aabb::new(boxes[0].centroid(), boxes[0].centroid()),
+            |b, o| Aabb::surrounding_box(&b, &Aabb::new(o.centroid(), o.centroid())),
+        );
+        let extent = centroids.maximum - centroids.minimum;
+        let split_axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        let (left, right): (HittablePtr, HittablePtr) = match objects.len() {
+            1 => (Arc::clone(&objects[0]), Arc::clone(&objects[0])),
+            2 => (Arc::clone(&objects[0]), Arc::clone(&objects[1])),
+            len => {
+                objects.sort_by(|a, b| BvhNode::box_compare(a, b, split_axis, time0, time1));
+                let (first, second) = objects.split_at_mut(len / 2);
+                (
+                    Arc::new(BvhNode::new_from_objects(first, time0, time1)),
+                    Arc::new(BvhNode::new_from_objects(second, time0, time1)),
+                )
+            }
+        };
+
+        let bbox = Aabb::surrounding_box(
+            &BvhNode::box_of(&left, time0, time1),
+            &BvhNode::box_of(&right, time0, time1),
+        );
+
+        BvhNode { left, right, bbox }
+    }
+
+    fn box_of(object: &HittablePtr, time0: f64, time1: f64) -> Aabb {
+        object
+            .bounding_box(time0, time1)
+            .expect("No bounding box in BvhNode constructor.")
+    }
+
+    fn box_compare(
+        a: &HittablePtr,
+        b: &HittablePtr,
+        split_axis: usize,
+        time0: f64,
+        time1: f64,
+    ) -> Ordering {
+        let a = axis(&BvhNode::box_of(a, time0, time1).centroid(), split_axis);
+        let b = axis(&BvhNode::box_of(b, time0, time1).centroid(), split_axis);
+        a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(r, t_min, t_max);
+        let closest_so_far = match &hit_left {
+            Some(rec) => rec.t,
+            None => t_max,
+        };
+        let hit_right = self.right.hit(r, t_min, closest_so_far);
+
+        hit_right.or(hit_left)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
@@ -14,7 +14,37 @@ pub struct Camera {
     t1: f64,
 }
 
+/// All parameters `Camera::new` takes, so they can be stored and modified before building
+/// the camera.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aspect_ratio: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub t0: f64,
+    pub t1: f64,
+}
+
 impl Camera {
+    pub fn new_from_settings(settings: &CameraSettings) -> Camera {
+        Camera::new(
+            settings.lookfrom,
+            settings.lookat,
+            settings.vup,
+            settings.vfov,
+            settings.aspect_ratio,
+            settings.aperture,
+            settings.focus_dist,
+            settings.t0,
+            settings.t1,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -58,7 +88,7 @@ impl Camera {
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
         let rd = Vec3::random_in_unit_disk() * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
-        let mut rng = rand::thread_rng();
+        let mut rng = vec3::rng::thread_rng();
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
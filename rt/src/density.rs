@@ -0,0 +1,290 @@
+//! Spatially varying densities for heterogeneous media.
+
+use crate::aabb::Aabb;
+use crate::perlin::Perlin;
+use crate::texture::TexturePtr;
+use std::sync::Arc;
+use vec3::Point3;
+
+pub trait Density {
+    /// The density at `p`, never negative.
+    fn density(&self, p: &Point3) -> f64;
+    /// An upper bound of `density` everywhere, used as the majorant for delta tracking.
+    /// Tighter bounds make tracking faster.
+    fn max_density(&self) -> f64;
+    /// A box outside of which the density is zero, if there is one.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+pub type DensityPtr = Arc<dyn Density + Send + Sync>;
+
+/// The average of the channels of a 3D texture, evaluated at the point itself, clamped to
+/// [0, 1].
+pub struct TextureDensity {
+    texture: TexturePtr,
+}
+
+impl TextureDensity {
+    pub fn new(texture: TexturePtr) -> Self {
+        TextureDensity { texture }
+    }
+}
+
+impl Density for TextureDensity {
+    fn density(&self, p: &Point3) -> f64 {
+        let c = self.texture.value(0.0, 0.0, p);
+        num::clamp((c.x + c.y + c.z) / 3.0, 0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Perlin turbulence, clamped to [0, 1], for clouds and smoke.
+pub struct PerlinDensity {
+    noise: Perlin,
+    frequency: f64,
+    octaves: u8,
+}
+
+impl PerlinDensity {
+    /// `frequency` scales the points before the lookup, `octaves` is the number of noise
+    /// layers summed up.
+    pub fn new(frequency: f64, octaves: u8) -> Self {
+        PerlinDensity {
+            noise: Perlin::new(),
+            frequency,
+            octaves: octaves.max(1),
+        }
+    }
+}
+
+impl Density for PerlinDensity {
+    fn density(&self, p: &Point3) -> f64 {
+        num::clamp(
+            self.noise.turb(&(*p * self.frequency), self.octaves),
+            0.0,
+            1.0,
+        )
+    }
+
+    fn max_density(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Values on a regular grid spanning the box from `min` to `max`, interpolated trilinearly
+/// between the voxel centers. The density is zero outside the box.
+pub struct GridDensity {
+    resolution: [usize; 3],
+    values: Vec<f64>,
+    min: Point3,
+    max: Point3,
+    max_value: f64,
+}
+
+impl GridDensity {
+    /// `values` holds the voxels with x varying fastest, then y, then z. Negative values
+    /// count as zero.
+    pub fn new(resolution: [usize; 3], values: Vec<f64>, min: Point3, max: Point3) -> Self {
+        assert_eq!(
+            values.len(),
+            resolution[0] * resolution[1] * resolution[2],
+            "values don't match the resolution"
+        );
+        let values: Vec<f64> = values.into_iter().map(|v| v.max(0.0)).collect();
+        let max_value = values.iter().cloned().fold(0.0, f64::max);
+        GridDensity {
+            resolution,
+            values,
+            min,
+            max,
+            max_value,
+        }
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+        self.values[(z * ny + y) * nx + x]
+    }
+}
+
+impl Density for GridDensity {
+    fn density(&self, p: &Point3) -> f64 {
+        let local = [
+            (p.x - self.min.x) / (self.max.x - self.min.x),
+            (p.y - self.min.y) / (self.max.y - self.min.y),
+            (p.z - self.min.z) / (self.max.z - self.min.z),
+        ];
+        if local.iter().any(|&l| !(0.0..=1.0).contains(&l)) {
+            return 0.0;
+        }
+        trilinear(&self.resolution, local, |x, y, z| self.voxel(x, y, z))
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_value
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+/// Interpolates between the centers of the voxels of a grid with `resolution` cells, at
+/// `local` coordinates in [0, 1]³ of the grid's box. Lookups past the outer centers
+/// clamp to the border voxels.
+pub(crate) fn trilinear(
+    resolution: &[usize; 3],
+    local: [f64; 3],
+    voxel: impl Fn(usize, usize, usize) -> f64,
+) -> f64 {
+    let mut index = [0; 3];
+    let mut next = [0; 3];
+    let mut t = [0.0; 3];
+    for axis in 0..3 {
+        let n = resolution[axis];
+        let x = (local[axis] * n as f64 - 0.5).max(0.0);
+        let i = (x as usize).min(n - 1);
+        index[axis] = i;
+        next[axis] = (i + 1).min(n - 1);
+        t[axis] = (x - i as f64).min(1.0);
+    }
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let pick = |axis: usize| corner >> axis & 1 == 1;
+        let mut weight = 1.0;
+        let mut at = [0; 3];
+        for axis in 0..3 {
+            if pick(axis) {
+                weight *= t[axis];
+                at[axis] = next[axis];
+            } else {
+                weight *= 1.0 - t[axis];
+                at[axis] = index[axis];
+            }
+        }
+        if weight > 0.0 {
+            value += weight * voxel(at[0], at[1], at[2]);
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::{Hittable, HittablePtr};
+    use crate::material::{Lambertian, MaterialPtr};
+    use crate::objects::{Cube, HeterogeneousMedium};
+    use crate::phase::IsotropicPhase;
+    use crate::ray::Ray;
+    use crate::texture::SolidColor;
+    use vec3::{Color, Vec3};
+
+    /// The same density everywhere, with a majorant twice as high so tracking also has to
+    /// reject tentative collisions.
+    struct Constant(f64);
+
+    impl Density for Constant {
+        fn density(&self, _p: &Point3) -> f64 {
+            self.0
+        }
+
+        fn max_density(&self) -> f64 {
+            2.0 * self.0
+        }
+    }
+
+    /// A medium filling the cube from 0 to 2 on every axis.
+    fn medium(density: DensityPtr) -> HeterogeneousMedium {
+        let material: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::default()));
+        let boundary: HittablePtr = Arc::new(Cube::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 2.0, 2.0),
+            material,
+        ));
+        HeterogeneousMedium::new(
+            boundary,
+            density,
+            0.25,
+            0.5,
+            Arc::new(SolidColor::new_from_rgb(1.0, 1.0, 1.0)),
+            Arc::new(IsotropicPhase),
+        )
+    }
+
+    /// Fraction of rays along `r` that get through the medium without a collision.
+    fn transmittance(medium: &HeterogeneousMedium, r: &Ray) -> f64 {
+        let trials = 40_000;
+        let passed = (0..trials)
+            .filter(|_| medium.hit(r, 0.001, f64::INFINITY).is_none())
+            .count();
+        passed as f64 / trials as f64
+    }
+
+    #[test]
+    fn delta_tracking_matches_the_transmittance_of_constant_densities() {
+        vec3::rng::seed(3);
+        let medium = medium(Arc::new(Constant(0.8)));
+        // The direction isn't a unit vector, so the distances along the ray are halved.
+        let r = Ray::new(Point3::new(-1.0, 1.0, 1.0), Vec3::new(2.0, 0.0, 0.0), 0.0);
+        let expected = (-0.75 * 0.8 * 2.0f64).exp();
+        let measured = transmittance(&medium, &r);
+        assert!(
+            (measured - expected).abs() < 0.01,
+            "{} {}",
+            measured,
+            expected
+        );
+
+        // Collisions land inside the medium, spread like the exponential distribution.
+        let mut depths = Vec::new();
+        while depths.len() < 20_000 {
+            if let Some(rec) = medium.hit(&r, 0.001, f64::INFINITY) {
+                assert!(rec.p.x >= 0.0 && rec.p.x <= 2.0);
+                depths.push(rec.p.x);
+            }
+        }
+        let mean = depths.iter().sum::<f64>() / depths.len() as f64;
+        let sigma: f64 = 0.75 * 0.8;
+        let expected_mean = 1.0 / sigma - 2.0 * (-2.0 * sigma).exp() / (1.0 - (-2.0 * sigma).exp());
+        assert!(
+            (mean - expected_mean).abs() < 0.02,
+            "{} {}",
+            mean,
+            expected_mean
+        );
+    }
+
+    #[test]
+    fn grids_only_attenuate_inside_their_box() {
+        vec3::rng::seed(4);
+        // Half a unit of the ray crosses the grid, the rest of the cube is empty.
+        let grid = GridDensity::new(
+            [2, 2, 2],
+            vec![1.5; 8],
+            Point3::new(0.5, 0.5, 0.5),
+            Point3::new(1.0, 1.5, 1.5),
+        );
+        assert_eq!(grid.density(&Point3::new(0.75, 1.0, 1.0)), 1.5);
+        assert_eq!(grid.density(&Point3::new(1.25, 1.0, 1.0)), 0.0);
+
+        let medium = medium(Arc::new(grid));
+        let r = Ray::new(Point3::new(-1.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        let expected = (-0.75 * 1.5 * 0.5f64).exp();
+        let measured = transmittance(&medium, &r);
+        assert!(
+            (measured - expected).abs() < 0.01,
+            "{} {}",
+            measured,
+            expected
+        );
+
+        let missing = Ray::new(Point3::new(-1.0, 0.25, 1.0), Vec3::new(1.0, 0.0, 0.0), 0.0);
+        assert_eq!(transmittance(&medium, &missing), 1.0);
+    }
+}
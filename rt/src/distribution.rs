@@ -0,0 +1,171 @@
+//! Piecewise constant distributions for importance sampling tabulated functions.
+
+/// A distribution over [0, 1) proportional to a step function with equally wide steps.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    /// Running integral of `func`, normalized, with one more entry than `func`.
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Negative values count as zero. A function that's zero everywhere is sampled uniformly.
+    pub fn new(func: &[f64]) -> Self {
+        assert!(!func.is_empty(), "a distribution needs at least one step");
+        let n = func.len() as f64;
+        let mut func: Vec<f64> = func.iter().map(|&f| f.max(0.0)).collect();
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for f in &func {
+            cdf.push(cdf.last().unwrap() + f / n);
+        }
+        let mut integral = *cdf.last().unwrap();
+        if integral <= 0.0 {
+            func.iter_mut().for_each(|f| *f = 1.0);
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n;
+            }
+            integral = 1.0;
+        } else {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps `u` in [0, 1) to a sample, returning it with its density and the step it's in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // The last entry of the cdf that is <= u.
+        let index = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.count() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+        let x = ((index as f64 + offset) / self.count() as f64).min(1.0 - f64::EPSILON);
+        (x, self.func[index] / self.integral, index)
+    }
+
+    /// Density of sampling `x` in [0, 1).
+    pub fn pdf(&self, x: f64) -> f64 {
+        self.func[self.index(x)] / self.integral
+    }
+
+    fn index(&self, x: f64) -> usize {
+        ((x * self.count() as f64) as usize).min(self.count() - 1)
+    }
+}
+
+/// A distribution over [0, 1)² proportional to a function tabulated on a grid: `v` is
+/// picked from the marginal distribution of the rows, then `u` from that row.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `height` rows of `width` values each.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(
+            func.len(),
+            width * height,
+            "function doesn't match the grid size"
+        );
+        let rows: Vec<Distribution1D> = func.chunks(width).map(Distribution1D::new).collect();
+        // Integrals of the rows as given, so rows that are zero everywhere are never picked.
+        let row_integrals: Vec<f64> = func
+            .chunks(width)
+            .map(|row| row.iter().map(|f| f.max(0.0)).sum::<f64>() / width as f64)
+            .collect();
+        let marginal = Distribution1D::new(&row_integrals);
+        Distribution2D { rows, marginal }
+    }
+
+    /// Maps `(u1, u2)` to a point `(u, v)` and its density.
+    pub fn sample_continuous(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.rows[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        self.marginal.pdf(v) * self.rows[self.marginal.index(v)].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_in_proportion_to_the_function() {
+        let distribution = Distribution1D::new(&[1.0, 0.0, 3.0, -2.0, 4.0]);
+        assert!((distribution.integral() - 8.0 / 5.0).abs() < 1e-12);
+        let samples = 10_000;
+        let mut counts = [0; 5];
+        for i in 0..samples {
+            let (x, pdf, index) = distribution.sample_continuous((i as f64 + 0.5) / samples as f64);
+            assert_eq!(index, (x * 5.0) as usize);
+            assert_eq!(pdf, distribution.pdf(x));
+            counts[index] += 1;
+        }
+        assert_eq!(counts, [1250, 0, 3750, 0, 5000]);
+    }
+
+    #[test]
+    fn zero_functions_are_sampled_uniformly() {
+        let distribution = Distribution1D::new(&[0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(distribution.integral(), 1.0);
+        for i in 0..100 {
+            let u = (i as f64 + 0.5) / 100.0;
+            let (x, pdf, _) = distribution.sample_continuous(u);
+            assert!((x - u).abs() < 1e-12);
+            assert_eq!(pdf, 1.0);
+        }
+    }
+
+    #[test]
+    fn joint_density_matches_samples_and_integrates_to_one() {
+        let (width, height) = (7, 5);
+        let func: Vec<f64> = (0..width * height)
+            .map(|i| {
+                if i / width == 2 {
+                    0.0
+                } else {
+                    (i % 4) as f64 + 0.5
+                }
+            })
+            .collect();
+        let distribution = Distribution2D::new(&func, width, height);
+
+        // A grid that lines up with the cells, so the midpoint rule is exact.
+        let n = width * height * 8;
+        let mut integral = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let ((u, v), pdf) = distribution.sample_continuous(u1, u2);
+                assert!((pdf - distribution.pdf(u, v)).abs() < 1e-12);
+                // The empty row is never picked.
+                assert_ne!((v * height as f64) as usize, 2);
+                integral += distribution.pdf(u1, u2);
+            }
+        }
+        assert!((integral / (n * n) as f64 - 1.0).abs() < 1e-9);
+    }
+}
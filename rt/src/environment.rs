@@ -0,0 +1,291 @@
+//! Light arriving from infinitely far away, seen by rays that leave the scene. Environments
+//! can be sampled directly, like the lights.
+
+use crate::distribution::Distribution2D;
+use crate::exr::read_exr;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vec3::{Color, Vec3};
+
+pub trait Environment {
+    /// Radiance arriving from `direction`.
+    fn radiance(&self, direction: &Vec3) -> Color;
+
+    /// Maps `(u1, u2)` in [0, 1)² to a unit direction, returning it with its density per
+    /// solid angle.
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64);
+
+    /// Density per solid angle of `sample` returning `direction`.
+    fn pdf(&self, direction: &Vec3) -> f64;
+}
+
+pub type EnvironmentPtr = Arc<dyn Environment + Send + Sync>;
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnvironmentError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            EnvironmentError::Format(path, message) => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvironmentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EnvironmentError::Io(_, err) => Some(err),
+            EnvironmentError::Format(..) => None,
+        }
+    }
+}
+
+/// An environment looked up in an equirectangular (latitude-longitude) image. The center of the image is the -z direction and its top row is +y.
+///
+/// Directions are importance sampled proportionally to the luminance of the pixels,
+/// weighted by the solid angle they cover.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Rotation around the y axis, in radians.
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// `pixels` holds `height` rows of `width` linear RGB values, starting at the top.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "pixels don't match the size");
+        let mut weights = Vec::with_capacity(pixels.len());
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            for pixel in &pixels[y * width..(y + 1) * width] {
+                let luminance = 0.2126 * pixel.x + 0.7152 * pixel.y + 0.0722 * pixel.z;
+                weights.push(luminance * sin_theta);
+            }
+        }
+        EnvironmentMap {
+            width,
+            height,
+            distribution: Distribution2D::new(&weights, width, height),
+            pixels,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Loads a Radiance `.hdr` or OpenEXR `.exr` file.
+    pub fn load(path: &Path) -> Result<Self, EnvironmentError> {
+        let format = |message: String| EnvironmentError::Format(path.to_path_buf(), message);
+        let bytes = fs::read(path).map_err(|e| EnvironmentError::Io(path.to_path_buf(), e))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("hdr") => {
+                let decoder =
+                    image::hdr::HdrDecoder::new(&bytes[..]).map_err(|e| format(e.to_string()))?;
+                let metadata = decoder.metadata();
+                let pixels = decoder
+                    .read_image_hdr()
+                    .map_err(|e| format(e.to_string()))?
+                    .iter()
+                    .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                    .collect();
+                Ok(EnvironmentMap::new(
+                    metadata.width as usize,
+                    metadata.height as usize,
+                    pixels,
+                ))
+            }
+            Some("exr") => {
+                let (width, height, pixels) = read_exr(&bytes).map_err(format)?;
+                Ok(EnvironmentMap::new(width as usize, height as usize, pixels))
+            }
+            _ => Err(format(
+                "unknown environment map format, expected .hdr or .exr".to_string(),
+            )),
+        }
+    }
+
+    /// Turns the map around the y axis by `degrees`, from -z towards +x.
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees.to_radians();
+        self
+    }
+
+    /// Scales the brightness of the map.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn to_uv(&self, direction: &Vec3) -> (f64, f64) {
+        let d = direction.unit_vector();
+        let phi = d.x.atan2(-d.z) - self.rotation;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = num::clamp(d.y, -1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.to_uv(direction);
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[y * self.width + x] * self.intensity
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let ((u, v), pdf) = self.distribution.sample_continuous(u1, u2);
+        let direction = self.direction(u, v);
+        let sin_theta = (v * PI).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return (direction, 0.0);
+        }
+        (direction, pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// A dim gradient with one bright pixel.
+    fn map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let value = if i == 2 * width + 11 {
+                    50.0
+                } else {
+                    0.1 + (i % width) as f64 / width as f64
+                };
+                Color::new(value, value * 0.5, value * 0.25)
+            })
+            .collect();
+        EnvironmentMap::new(width, height, pixels)
+    }
+
+    /// Directions with one uniformly distributed in each of `n` by `n` equally sized cells
+    /// of the sphere.
+    fn stratified_directions(n: usize) -> impl Iterator<Item = Vec3> {
+        (0..n * n).map(move |i| {
+            let mut rng = vec3::rng::thread_rng();
+            let z = 1.0 - 2.0 * ((i / n) as f64 + rng.gen::<f64>()) / n as f64;
+            let phi = 2.0 * PI * ((i % n) as f64 + rng.gen::<f64>()) / n as f64;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            Vec3::new(r * phi.cos(), r * phi.sin(), z)
+        })
+    }
+
+    fn pdf_integral(environment: &EnvironmentMap) -> f64 {
+        let n = 400;
+        let sum: f64 = stratified_directions(n).map(|d| environment.pdf(&d)).sum();
+        sum * 4.0 * PI / (n * n) as f64
+    }
+
+    /// Checks that sampled directions come with the density `pdf` gives them.
+    fn check_samples(environment: &EnvironmentMap) {
+        let n = 100;
+        for i in 0..n {
+            for j in 0..n {
+                let (u1, u2) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                let (direction, pdf) = environment.sample(u1, u2);
+                assert!((direction.length() - 1.0).abs() < 1e-9);
+                let expected = environment.pdf(&direction);
+                assert!((pdf / expected - 1.0).abs() < 1e-6, "{} {}", pdf, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn directions_round_trip_through_rotated_maps() {
+        vec3::rng::seed(1);
+        let environment = map().with_rotation(37.0);
+        for _ in 0..1000 {
+            let d = Vec3::random_unit_vector();
+            let (u, v) = environment.to_uv(&d);
+            assert!((environment.direction(u, v) - d).length() < 1e-9);
+        }
+
+        // Turning the map a quarter turn brings what was at -z to +x.
+        let plain = map();
+        let turned = map().with_rotation(90.0);
+        let ahead = Vec3::new(0.3, 0.2, -1.0);
+        let right = Vec3::new(1.0, 0.2, 0.3);
+        assert!((turned.radiance(&right) - plain.radiance(&ahead)).length() < 1e-12);
+    }
+
+    #[test]
+    fn samples_have_the_density_of_pdf() {
+        vec3::rng::seed(2);
+        check_samples(&map());
+        check_samples(&map().with_rotation(-120.0));
+
+        // The bright pixel is sampled far more often than its share of the sphere.
+        let environment = map();
+        let bright = environment.direction(11.5 / 16.0, 2.5 / 8.0);
+        assert!(environment.pdf(&bright) > 10.0 / (4.0 * PI));
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        vec3::rng::seed(3);
+        let integral = pdf_integral(&map().with_rotation(15.0));
+        assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+    }
+
+    #[test]
+    fn black_maps_are_sampled_uniformly() {
+        vec3::rng::seed(4);
+        let environment = EnvironmentMap::new(8, 4, vec![Color::default(); 32]);
+        check_samples(&environment);
+        let integral = pdf_integral(&environment);
+        assert!((integral - 1.0).abs() < 0.01, "{}", integral);
+        // Every pixel is as likely, so the density only depends on the latitude.
+        let a = environment.pdf(&Vec3::new(1.0, 0.5, 0.0));
+        let b = environment.pdf(&Vec3::new(0.6, 0.5, -0.8));
+        assert!((a / b - 1.0).abs() < 1e-9);
+        assert_eq!(
+            environment.radiance(&Vec3::new(0.0, 1.0, 0.0)).length(),
+            0.0
+        );
+    }
+}
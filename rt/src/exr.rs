@@ -0,0 +1,646 @@
+//! A small reader for OpenEXR images, enough for environment maps.
+//!
+//! Supports single part scanline files with `NONE`, `RLE`, `ZIPS` or `ZIP` compression and
+//! `HALF`, `FLOAT` or `UINT` channels. Color comes from the `R`, `G` and `B` channels, or
+//! from `Y` for luminance images. Tiled, deep and multi-part files and the lossy or wavelet
+//! compressions are rejected with an error.
+
+use miniz_oxide::inflate::core::{decompress as inflate, inflate_flags, DecompressorOxide};
+use miniz_oxide::inflate::TINFLStatus;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Cursor;
+use vec3::Color;
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const TILED_FLAG: u32 = 0x200;
+const NON_IMAGE_FLAG: u32 = 0x800;
+const MULTI_PART_FLAG: u32 = 0x1000;
+/// Larger images are rejected rather than allocated, in case the header is corrupt.
+const MAX_PIXELS: usize = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+}
+
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    None,
+    Rle,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    fn lines_per_block(self) -> usize {
+        match self {
+            Compression::None | Compression::Rle | Compression::Zips => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+/// Reads the bytes of an OpenEXR file into `(width, height, pixels)`, with the pixels in
+/// rows from the top of the image.
+pub fn read_exr(bytes: &[u8]) -> Result<(u32, u32, Vec<Color>), String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err("not an OpenEXR file".to_string());
+    }
+    let version = reader.u32()?;
+    if version & 0xff != 2 {
+        return Err(format!("unsupported OpenEXR version {}", version & 0xff));
+    }
+    if version & TILED_FLAG != 0 {
+        return Err("tiled OpenEXR files aren't supported".to_string());
+    }
+    if version & (NON_IMAGE_FLAG | MULTI_PART_FLAG) != 0 {
+        return Err("deep and multi-part OpenEXR files aren't supported".to_string());
+    }
+
+    // The header is a list of (name, type, size, value) attributes ending with an empty name.
+    let mut attributes = HashMap::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let _kind = reader.string()?;
+        let size = reader.u32()? as usize;
+        attributes.insert(name, reader.take(size)?);
+    }
+    let attribute = |name: &str| {
+        attributes
+            .get(name)
+            .copied()
+            .ok_or_else(|| format!("missing '{}' attribute", name))
+    };
+
+    let channels = parse_channels(attribute("channels")?)?;
+    let compression = match attribute("compression")?.first() {
+        Some(0) => Compression::None,
+        Some(1) => Compression::Rle,
+        Some(2) => Compression::Zips,
+        Some(3) => Compression::Zip,
+        Some(other) => return Err(format!("unsupported compression {}", other)),
+        None => return Err("empty compression attribute".to_string()),
+    };
+    let data_window = attribute("dataWindow")?;
+    if data_window.len() != 16 {
+        return Err("malformed dataWindow attribute".to_string());
+    }
+    let mut window = Reader {
+        bytes: data_window,
+        position: 0,
+    };
+    let (x_min, y_min, x_max, y_max) = (window.i32()?, window.i32()?, window.i32()?, window.i32()?);
+    if x_max < x_min || y_max < y_min {
+        return Err("empty dataWindow".to_string());
+    }
+    // Computed in 64 bits, where the difference of two i32s can't overflow.
+    let width = (x_max as i64 - x_min as i64 + 1) as usize;
+    let height = (y_max as i64 - y_min as i64 + 1) as usize;
+    let pixel_count = width
+        .checked_mul(height)
+        .filter(|&count| count <= MAX_PIXELS)
+        .ok_or_else(|| format!("image of {}x{} pixels is too large", width, height))?;
+
+    let find = |name: &str| channels.iter().position(|c| c.name == name);
+    let sources = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err("no R, G and B or Y channels".to_string()),
+    };
+    let bytes_per_line = channels
+        .iter()
+        .try_fold(0usize, |sum, c| {
+            c.pixel_type
+                .size()
+                .checked_mul(width)
+                .and_then(|size| sum.checked_add(size))
+        })
+        .ok_or("scanlines are too large")?;
+
+    let lines_per_block = compression.lines_per_block();
+    let blocks = height.div_ceil(lines_per_block);
+    let offsets = (0..blocks)
+        .map(|_| reader.u64())
+        .collect::<Result<Vec<u64>, String>>()?;
+
+    let mut pixels = vec![Color::default(); pixel_count];
+    for offset in offsets {
+        let mut block = Reader {
+            bytes,
+            position: offset as usize,
+        };
+        let first_line = block.i32()? as i64 - y_min as i64;
+        let size = block.u32()? as usize;
+        let data = block.take(size)?;
+        if first_line < 0 || first_line as usize >= height {
+            return Err("block outside of the dataWindow".to_string());
+        }
+        let first_line = first_line as usize;
+        let lines = lines_per_block.min(height - first_line);
+        let expected = bytes_per_line
+            .checked_mul(lines)
+            .ok_or("block is too large")?;
+        let data = decompress(compression, data, expected)?;
+        if data.len() != expected {
+            return Err("block has the wrong size".to_string());
+        }
+
+        for line in 0..lines {
+            let mut start = line * bytes_per_line;
+            let mut values = vec![Vec::new(); channels.len()];
+            for (channel, values) in channels.iter().zip(values.iter_mut()) {
+                let size = channel.pixel_type.size();
+                *values = data[start..start + size * width]
+                    .chunks(size)
+                    .map(|v| decode(channel.pixel_type, v))
+                    .collect();
+                start += size * width;
+            }
+            let row = (first_line + line) * width;
+            for x in 0..width {
+                pixels[row + x] = Color::new(
+                    values[sources[0]][x],
+                    values[sources[1]][x],
+                    values[sources[2]][x],
+                );
+            }
+        }
+    }
+
+    Ok((width as u32, height as u32, pixels))
+}
+
+/// The channel list, in the order the channels are stored in each scanline.
+fn parse_channels(bytes: &[u8]) -> Result<Vec<Channel>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    let mut channels = Vec::new();
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = match reader.u32()? {
+            0 => PixelType::Uint,
+            1 => PixelType::Half,
+            2 => PixelType::Float,
+            other => return Err(format!("unknown pixel type {}", other)),
+        };
+        // pLinear and three reserved bytes.
+        reader.take(4)?;
+        let (x_sampling, y_sampling) = (reader.i32()?, reader.i32()?);
+        if x_sampling != 1 || y_sampling != 1 {
+            return Err(format!("subsampled channel '{}' isn't supported", name));
+        }
+        channels.push(Channel { name, pixel_type });
+    }
+}
+
+fn decompress(compression: Compression, data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    // Blocks that wouldn't get smaller are stored as they are.
+    if compression == Compression::None || data.len() == expected {
+        return Ok(data.to_vec());
+    }
+    let packed = match compression {
+        Compression::Rle => decode_rle(data, expected)?,
+        _ => decode_zip(data, expected)?,
+    };
+    Ok(unpredict(&packed))
+}
+
+/// Runs are a signed count followed by either `-count` literal bytes or one byte that's
+/// repeated `count + 1` times. Stops with an error once the output would grow past
+/// `expected` bytes.
+fn decode_rle(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let too_large = || "rle block is larger than expected".to_string();
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+        if count < 0 {
+            let end = i + (-(count as i32)) as usize;
+            let run = data.get(i..end).ok_or("truncated rle block")?;
+            if out.len() + run.len() > expected {
+                return Err(too_large());
+            }
+            out.extend_from_slice(run);
+            i = end;
+        } else {
+            let value = *data.get(i).ok_or("truncated rle block")?;
+            let length = count as usize + 1;
+            if out.len() + length > expected {
+                return Err(too_large());
+            }
+            out.extend(std::iter::repeat_n(value, length));
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Inflates a zlib stream into a buffer with room for one byte more than `expected`, so a
+/// block that's too large is noticed without inflating all of it.
+fn decode_zip(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut out = vec![0; expected + 1];
+    let flags = inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER
+        | inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let mut decompressor = DecompressorOxide::new();
+    let (status, _, written) = inflate(
+        &mut decompressor,
+        data,
+        &mut Cursor::new(&mut out[..]),
+        flags,
+    );
+    match status {
+        TINFLStatus::Done if written <= expected => {
+            out.truncate(written);
+            Ok(out)
+        }
+        TINFLStatus::Done | TINFLStatus::HasMoreOutput => {
+            Err("zip block is larger than expected".to_string())
+        }
+        other => Err(format!("corrupt zip block: {:?}", other)),
+    }
+}
+
+/// Undoes the delta encoding and the split into even and odd bytes done before RLE and
+/// zip compression.
+fn unpredict(packed: &[u8]) -> Vec<u8> {
+    let mut deltas = packed.to_vec();
+    for i in 1..deltas.len() {
+        deltas[i] = deltas[i - 1].wrapping_add(deltas[i]).wrapping_sub(128);
+    }
+    let half = deltas.len().div_ceil(2);
+    let (even, odd) = deltas.split_at(half);
+    let mut out = Vec::with_capacity(deltas.len());
+    for (i, &a) in even.iter().enumerate() {
+        out.push(a);
+        if let Some(&b) = odd.get(i) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn decode(pixel_type: PixelType, bytes: &[u8]) -> f64 {
+    match pixel_type {
+        PixelType::Uint => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PixelType::Half => half_to_f64(u16::from_le_bytes(bytes.try_into().unwrap())),
+        PixelType::Float => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    }
+}
+
+/// Converts an IEEE 754 half precision float.
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    match exponent {
+        0 => sign * mantissa * 2f64.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// Little endian reads with errors for truncated files.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let slice = &self.bytes[self.position..end];
+                self.position = end;
+                Ok(slice)
+            }
+            None => Err("unexpected end of file".to_string()),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// A null terminated string.
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.position.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("unexpected end of file")?;
+        let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.position += len + 1;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HALF: u32 = 1;
+    const FLOAT: u32 = 2;
+
+    /// An uncompressed file with one float `Y` channel, a `data_window` of (x_min, y_min,
+    /// x_max, y_max) and one block per scanline holding `lines[i]`.
+    fn exr(data_window: [i32; 4], lines: &[Vec<u8>]) -> Vec<u8> {
+        exr_file(&["Y"], FLOAT, Compression::None, data_window, lines)
+    }
+
+    /// A file with the channels `names`, all of one pixel type, stored in `blocks` of as
+    /// many scanlines as `compression` puts in each.
+    fn exr_file(
+        names: &[&str],
+        pixel_type: u32,
+        compression: Compression,
+        data_window: [i32; 4],
+        blocks: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            for s in &[name, kind] {
+                bytes.extend_from_slice(s.as_bytes());
+                bytes.push(0);
+            }
+            bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(value);
+        };
+        let mut channels = Vec::new();
+        for name in names {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&pixel_type.to_le_bytes());
+            channels.extend_from_slice(&[0; 4]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        attribute("channels", "chlist", &channels);
+        let compression = match compression {
+            Compression::None => 0,
+            Compression::Rle => 1,
+            Compression::Zips => 2,
+            Compression::Zip => 3,
+        };
+        attribute("compression", "compression", &[compression]);
+        let window: Vec<u8> = data_window.iter().flat_map(|v| v.to_le_bytes()).collect();
+        attribute("dataWindow", "box2i", &window);
+        bytes.push(0);
+
+        let lines_per_block = if compression == 3 { 16 } else { 1 };
+        let mut offset = bytes.len() + 8 * blocks.len();
+        let mut data = Vec::new();
+        for (i, block) in blocks.iter().enumerate() {
+            bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+            let y = data_window[1] + (i * lines_per_block) as i32;
+            data.extend_from_slice(&y.to_le_bytes());
+            data.extend_from_slice(&(block.len() as u32).to_le_bytes());
+            data.extend_from_slice(block);
+            offset += 8 + block.len();
+        }
+        bytes.extend_from_slice(&data);
+        bytes
+    }
+
+    /// The inverse of `unpredict`.
+    fn predict(raw: &[u8]) -> Vec<u8> {
+        let mut split: Vec<u8> = raw.iter().step_by(2).copied().collect();
+        split.extend(raw.iter().skip(1).step_by(2));
+        let mut deltas = split.clone();
+        for i in 1..split.len() {
+            deltas[i] = split[i].wrapping_sub(split[i - 1]).wrapping_add(128);
+        }
+        deltas
+    }
+
+    /// Repeats runs of three or more equal bytes and copies the rest literally.
+    fn encode_rle(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut literals: Vec<u8> = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let run = data[i..]
+                .iter()
+                .take(128)
+                .take_while(|&&b| b == data[i])
+                .count();
+            if run >= 3 || literals.len() == 128 || i + run == data.len() {
+                if run < 3 {
+                    literals.extend_from_slice(&data[i..i + run]);
+                    i += run;
+                }
+                for chunk in literals.chunks(128) {
+                    out.push((-(chunk.len() as i32)) as u8);
+                    out.extend_from_slice(chunk);
+                }
+                literals.clear();
+                if run >= 3 {
+                    out.push(run as u8 - 1);
+                    out.push(data[i]);
+                    i += run;
+                }
+            } else {
+                literals.push(data[i]);
+                i += 1;
+            }
+        }
+        out
+    }
+
+    fn compress(compression: Compression, raw: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::None => raw.to_vec(),
+            Compression::Rle => encode_rle(&predict(raw)),
+            Compression::Zips | Compression::Zip => {
+                miniz_oxide::deflate::compress_to_vec_zlib(&predict(raw), 6)
+            }
+        }
+    }
+
+    fn halves(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A `width` by `height` RGB half image, stored with `compression`, whose pixels are
+    /// small integers so they're exact in half precision.
+    fn rgb_halves(compression: Compression, width: usize, height: usize) -> Vec<u8> {
+        // Half precision 0, 1, 2, ... 7.
+        let half = |value: usize| -> u16 {
+            [0, 0x3c00, 0x4000, 0x4200, 0x4400, 0x4500, 0x4600, 0x4700][value]
+        };
+        let pixel = |x: usize, y: usize, channel: usize| (x + 2 * y + channel) % 8;
+        let lines_per_block = compression.lines_per_block();
+        let blocks: Vec<Vec<u8>> = (0..height)
+            .step_by(lines_per_block)
+            .map(|first| {
+                let mut raw = Vec::new();
+                for y in first..(first + lines_per_block).min(height) {
+                    // Channels are stored in alphabetical order: B, G, R.
+                    for channel in (0..3).rev() {
+                        let line: Vec<u16> =
+                            (0..width).map(|x| half(pixel(x, y, channel))).collect();
+                        raw.extend(halves(&line));
+                    }
+                }
+                compress(compression, &raw)
+            })
+            .collect();
+        let window = [0, 0, width as i32 - 1, height as i32 - 1];
+        exr_file(&["B", "G", "R"], HALF, compression, window, &blocks)
+    }
+
+    fn check_rgb_halves(bytes: &[u8], width: usize, height: usize) {
+        let (w, h, pixels) = read_exr(bytes).unwrap();
+        assert_eq!((w as usize, h as usize), (width, height));
+        for y in 0..height {
+            for x in 0..width {
+                let expected = |channel: usize| ((x + 2 * y + channel) % 8) as f64;
+                let p = pixels[y * width + x];
+                assert_eq!((p.x, p.y, p.z), (expected(0), expected(1), expected(2)));
+            }
+        }
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn reads_uncompressed_luminance() {
+        let bytes = exr([0, 0, 1, 1], &[floats(&[1.0, 2.0]), floats(&[0.5, 0.25])]);
+        let (width, height, pixels) = read_exr(&bytes).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(pixels[1].x, 2.0);
+        assert_eq!(pixels[3].z, 0.25);
+    }
+
+    #[test]
+    fn rejects_truncated_uncompressed_block() {
+        let bytes = exr([0, 0, 3, 0], &[floats(&[1.0])]);
+        assert!(read_exr(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_overflowing_data_window() {
+        let bytes = exr([i32::MIN, 0, i32::MAX, 0], &[floats(&[1.0])]);
+        assert!(read_exr(&bytes).is_err());
+        let bytes = exr([0, i32::MIN, 0, i32::MAX], &[floats(&[1.0])]);
+        assert!(read_exr(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_data_window() {
+        let bytes = exr([0, 0, 1 << 20, 1 << 20], &[floats(&[1.0])]);
+        assert!(read_exr(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_block_outside_data_window() {
+        let mut bytes = exr([0, 0, 0, 0], &[floats(&[1.0])]);
+        // The block's y coordinate follows the header and the single offset.
+        let at = bytes.len() - 12;
+        bytes[at..at + 4].copy_from_slice(&i32::MIN.to_le_bytes());
+        assert!(read_exr(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_header() {
+        let bytes = exr([0, 0, 0, 0], &[floats(&[1.0])]);
+        assert!(read_exr(&bytes[..20]).is_err());
+        assert!(read_exr(b"not an exr").is_err());
+    }
+
+    #[test]
+    fn reads_compressed_half_images() {
+        for &compression in [
+            Compression::None,
+            Compression::Rle,
+            Compression::Zips,
+            Compression::Zip,
+        ]
+        .iter()
+        {
+            // 20 lines take two zip blocks, the last one short.
+            check_rgb_halves(&rgb_halves(compression, 37, 20), 37, 20);
+        }
+    }
+
+    #[test]
+    fn reads_long_rle_runs() {
+        let mut values = vec![0.5; 300];
+        values.extend((0..300).map(|i| i as f32));
+        let raw = floats(&values);
+        let block = compress(Compression::Rle, &raw);
+        assert!(block.len() < raw.len());
+        let bytes = exr_file(&["Y"], FLOAT, Compression::Rle, [0, 0, 599, 0], &[block]);
+        let (_, _, pixels) = read_exr(&bytes).unwrap();
+        assert_eq!(pixels[299].x, 0.5);
+        assert_eq!(pixels[599].x, 299.0);
+    }
+
+    #[test]
+    fn rejects_blocks_that_decompress_too_far() {
+        let raw = floats(&[1.0; 64]);
+        for &compression in [Compression::Rle, Compression::Zips].iter() {
+            // The block holds two lines' worth of data for a single line.
+            let block = compress(compression, &[raw.clone(), raw.clone()].concat());
+            let bytes = exr_file(&["Y"], FLOAT, compression, [0, 0, 63, 0], &[block]);
+            let error = read_exr(&bytes).unwrap_err();
+            assert!(error.contains("larger than expected"), "{}", error);
+        }
+        assert!(decode_rle(&[127, 0], 100).is_err());
+        assert_eq!(decode_rle(&[127, 0], 128).unwrap().len(), 128);
+    }
+
+    #[test]
+    fn converts_halves() {
+        assert_eq!(half_to_f64(0x0000), 0.0);
+        assert_eq!(half_to_f64(0x3c00), 1.0);
+        assert_eq!(half_to_f64(0x3800), 0.5);
+        assert_eq!(half_to_f64(0xc000), -2.0);
+        assert_eq!(half_to_f64(0x7bff), 65504.0);
+        assert_eq!(half_to_f64(0x0001), 2f64.powi(-24));
+        assert_eq!(half_to_f64(0x0400), 2f64.powi(-14));
+        assert_eq!(half_to_f64(0x7c00), f64::INFINITY);
+        assert_eq!(half_to_f64(0xfc00), f64::NEG_INFINITY);
+        assert!(half_to_f64(0x7e00).is_nan());
+    }
+}
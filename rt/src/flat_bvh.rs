@@ -0,0 +1,460 @@
+use crate::aabb::{axis, Aabb};
+use crate::hit::{HitRecord, Hittable, HittableList, HittablePtr};
+use crate::ray::Ray;
+use vec3::Point3;
+
+/// Number of buckets the centroids are binned into when evaluating split candidates.
+const SAH_BUCKETS: usize = 12;
+/// Cost of traversing an interior node relative to intersecting one primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+const INTERSECTION_COST: f64 = 1.0;
+/// Leaves never hold more primitives than this, unless they all share the same centroid.
+const MAX_LEAF_SIZE: usize = 4;
+/// Maximum tree depth, which bounds the size of the traversal stack.
+const STACK_SIZE: usize = 64;
+
+/// A node in the flattened hierarchy. Interior nodes store their first child directly after
+/// themselves in the node array and the index of their second child in `offset`.
+/// Leaves store the range `offset..offset + count` into the primitive order.
+#[derive(Clone, Copy)]
+struct FlatNode {
+    bbox: Aabb,
+    offset: usize,
+    count: usize,
+    axis: usize,
+}
+
+impl FlatNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Statistics gathered while building a `FlatBvh`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub average_leaf_size: f64,
+    /// Expected cost of a ray query according to the surface area heuristic.
+    pub sah_cost: f64,
+}
+
+/// Per-primitive data needed during the build.
+struct BuildPrimitive {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+/// The node array of a SAH hierarchy built over a list of bounding boxes. It only knows
+/// primitives by their index, so it can be shared by anything that can intersect them.
+pub(crate) struct BvhLayout {
+    nodes: Vec<FlatNode>,
+    /// Primitive indices in the order the leaves reference them.
+    order: Vec<usize>,
+    stats: BvhStats,
+}
+
+impl BvhLayout {
+    pub(crate) fn new(boxes: &[Aabb]) -> Self {
+        let mut build_primitives: Vec<BuildPrimitive> = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, bbox)| BuildPrimitive {
+                index,
+                bbox: *bbox,
+                centroid: bbox.centroid(),
+            })
+            .collect();
+
+        let mut layout = BvhLayout {
+            nodes: Vec::with_capacity(2 * boxes.len()),
+            order: Vec::with_capacity(boxes.len()),
+            stats: BvhStats::default(),
+        };
+
+        if !build_primitives.is_empty() {
+            layout.build(&mut build_primitives, 1);
+            layout.compute_stats();
+        }
+
+        layout
+    }
+
+    pub(crate) fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|n| n.bbox)
+    }
+
+    /// Visits every primitive whose leaf box is hit by the ray, nearest child first.
+    /// `visit` gets the primitive index and the current closest distance and returns the
+    /// distance of a closer hit, which is then used to cull the remaining boxes.
+    pub(crate) fn traverse<F>(&self, r: &Ray, t_min: f64, t_max: f64, mut visit: F)
+    where
+        F: FnMut(usize, f64) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let dir_is_neg = [
+            r.direction.x < 0.0,
+            r.direction.y < 0.0,
+            r.direction.z < 0.0,
+        ];
+        let mut closest_so_far = t_max;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+            if node.bbox.hit(r, t_min, closest_so_far) {
+                if node.is_leaf() {
+                    for i in node.offset..node.offset + node.count {
+                        if let Some(t) = visit(self.order[i], closest_so_far) {
+                            closest_so_far = t;
+                        }
+                    }
+                } else {
+                    // Visit the child closest to the ray origin first so that later boxes
+                    // can be culled against a tighter closest_so_far.
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+    }
+
+    /// Recursively builds the subtree for `prims` and returns the index of its root node.
+    fn build(&mut self, prims: &mut [BuildPrimitive], depth: usize) -> usize {
+        self.stats.depth = self.stats.depth.max(depth);
+
+        let bbox = prims
+            .iter()
+            .skip(1)
+            .fold(prims[0].bbox, |b, p| Aabb::surrounding_box(&b, &p.bbox));
+        let centroid_bounds = prims
+            .iter()
+            .skip(1)
+            .fold(Aabb::new(prims[0].centroid, prims[0].centroid), |b, p| {
+                Aabb::surrounding_box(&b, &Aabb::new(p.centroid, p.centroid))
+            });
+
+        let node_index = self.nodes.len();
+        self.nodes.push(FlatNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        let extent = centroid_bounds.maximum - centroid_bounds.minimum;
+        let split_axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+        let axis_min = axis(&centroid_bounds.minimum, split_axis);
+        let axis_extent = axis(&extent, split_axis);
+
+        // When all centroids coincide no split can separate them.
+        if prims.len() == 1 || axis_extent <= 0.0 || depth >= STACK_SIZE {
+            self.make_leaf(node_index, prims);
+            return node_index;
+        }
+
+        let bucket_of = |p: &BuildPrimitive| -> usize {
+            let b = ((axis(&p.centroid, split_axis) - axis_min) / axis_extent * SAH_BUCKETS as f64)
+                as usize;
+            b.min(SAH_BUCKETS - 1)
+        };
+
+        let mut counts = [0usize; SAH_BUCKETS];
+        let mut bounds: [Option<Aabb>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+        for p in prims.iter() {
+            let b = bucket_of(p);
+            counts[b] += 1;
+            bounds[b] = merge(bounds[b], Some(p.bbox));
+        }
+
+        // Sweep from both sides so every split cost is evaluated in linear time.
+        let mut below_area = [0.0; SAH_BUCKETS - 1];
+        let mut below_count = [0usize; SAH_BUCKETS - 1];
+        let mut running: Option<Aabb> = None;
+        let mut running_count = 0;
+        for i in 0..SAH_BUCKETS - 1 {
+            running = merge(running, bounds[i]);
+            running_count += counts[i];
+            below_area[i] = running.map_or(0.0, |b| b.surface_area());
+            below_count[i] = running_count;
+        }
+
+        let mut best_split = 0;
+        let mut best_cost = f64::INFINITY;
+        let mut running: Option<Aabb> = None;
+        let mut running_count = 0;
+        for i in (1..SAH_BUCKETS).rev() {
+            running = merge(running, bounds[i]);
+            running_count += counts[i];
+            let above_area = running.map_or(0.0, |b| b.surface_area());
+            let cost =
+                below_count[i - 1] as f64 * below_area[i - 1] + running_count as f64 * above_area;
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = i - 1;
+            }
+        }
+
+        let area = bbox.surface_area();
+        let split_cost = if area > 0.0 {
+            TRAVERSAL_COST + INTERSECTION_COST * best_cost / area
+        } else {
+            TRAVERSAL_COST + INTERSECTION_COST * prims.len() as f64
+        };
+        let leaf_cost = INTERSECTION_COST * prims.len() as f64;
+
+        if prims.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+            self.make_leaf(node_index, prims);
+            return node_index;
+        }
+
+        let mid = partition(prims, |p| bucket_of(p) <= best_split);
+        let (left, right) = prims.split_at_mut(mid);
+
+        self.build(left, depth + 1);
+        let second_child = self.build(right, depth + 1);
+
+        self.nodes[node_index].offset = second_child;
+        self.nodes[node_index].axis = split_axis;
+        node_index
+    }
+
+    fn make_leaf(&mut self, node_index: usize, prims: &[BuildPrimitive]) {
+        self.nodes[node_index].offset = self.order.len();
+        self.nodes[node_index].count = prims.len();
+        self.order.extend(prims.iter().map(|p| p.index));
+    }
+
+    fn compute_stats(&mut self) {
+        let root_area = self.nodes[0].bbox.surface_area();
+        let mut stats = BvhStats {
+            node_count: self.nodes.len(),
+            depth: self.stats.depth,
+            min_leaf_size: usize::MAX,
+            ..BvhStats::default()
+        };
+
+        for node in self.nodes.iter() {
+            let relative_area = if root_area > 0.0 {
+                node.bbox.surface_area() / root_area
+            } else {
+                1.0
+            };
+            if node.is_leaf() {
+                stats.leaf_count += 1;
+                stats.min_leaf_size = stats.min_leaf_size.min(node.count);
+                stats.max_leaf_size = stats.max_leaf_size.max(node.count);
+                stats.sah_cost += INTERSECTION_COST * node.count as f64 * relative_area;
+            } else {
+                stats.sah_cost += TRAVERSAL_COST * relative_area;
+            }
+        }
+        stats.average_leaf_size = self.order.len() as f64 / stats.leaf_count as f64;
+
+        self.stats = stats;
+    }
+}
+
+/// A bounding volume hierarchy built with a binned surface area heuristic and stored as a
+/// flat array of nodes, traversed iteratively with an explicit stack.
+pub struct FlatBvh {
+    layout: BvhLayout,
+    /// Objects with a bounding box, in the order their boxes were passed to the layout.
+    primitives: Vec<HittablePtr>,
+    /// Objects without a bounding box, tested linearly on every ray.
+    unbounded: Vec<HittablePtr>,
+}
+
+impl FlatBvh {
+    pub fn new(list: &HittableList, time0: f64, time1: f64) -> Self {
+        FlatBvh::new_from_objects(list.objects(), time0, time1)
+    }
+
+    pub fn new_from_objects(objects: &[HittablePtr], time0: f64, time1: f64) -> Self {
+        let mut boxes = Vec::with_capacity(objects.len());
+        let mut primitives = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+
+        for object in objects.iter() {
+            match object.bounding_box(time0, time1) {
+                Some(bbox) => {
+                    boxes.push(bbox);
+                    primitives.push(HittablePtr::clone(object));
+                }
+                None => unbounded.push(HittablePtr::clone(object)),
+            }
+        }
+
+        let layout = BvhLayout::new(&boxes);
+
+        FlatBvh {
+            layout,
+            primitives,
+            unbounded,
+        }
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.layout.stats
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        let mut closest_so_far = t_max;
+
+        for o in self.unbounded.iter() {
+            if let Some(rec) = o.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                closest = Some(rec);
+            }
+        }
+
+        self.layout
+            .traverse(r, t_min, closest_so_far, |i, closest_so_far| {
+                let rec = self.primitives[i].hit(r, t_min, closest_so_far)?;
+                let t = rec.t;
+                closest = Some(rec);
+                Some(t)
+            });
+
+        closest
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+        self.layout.bounding_box()
+    }
+}
+
+fn merge(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::surrounding_box(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Moves all elements matching the predicate to the front and returns how many there are.
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize {
+    let mut first = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(first, i);
+            first += 1;
+        }
+    }
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MaterialPtr};
+    use crate::objects::Sphere;
+    use rand::Rng;
+    use std::sync::Arc;
+    use vec3::{Color, Vec3};
+
+    fn sphere(center: Point3, radius: f64) -> HittablePtr {
+        let material: MaterialPtr = Arc::new(Lambertian::new_from_color(&Color::default()));
+        Arc::new(Sphere::new(center, radius, material))
+    }
+
+    #[test]
+    fn finds_the_same_closest_hit_as_a_list() {
+        vec3::rng::seed(1);
+        let mut rng = vec3::rng::thread_rng();
+        let objects: Vec<HittablePtr> = (0..500)
+            .map(|_| sphere(Vec3::random_range(-10.0, 10.0), rng.gen_range(0.1, 1.5)))
+            .collect();
+        let list = HittableList::new(objects);
+        let bvh = FlatBvh::new(&list, 0.0, 1.0);
+
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let origin = Vec3::random_range(-15.0, 15.0);
+            let r = Ray::new(origin, Vec3::random_unit_vector(), 0.0);
+            let t_max = rng.gen_range(1.0, 40.0);
+            match (bvh.hit(&r, 0.001, t_max), list.hit(&r, 0.001, t_max)) {
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.t, b.t);
+                    assert!((a.p - b.p).length() == 0.0);
+                    hits += 1;
+                }
+                (None, None) => {}
+                (a, b) => panic!("bvh hit {} but list hit {}", a.is_some(), b.is_some()),
+            }
+        }
+        // Most rays start among the spheres, so both outcomes are covered.
+        assert!(hits > 1000 && hits < 4900, "{}", hits);
+    }
+
+    #[test]
+    fn splits_spread_out_objects_down_to_single_leaves() {
+        let objects: Vec<HittablePtr> = (0..8)
+            .map(|i| sphere(Point3::new(10.0 * i as f64, 0.0, 0.0), 0.5))
+            .collect();
+        let stats = *FlatBvh::new_from_objects(&objects, 0.0, 1.0).stats();
+        assert_eq!(stats.node_count, 15);
+        assert_eq!(stats.leaf_count, 8);
+        assert_eq!(stats.depth, 4);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+        assert_eq!(stats.average_leaf_size, 1.0);
+        // Far cheaper than testing all eight spheres, since the leaves are tiny.
+        assert!(
+            stats.sah_cost > 0.0 && stats.sah_cost < 1.0,
+            "{}",
+            stats.sah_cost
+        );
+    }
+
+    #[test]
+    fn keeps_objects_with_one_centroid_in_one_leaf() {
+        let objects: Vec<HittablePtr> = (1..=10)
+            .map(|i| sphere(Point3::new(1.0, 2.0, 3.0), i as f64 * 0.1))
+            .collect();
+        let bvh = FlatBvh::new_from_objects(&objects, 0.0, 1.0);
+        let stats = *bvh.stats();
+        assert_eq!((stats.node_count, stats.leaf_count, stats.depth), (1, 1, 1));
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (10, 10));
+        assert_eq!(stats.sah_cost, 10.0);
+
+        // The largest sphere is hit first.
+        let r = Ray::new(Point3::new(1.0, 2.0, -10.0), Vec3::new(0.0, 0.0, 1.0), 0.0);
+        let rec = bvh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 12.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,65 @@
+use image::RgbImage;
+use std::sync::atomic::{AtomicU64, Ordering};
+use vec3::Color;
+
+/// A linear RGB image that can be written from many threads at once without locking.
+/// Every channel is stored as the bit pattern of an `f64` in an atomic, so writers of
+/// different pixels never wait on each other. Row 0 is the top of the image.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    data: Vec<AtomicU64>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        let len = width as usize * height as usize * 3;
+        let black = 0.0f64.to_bits();
+        Framebuffer {
+            width,
+            height,
+            data: (0..len).map(|_| AtomicU64::new(black)).collect(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set(&self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.data[index].store(color.x.to_bits(), Ordering::Relaxed);
+        self.data[index + 1].store(color.y.to_bits(), Ordering::Relaxed);
+        self.data[index + 2].store(color.z.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        let index = self.index(x, y);
+        Color::new(
+            f64::from_bits(self.data[index].load(Ordering::Relaxed)),
+            f64::from_bits(self.data[index + 1].load(Ordering::Relaxed)),
+            f64::from_bits(self.data[index + 2].load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Gamma corrects and quantizes the image to 8 bits per channel.
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let mut raw = Vec::with_capacity(self.data.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                raw.extend_from_slice(&self.get(x, y).to_rgb_array(1));
+            }
+        }
+        RgbImage::from_raw(self.width, self.height, raw)
+            .expect("framebuffer size matches its dimensions")
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        (y as usize * self.width as usize + x as usize) * 3
+    }
+}
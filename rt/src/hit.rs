@@ -1,10 +1,24 @@
+use crate::aabb::Aabb;
 use crate::material::{Lambertian, MaterialPtr};
 use crate::ray::Ray;
+use rand::Rng;
 use std::sync::Arc;
-use vec3::{Point3, Vec3};
+use vec3::{Color, Point3, Vec3};
 
 pub trait Hittable {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    /// Returns a box enclosing the object for the whole time interval [t0, t1],
+    /// or None when the object is unbounded (e.g. an infinite plane).
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb>;
+    /// Density, with respect to solid angle, of `random` returning `direction` from
+    /// `origin`. Zero for objects that can't be sampled as lights.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+    /// Returns a direction from `origin` towards a random point on the object.
+    fn random(&self, _origin: &Point3, _time: f64) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub struct HitRecord {
@@ -15,6 +29,11 @@ pub struct HitRecord {
     pub v: f64,
     pub front_face: bool,
     pub mat_ptr: MaterialPtr,
+    /// The vertex colors of a mesh interpolated at the hit, when the mesh has them.
+    pub vertex_color: Option<Color>,
+    /// The direction in which `u` increases along the surface, when the object has a
+    /// parameterization. Not normalized, and not necessarily perpendicular to `normal`.
+    pub tangent: Option<Vec3>,
 }
 
 impl HitRecord {
@@ -35,6 +54,8 @@ impl HitRecord {
             v,
             front_face,
             mat_ptr,
+            vertex_color: None,
+            tangent: None,
         }
     }
 
@@ -81,7 +102,17 @@ impl HittableList {
         self.objects.clear();
     }
 
-    pub fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    pub fn objects(&self) -> &[HittablePtr] {
+        &self.objects
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut temp_rec: HitRecord = HitRecord::default();
         let mut hit_anything: bool = false;
         let mut closest_so_far = t_max;
@@ -95,10 +126,45 @@ impl HittableList {
         }
 
         if hit_anything {
-            return Some(temp_rec);
+            Some(temp_rec)
         } else {
-            return None;
+            None
+        }
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let mut output_box: Option<Aabb> = None;
+
+        for o in self.objects.iter() {
+            let temp_box = o.bounding_box(t0, t1)?;
+            output_box = match output_box {
+                Some(b) => Some(Aabb::surrounding_box(&b, &temp_box)),
+                None => Some(temp_box),
+            };
+        }
+
+        output_box
+    }
+
+    /// The objects are picked with equal probability, so the density is their average.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
+            .iter()
+            .map(|o| o.pdf_value(origin, direction, time))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
         }
+        let index = vec3::rng::thread_rng().gen_range(0, self.objects.len());
+        self.objects[index].random(origin, time)
     }
 }
 
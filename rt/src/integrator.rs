@@ -0,0 +1,407 @@
+use crate::hit::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::renderer::RenderScene;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use rand::Rng;
+use std::sync::Arc;
+use vec3::Color;
+
+/// Computes the light arriving at the camera along a ray.
+pub trait Integrator {
+    fn radiance(&self, r: &Ray, scene: &RenderScene) -> Color;
+}
+
+pub type IntegratorPtr = Arc<dyn Integrator + Send + Sync>;
+
+/// Unidirectional path tracer that follows the material's scattered rays until they escape
+/// to the background, hit a light or run out of bounces.
+pub struct PathTracer {
+    max_depth: u32,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: u32) -> Self {
+        PathTracer { max_depth }
+    }
+
+    fn ray_color(&self, r: &Ray, scene: &RenderScene, depth: u32) -> Color {
+        if depth == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        if let Some(rec) = scene.world.hit(r, 0.001, f64::INFINITY) {
+            let mut emitted = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+            // Scattered rays never hit delta lights, so they're always sampled.
+            if let Some(light) = sample_delta_light(r, &rec, scene) {
+                emitted += light.f * light.emitted;
+            }
+            if let Some(srec) = rec.mat_ptr.scatter(r, &rec) {
+                emitted + srec.attenuation * self.ray_color(&srec.ray, scene, depth - 1)
+            } else {
+                emitted
+            }
+        } else {
+            scene.escaped_radiance(&r.direction)
+        }
+    }
+}
+
+impl Default for PathTracer {
+    fn default() -> Self {
+        PathTracer::new(50)
+    }
+}
+
+impl Integrator for PathTracer {
+    fn radiance(&self, r: &Ray, scene: &RenderScene) -> Color {
+        self.ray_color(r, scene, self.max_depth)
+    }
+}
+
+/// Path tracer that also samples the lights directly at every diffuse bounce (next event
+/// estimation) and weights both strategies with the power heuristic, so small lights are
+/// found without relying on paths to hit them by chance.
+pub struct MisPathTracer {
+    max_depth: u32,
+}
+
+impl MisPathTracer {
+    pub fn new(max_depth: u32) -> Self {
+        MisPathTracer { max_depth }
+    }
+}
+
+impl Default for MisPathTracer {
+    fn default() -> Self {
+        MisPathTracer::new(50)
+    }
+}
+
+impl Integrator for MisPathTracer {
+    fn radiance(&self, r: &Ray, scene: &RenderScene) -> Color {
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = Ray::new(r.origin, r.direction, r.time);
+        // Density of the bounce that produced `ray`, zero for camera rays and delta bounces,
+        // whose emission can't be found by light sampling.
+        let mut scattering_pdf = 0.0;
+        let mut previous_point = r.origin;
+
+        for _ in 0..self.max_depth {
+            let rec = match scene.world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(rec) => rec,
+                None => {
+                    let weight = if scattering_pdf > 0.0 {
+                        power_heuristic(
+                            scattering_pdf,
+                            scene.light_pdf(&previous_point, &ray.direction, ray.time),
+                        )
+                    } else {
+                        1.0
+                    };
+                    radiance += throughput * scene.escaped_radiance(&ray.direction) * weight;
+                    break;
+                }
+            };
+
+            let emitted = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+            let weight = if scattering_pdf > 0.0 {
+                let light_pdf = scene.light_pdf(&previous_point, &ray.direction, ray.time);
+                power_heuristic(scattering_pdf, light_pdf)
+            } else {
+                1.0
+            };
+            radiance += throughput * emitted * weight;
+
+            // Lights are sampled even when the BSDF sample below is absorbed, delta
+            // materials have no density for them and contribute nothing here.
+            if scene.has_lights() {
+                if let Some(light) = sample_light(&ray, &rec, scene) {
+                    radiance += throughput * light.f * light.emitted * light.weight;
+                }
+            }
+            if let Some(light) = sample_delta_light(&ray, &rec, scene) {
+                radiance += throughput * light.f * light.emitted;
+            }
+
+            let srec = match rec.mat_ptr.scatter(&ray, &rec) {
+                Some(srec) => srec,
+                None => break,
+            };
+
+            scattering_pdf = if srec.is_delta { 0.0 } else { srec.pdf };
+
+            throughput *= srec.attenuation;
+            previous_point = rec.p;
+            ray = srec.ray;
+        }
+
+        radiance
+    }
+}
+
+/// The MIS path tracer, but every path carries a handful of wavelengths instead of RGB
+/// (hero wavelength sampling). Colors of textures, materials and lights are upsampled to
+/// spectra and the result is converted back to sRGB through CIE XYZ. Dispersive materials
+/// split light into a rainbow, past them a path only carries its hero wavelength.
+pub struct SpectralPathTracer {
+    max_depth: u32,
+}
+
+impl SpectralPathTracer {
+    pub fn new(max_depth: u32) -> Self {
+        SpectralPathTracer { max_depth }
+    }
+}
+
+impl Default for SpectralPathTracer {
+    fn default() -> Self {
+        SpectralPathTracer::new(50)
+    }
+}
+
+impl Integrator for SpectralPathTracer {
+    fn radiance(&self, r: &Ray, scene: &RenderScene) -> Color {
+        let mut rng = vec3::rng::thread_rng();
+        let mut wavelengths = SampledWavelengths::sample_uniform(rng.gen());
+        let mut radiance = SampledSpectrum::new(0.0);
+        let mut throughput = SampledSpectrum::new(1.0);
+        let mut ray = Ray::new(r.origin, r.direction, r.time).with_wavelength(wavelengths.hero());
+        let mut scattering_pdf = 0.0;
+        let mut previous_point = r.origin;
+
+        for _ in 0..self.max_depth {
+            let rec = match scene.world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(rec) => rec,
+                None => {
+                    let weight = if scattering_pdf > 0.0 {
+                        power_heuristic(
+                            scattering_pdf,
+                            scene.light_pdf(&previous_point, &ray.direction, ray.time),
+                        )
+                    } else {
+                        1.0
+                    };
+                    let escaped = scene.escaped_radiance(&ray.direction);
+                    radiance +=
+                        throughput * SampledSpectrum::from_rgb(&escaped, &wavelengths) * weight;
+                    break;
+                }
+            };
+
+            let emitted = rec.mat_ptr.emitted(rec.u, rec.v, &rec.p);
+            let weight = if scattering_pdf > 0.0 {
+                let light_pdf = scene.light_pdf(&previous_point, &ray.direction, ray.time);
+                power_heuristic(scattering_pdf, light_pdf)
+            } else {
+                1.0
+            };
+            radiance += throughput * SampledSpectrum::from_rgb(&emitted, &wavelengths) * weight;
+
+            let lights = if scene.has_lights() {
+                sample_light(&ray, &rec, scene)
+            } else {
+                None
+            };
+            let delta_light = sample_delta_light(&ray, &rec, scene);
+            for light in lights.iter().chain(delta_light.iter()) {
+                let f = SampledSpectrum::from_rgb(&light.f, &wavelengths);
+                let emitted = SampledSpectrum::from_rgb(&light.emitted, &wavelengths);
+                radiance += throughput * f * emitted * light.weight;
+            }
+
+            let srec = match rec.mat_ptr.scatter(&ray, &rec) {
+                Some(srec) => srec,
+                None => break,
+            };
+            if rec.mat_ptr.is_dispersive() {
+                wavelengths.terminate_secondary();
+            }
+
+            scattering_pdf = if srec.is_delta { 0.0 } else { srec.pdf };
+            throughput *= SampledSpectrum::from_rgb(&srec.attenuation, &wavelengths);
+            previous_point = rec.p;
+            ray = srec.ray.with_wavelength(wavelengths.hero());
+        }
+
+        wavelengths.to_rgb(&radiance)
+    }
+}
+
+/// Light reaching a hit from a direction sampled on the lights. The contribution is
+/// `f * emitted * weight`, kept apart so spectral integrators can upsample the colors.
+struct LightSample {
+    f: Color,
+    emitted: Color,
+    /// The MIS weight over the density of the light sample.
+    weight: f64,
+}
+
+/// Samples a direction on the lights from `rec.p`, weighted against the chance of the
+/// material scattering in that direction. None when nothing is received.
+fn sample_light(r_in: &Ray, rec: &HitRecord, scene: &RenderScene) -> Option<LightSample> {
+    let direction = scene.sample_light(&rec.p, r_in.time);
+    let light_pdf = scene.light_pdf(&rec.p, &direction, r_in.time);
+    if light_pdf <= 0.0 {
+        return None;
+    }
+    let scattering_pdf = rec.mat_ptr.pdf(r_in, rec, &direction);
+    if scattering_pdf <= 0.0 {
+        return None;
+    }
+    let f = rec.mat_ptr.eval(r_in, rec, &direction);
+
+    // Whatever the shadow ray hits first is what it sees, an occluder emits nothing.
+    let shadow_ray = Ray::new(rec.p, direction, r_in.time);
+    let emitted = match scene.world.hit(&shadow_ray, 0.001, f64::INFINITY) {
+        Some(light_rec) => light_rec
+            .mat_ptr
+            .emitted(light_rec.u, light_rec.v, &light_rec.p),
+        None => scene.escaped_radiance(&direction),
+    };
+    Some(LightSample {
+        f,
+        emitted,
+        weight: power_heuristic(light_pdf, scattering_pdf) / light_pdf,
+    })
+}
+
+/// Light reaching a hit from one of the delta lights. Scattered rays can't find these, so
+/// the sample needs no MIS weight.
+fn sample_delta_light(r_in: &Ray, rec: &HitRecord, scene: &RenderScene) -> Option<LightSample> {
+    if scene.delta_lights.is_empty() {
+        return None;
+    }
+    let incident = scene
+        .delta_lights
+        .sample(&rec.p, vec3::rng::thread_rng().gen())?;
+    let f = rec.mat_ptr.eval(r_in, rec, &incident.direction);
+    if f.length_squared() <= 0.0 {
+        return None;
+    }
+    let shadow_ray = Ray::new(rec.p, incident.direction, r_in.time);
+    if scene
+        .world
+        .hit(&shadow_ray, 0.001, incident.distance - 0.001)
+        .is_some()
+    {
+        return None;
+    }
+    Some(LightSample {
+        f,
+        emitted: incident.irradiance,
+        weight: 1.0,
+    })
+}
+
+/// Weight of a sample taken with density `pdf` when another strategy could have taken it
+/// with density `other_pdf`.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf2 = pdf * pdf;
+    pdf2 / (pdf2 + other_pdf * other_pdf)
+}
+
+/// Shades hits with their surface normal, which is handy to debug geometry and meshes.
+pub struct NormalIntegrator;
+
+impl Integrator for NormalIntegrator {
+    fn radiance(&self, r: &Ray, scene: &RenderScene) -> Color {
+        match scene.world.hit(r, 0.001, f64::INFINITY) {
+            Some(rec) => (rec.normal + Color::new(1.0, 1.0, 1.0)) * 0.5,
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flat_bvh::FlatBvh;
+    use crate::hit::{HittableList, HittablePtr};
+    use crate::light::LightList;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::objects::{Triangle, TriangleMesh, XzRect};
+    use vec3::{Point3, Vec3};
+
+    /// A gray floor lit only by `light`.
+    fn scene_with_light(light: HittablePtr) -> RenderScene {
+        let floor: HittablePtr = Arc::new(XzRect::new(
+            -5.0,
+            5.0,
+            -5.0,
+            5.0,
+            0.0,
+            Arc::new(Lambertian::new_from_color(&Color::new(0.5, 0.5, 0.5))),
+        ));
+        RenderScene {
+            world: FlatBvh::new(&HittableList::new(vec![floor, light.clone()]), 0.0, 1.0),
+            lights: HittableList::new(vec![light]),
+            delta_lights: LightList::default(),
+            background: Color::default(),
+            environment: None,
+        }
+    }
+
+    /// The average radiance seen along rays from above towards random points on the floor.
+    fn mean_radiance(integrator: &dyn Integrator, scene: &RenderScene, samples: u32) -> f64 {
+        vec3::rng::seed(5);
+        let mut rng = vec3::rng::thread_rng();
+        let origin = Point3::new(0.0, 3.0, 4.0);
+        let mut total = 0.0;
+        for _ in 0..samples {
+            let target = Point3::new(rng.gen_range(-3.0, 3.0), 0.0, rng.gen_range(-3.0, 3.0));
+            let c = integrator.radiance(&Ray::new(origin, target - origin, 0.0), scene);
+            total += (c.x + c.y + c.z) / 3.0;
+        }
+        total / samples as f64
+    }
+
+    fn assert_mis_matches_path(light: HittablePtr) {
+        let scene = scene_with_light(light);
+        let path = mean_radiance(&PathTracer::new(5), &scene, 40000);
+        let mis = mean_radiance(&MisPathTracer::new(5), &scene, 40000);
+        assert!(
+            (mis - path).abs() < 0.05 * path,
+            "mis {} path {}",
+            mis,
+            path
+        );
+    }
+
+    #[test]
+    fn mis_matches_path_for_triangle_light() {
+        assert_mis_matches_path(Arc::new(Triangle::new(
+            Vec3::new(-1.0, 2.0, -1.0),
+            Vec3::new(1.5, 2.0, -1.0),
+            Vec3::new(0.0, 2.0, 1.5),
+            Arc::new(DiffuseLight::new_from_color(Color::new(4.0, 4.0, 4.0))),
+        )));
+    }
+
+    #[test]
+    fn mis_matches_path_for_closed_mesh_light() {
+        let positions = vec![
+            Point3::new(1.0, 1.5, 0.0),
+            Point3::new(-1.0, 1.5, 0.0),
+            Point3::new(0.0, 2.5, 0.0),
+            Point3::new(0.0, 0.5, 0.0),
+            Point3::new(0.0, 1.5, 1.0),
+            Point3::new(0.0, 1.5, -1.0),
+        ];
+        let indices = vec![
+            [0, 2, 4],
+            [2, 1, 4],
+            [1, 3, 4],
+            [3, 0, 4],
+            [2, 0, 5],
+            [1, 2, 5],
+            [3, 1, 5],
+            [0, 3, 5],
+        ];
+        assert_mis_matches_path(Arc::new(TriangleMesh::new(
+            positions,
+            indices,
+            Arc::new(DiffuseLight::new_from_color(Color::new(2.0, 2.0, 2.0))),
+        )));
+    }
+}
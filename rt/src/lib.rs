@@ -1,8 +1,29 @@
+pub mod aabb;
+pub mod bvh;
 pub mod camera;
+pub mod density;
+pub mod distribution;
+pub mod environment;
+pub mod exr;
+pub mod flat_bvh;
+pub mod framebuffer;
 pub mod hit;
+pub mod integrator;
+pub mod light;
 pub mod material;
+pub mod microfacet;
+pub mod obj;
 pub mod objects;
+pub mod onb;
 pub mod perlin;
+pub mod phase;
+pub mod ply;
 pub mod ray;
+pub mod renderer;
+pub mod scene;
+pub mod scheduler;
+pub mod sky;
+pub mod spectrum;
 pub mod texture;
 pub mod transform;
+pub mod voxel;
@@ -0,0 +1,270 @@
+//! Lights without any geometry: points, spots and directions. Rays can't hit them, so
+//! integrators reach them only by sampling them from every shading point.
+
+use std::sync::Arc;
+use vec3::{Color, Point3, Vec3};
+
+/// Light arriving at a point from a delta light.
+#[derive(Debug, Clone, Copy)]
+pub struct Incident {
+    /// Unit vector from the point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, infinite for directional lights.
+    pub distance: f64,
+    /// Power per unit area arriving on a surface facing the light.
+    pub irradiance: Color,
+}
+
+pub trait Light {
+    /// The light arriving at `p`, or None when none does.
+    fn incident(&self, p: &Point3) -> Option<Incident>;
+}
+
+pub type LightPtr = Arc<dyn Light + Send + Sync>;
+
+/// Shines equally in every direction from a point.
+pub struct PointLight {
+    position: Point3,
+    /// Power per unit solid angle.
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn incident(&self, p: &Point3) -> Option<Incident> {
+        incident_from_point(&self.position, p, self.intensity)
+    }
+}
+
+/// A point light restricted to a cone. It's at full intensity inside `inner_angle` of its
+/// axis, fades out towards `outer_angle` and is dark beyond it.
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_inner: f64,
+    cos_outer: f64,
+    falloff: f64,
+}
+
+impl SpotLight {
+    /// The angles are in degrees from the axis. `falloff` is the exponent of the fade
+    /// between them: 1 is linear in the cosine, larger values narrow the bright part.
+    pub fn new(
+        position: Point3,
+        direction: Vec3,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+        falloff: f64,
+    ) -> Self {
+        let outer_angle = num::clamp(outer_angle, 0.0, 180.0);
+        let inner_angle = num::clamp(inner_angle, 0.0, outer_angle);
+        SpotLight {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_inner: inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            falloff,
+        }
+    }
+
+    fn cone(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            1.0
+        } else if cos_theta <= self.cos_outer {
+            0.0
+        } else {
+            ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).powf(self.falloff)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn incident(&self, p: &Point3) -> Option<Incident> {
+        let incident = incident_from_point(&self.position, p, self.intensity)?;
+        let cone = self.cone(-incident.direction.dot(self.direction));
+        if cone <= 0.0 {
+            return None;
+        }
+        Some(Incident {
+            irradiance: incident.irradiance * cone,
+            ..incident
+        })
+    }
+}
+
+/// Parallel light from infinitely far away, like the sun.
+pub struct DirectionalLight {
+    /// Unit vector the light travels along.
+    direction: Vec3,
+    irradiance: Color,
+}
+
+impl DirectionalLight {
+    /// `direction` is the way the light travels, and `irradiance` the power it delivers
+    /// per unit area to a surface facing it.
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        DirectionalLight {
+            direction: direction.unit_vector(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn incident(&self, _p: &Point3) -> Option<Incident> {
+        Some(Incident {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            irradiance: self.irradiance,
+        })
+    }
+}
+
+/// Light from `position` reaching `p`, falling off with the square of the distance.
+fn incident_from_point(position: &Point3, p: &Point3, intensity: Color) -> Option<Incident> {
+    let to_light = *position - *p;
+    let distance_squared = to_light.length_squared();
+    if distance_squared <= 0.0 {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    Some(Incident {
+        direction: to_light / distance,
+        distance,
+        irradiance: intensity / distance_squared,
+    })
+}
+
+/// The delta lights of a scene. Integrators sample one of them, picked uniformly, per
+/// shading point.
+#[derive(Default, Clone)]
+pub struct LightList {
+    lights: Vec<LightPtr>,
+}
+
+impl LightList {
+    pub fn new(lights: Vec<LightPtr>) -> Self {
+        LightList { lights }
+    }
+
+    pub fn add(&mut self, light: LightPtr) {
+        self.lights.push(light);
+    }
+
+    pub fn lights(&self) -> &[LightPtr] {
+        &self.lights
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Picks a light with `u` in [0, 1) and returns the light it sends to `p`, divided by
+    /// the chance of picking it.
+    pub fn sample(&self, p: &Point3, u: f64) -> Option<Incident> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let count = self.lights.len();
+        let index = ((u * count as f64) as usize).min(count - 1);
+        let incident = self.lights[index].incident(p)?;
+        Some(Incident {
+            irradiance: incident.irradiance * count as f64,
+            ..incident
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A spot one unit above the origin pointing down, lit with unit intensity.
+    fn spot(falloff: f64) -> SpotLight {
+        SpotLight::new(
+            Point3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -2.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+            20.0,
+            40.0,
+            falloff,
+        )
+    }
+
+    /// The irradiance one unit away from the spot, `angle` degrees off its axis.
+    fn irradiance_at(light: &SpotLight, angle: f64) -> f64 {
+        let angle = angle.to_radians();
+        let p = Point3::new(angle.sin(), 1.0 - angle.cos(), 0.0);
+        light.incident(&p).map_or(0.0, |incident| {
+            assert!((incident.distance - 1.0).abs() < 1e-12);
+            incident.irradiance.x
+        })
+    }
+
+    #[test]
+    fn spot_lights_fade_between_their_angles() {
+        let (cos_inner, cos_outer) = (20f64.to_radians().cos(), 40f64.to_radians().cos());
+        for &falloff in [1.0, 2.0, 0.5].iter() {
+            let light = spot(falloff);
+            assert!((irradiance_at(&light, 0.0) - 1.0).abs() < 1e-12);
+            assert!((irradiance_at(&light, 19.9) - 1.0).abs() < 1e-12);
+            assert!(light.incident(&Point3::new(1.0, 0.0, 0.0)).is_none());
+            assert!(light.incident(&Point3::new(0.0, 2.0, 0.0)).is_none());
+
+            let mut previous = 1.0;
+            for i in 1..20 {
+                let angle = 20.0 + i as f64;
+                let t = (angle.to_radians().cos() - cos_outer) / (cos_inner - cos_outer);
+                let irradiance = irradiance_at(&light, angle);
+                assert!((irradiance - t.powf(falloff)).abs() < 1e-12, "{}", angle);
+                assert!(irradiance < previous && irradiance > 0.0, "{}", angle);
+                previous = irradiance;
+            }
+            assert_eq!(irradiance_at(&light, 40.1), 0.0);
+        }
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_squared_distance() {
+        let light = PointLight::new(Point3::new(1.0, 2.0, 3.0), Color::new(8.0, 4.0, 2.0));
+        let incident = light.incident(&Point3::new(1.0, 2.0, 1.0)).unwrap();
+        assert!((incident.direction - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert_eq!(incident.distance, 2.0);
+        assert!((incident.irradiance - Color::new(2.0, 1.0, 0.5)).length() < 1e-12);
+        assert!(light.incident(&Point3::new(1.0, 2.0, 3.0)).is_none());
+    }
+
+    #[test]
+    fn lists_divide_by_the_chance_of_picking_a_light() {
+        let lights = LightList::new(vec![
+            Arc::new(DirectionalLight::new(
+                Vec3::new(0.0, -1.0, 0.0),
+                Color::new(1.0, 1.0, 1.0),
+            )),
+            Arc::new(PointLight::new(
+                Point3::new(0.0, 1.0, 0.0),
+                Color::new(2.0, 2.0, 2.0),
+            )),
+        ]);
+        let sun = lights.sample(&Point3::default(), 0.2).unwrap();
+        assert_eq!(sun.distance, f64::INFINITY);
+        assert!((sun.direction - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((sun.irradiance - Color::new(2.0, 2.0, 2.0)).length() < 1e-12);
+        let point = lights.sample(&Point3::default(), 0.99).unwrap();
+        assert!((point.irradiance - Color::new(4.0, 4.0, 4.0)).length() < 1e-12);
+        assert!(LightList::default()
+            .sample(&Point3::default(), 0.5)
+            .is_none());
+    }
+}
@@ -1,15 +1,66 @@
 use crate::hit::HitRecord;
+use crate::microfacet::{self, fresnel_conductor, fresnel_dielectric, Ggx, MIN_ALPHA};
+use crate::onb::Onb;
+use crate::phase::PhaseFunctionPtr;
 use crate::ray::Ray;
+use crate::spectrum::Dispersion;
 use crate::texture::{SolidColor, TexturePtr};
 use rand::Rng;
+use std::f64::consts::PI;
+use std::str::FromStr;
 use std::sync::Arc;
 use vec3::{Color, Point3, Vec3};
 
+/// A direction sampled by `Material::scatter`.
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// The sample's weight: `eval / pdf`, or the reflectance of a delta lobe.
+    pub attenuation: Color,
+    /// Density, with respect to solid angle, of sampling `ray`'s direction. Meaningless
+    /// for delta lobes.
+    pub pdf: f64,
+    /// Whether the direction was picked from a delta distribution (a perfect mirror or
+    /// glass), which `eval` and `pdf` can't represent and lights can't be sampled for.
+    pub is_delta: bool,
+}
+
+impl ScatterRecord {
+    pub fn new_delta(ray: Ray, attenuation: Color) -> Self {
+        ScatterRecord {
+            ray,
+            attenuation,
+            pdf: 0.0,
+            is_delta: true,
+        }
+    }
+}
+
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    /// Samples the direction the incoming ray continues in, or returns None when it's absorbed.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+    /// The BSDF for light leaving along `direction` towards `r_in`'s origin, times the
+    /// cosine of `direction` with the normal (the phase function for media).
+    /// Delta lobes aren't included.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::default()
+    }
+    /// Density, with respect to solid angle, of `scatter` sampling `direction`. Delta
+    /// lobes aren't included.
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
     fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
         Color::default()
     }
+    /// Whether the material emits light, so objects using it can be sampled as lights.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    /// Whether scattered directions depend on the ray's wavelength, so a spectral path
+    /// can only carry one wavelength past it.
+    fn is_dispersive(&self) -> bool {
+        false
+    }
 }
 
 pub type MaterialPtr = Arc<dyn Material + Send + Sync>;
@@ -31,13 +82,30 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    /// Returns (scattered ray, attenuation).
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let scatter_direction: Vec3 = rec.normal + Vec3::random_unit_vector();
-        Some((
-            Ray::new(rec.p, scatter_direction, r_in.time),
-            self.albedo.value(rec.u, rec.v, &rec.p),
-        ))
+    /// Samples the cosine weighted hemisphere around the normal, which makes the weight of
+    /// every sample the albedo.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let uvw = Onb::new_from_w(&rec.normal);
+        let direction = uvw.local_vec(&Vec3::random_cosine_direction());
+        Some(ScatterRecord {
+            pdf: self.pdf(r_in, rec, &direction),
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value_at(rec),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value_at(rec) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = rec.normal.dot(direction.unit_vector());
+        if cosine < 0.0 {
+            0.0
+        } else {
+            cosine / PI
+        }
     }
 }
 
@@ -58,21 +126,59 @@ impl Metal {
     }
 }
 
+impl Metal {
+    /// The direction of a perfect mirror reflection of `r_in`.
+    fn reflected(r_in: &Ray, rec: &HitRecord) -> Vec3 {
+        Vec3::reflect(r_in.direction.unit_vector(), rec.normal)
+    }
+}
+
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        let reflected: Vec3 = Vec3::reflect(r_in.direction.unit_vector(), rec.normal);
-        let scattered = Ray::new(
-            rec.p,
-            reflected + Vec3::random_in_unit_sphere() * self.fuzz,
-            r_in.time,
-        );
-        let attenuation = self.albedo;
+    /// Perturbs the mirror direction by a random point in a sphere of radius `fuzz`, which
+    /// is a delta distribution when there's no fuzz.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let reflected = Metal::reflected(r_in, rec);
+        if self.fuzz <= 0.0 {
+            let scattered = Ray::new(rec.p, reflected, r_in.time);
+            return Some(ScatterRecord::new_delta(scattered, self.albedo));
+        }
 
-        if scattered.direction.dot(rec.normal) > 0.0 {
-            return Some((scattered, attenuation));
-        } else {
+        let direction = reflected + Vec3::random_in_unit_sphere() * self.fuzz;
+        // Directions below the surface are absorbed.
+        if direction.dot(rec.normal) <= 0.0 {
             return None;
         }
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo,
+            pdf: self.pdf(r_in, rec, &direction),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo * self.pdf(r_in, rec, direction)
+    }
+
+    /// The directions of points uniformly distributed in a ball of radius `fuzz` around the
+    /// mirror direction. Integrating r² along the part of `direction` inside the ball gives
+    /// the density in closed form.
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.fuzz <= 0.0 || direction.dot(rec.normal) <= 0.0 {
+            return 0.0;
+        }
+        let reflected = Metal::reflected(r_in, rec);
+        let cosine = direction.unit_vector().dot(reflected);
+        let discriminant = self.fuzz * self.fuzz - (1.0 - cosine * cosine);
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+        let far = cosine + discriminant.sqrt();
+        if far <= 0.0 {
+            return 0.0;
+        }
+        let near = (cosine - discriminant.sqrt()).max(0.0);
+        (far.powi(3) - near.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
     }
 }
 
@@ -82,13 +188,198 @@ impl Default for Metal {
     }
 }
 
+/// A measured metal for `Conductor::new_from_preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorPreset {
+    /// The complex index of refraction `(eta, k)`, sampled at red, green and blue wavelengths.
+    pub fn ior(&self) -> (Color, Color) {
+        match self {
+            ConductorPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            ConductorPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            ConductorPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            ConductorPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl FromStr for ConductorPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gold" => Ok(ConductorPreset::Gold),
+            "copper" => Ok(ConductorPreset::Copper),
+            "aluminium" | "aluminum" => Ok(ConductorPreset::Aluminium),
+            "silver" => Ok(ConductorPreset::Silver),
+            _ => Err(format!(
+                "unknown metal '{}', expected gold, copper, aluminium or silver",
+                s
+            )),
+        }
+    }
+}
+
+/// A rough metal with a GGX microfacet distribution and the exact Fresnel reflectance of a
+/// complex index of refraction. Anisotropic roughness is stretched along the directions
+/// in which the surface's `u` and `v` coordinates increase, which objects without a
+/// parameterization leave arbitrary.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+    smooth: bool,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Conductor {
+        Conductor::new_anisotropic(eta, k, roughness, roughness)
+    }
+
+    pub fn new_anisotropic(eta: Color, k: Color, roughness_u: f64, roughness_v: f64) -> Conductor {
+        let alpha_x = Ggx::roughness_to_alpha(roughness_u);
+        let alpha_y = Ggx::roughness_to_alpha(roughness_v);
+        Conductor {
+            eta,
+            k,
+            distribution: Ggx::new(alpha_x, alpha_y),
+            smooth: alpha_x < MIN_ALPHA && alpha_y < MIN_ALPHA,
+        }
+    }
+
+    pub fn new_from_preset(preset: ConductorPreset, roughness: f64) -> Conductor {
+        let (eta, k) = preset.ior();
+        Conductor::new(eta, k, roughness)
+    }
+
+    fn fresnel(&self, cosine: f64) -> Color {
+        Color::new(
+            fresnel_conductor(cosine, self.eta.x, self.k.x),
+            fresnel_conductor(cosine, self.eta.y, self.k.y),
+            fresnel_conductor(cosine, self.eta.z, self.k.z),
+        )
+    }
+
+    /// The shading frame, with `u` along the surface tangent, and the direction towards
+    /// `r_in`'s origin in it.
+    fn local_frame(r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3) {
+        let uvw = match &rec.tangent {
+            Some(tangent) => Onb::new_from_w_and_u(&rec.normal, tangent),
+            None => Onb::new_from_w(&rec.normal),
+        };
+        let wo = uvw.to_local(&-r_in.direction.unit_vector());
+        (uvw, wo)
+    }
+}
+
+impl Material for Conductor {
+    /// Samples the microfacet normals visible from the incoming direction and reflects
+    /// around them, which leaves `F * G / G1` as the weight.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (uvw, wo) = Conductor::local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        if self.smooth {
+            let direction = uvw.local_vec(&microfacet::reflect(&wo, &Vec3::new(0.0, 0.0, 1.0)));
+            let scattered = Ray::new(rec.p, direction, r_in.time);
+            return Some(ScatterRecord::new_delta(scattered, self.fresnel(wo.z)));
+        }
+
+        let mut rng = vec3::rng::thread_rng();
+        let wh = self.distribution.sample_wh(&wo, rng.gen(), rng.gen());
+        let wi = microfacet::reflect(&wo, &wh);
+        // Reflections off the sampled facet that end up below the surface are absorbed.
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let weight = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+        let direction = uvw.local_vec(&wi);
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.fresnel(wo.dot(wh)) * weight,
+            pdf: self.pdf(r_in, rec, &direction),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.smooth {
+            return Color::default();
+        }
+        let (uvw, wo) = Conductor::local_frame(r_in, rec);
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::default();
+        }
+        let wh = (wo + wi).unit_vector();
+        let d = self.distribution.d(&wh);
+        let g = self.distribution.g(&wo, &wi);
+        // The cosine of `wi` cancels against the denominator of the BSDF.
+        self.fresnel(wo.dot(wh)) * (d * g / (4.0 * wo.z))
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.smooth {
+            return 0.0;
+        }
+        let (uvw, wo) = Conductor::local_frame(r_in, rec);
+        let wi = uvw.to_local(&direction.unit_vector());
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wh = (wo + wi).unit_vector();
+        self.distribution.pdf_wh(&wo, &wh) / (4.0 * wo.dot(wh))
+    }
+}
+
 pub struct Dielectric {
     ref_idx: f64,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f64) -> Dielectric {
-        Dielectric { ref_idx }
+        Dielectric {
+            ref_idx,
+            dispersion: None,
+        }
+    }
+
+    /// Glass whose index of refraction depends on the wavelength of spectral rays. RGB
+    /// rays use the index at the sodium d line.
+    pub fn new_dispersive(dispersion: Dispersion) -> Dielectric {
+        Dielectric {
+            ref_idx: dispersion.ior_d(),
+            dispersion: Some(dispersion),
+        }
+    }
+
+    fn ref_idx(&self, r_in: &Ray) -> f64 {
+        match (&self.dispersion, r_in.wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+            _ => self.ref_idx,
+        }
     }
 
     fn schlick(cosine: f64, ref_idx: f64) -> f64 {
@@ -99,12 +390,13 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         let attenuation = Color::new(1.0, 1.0, 1.0);
+        let ref_idx = self.ref_idx(r_in);
         let etai_over_etat = if rec.front_face {
-            1.0 / self.ref_idx
+            1.0 / ref_idx
         } else {
-            self.ref_idx
+            ref_idx
         };
 
         let unit_direction = r_in.direction.unit_vector();
@@ -114,20 +406,24 @@ impl Material for Dielectric {
         if etai_over_etat * sin_theta > 1.0 {
             let reflected = Vec3::reflect(unit_direction, rec.normal);
             let scattered = Ray::new(rec.p, reflected, r_in.time);
-            return Some((scattered, attenuation));
+            return Some(ScatterRecord::new_delta(scattered, attenuation));
         }
 
         let reflect_prob = Dielectric::schlick(cos_theta, etai_over_etat);
-        let mut rng = rand::thread_rng();
+        let mut rng = vec3::rng::thread_rng();
         if rng.gen::<f64>() < reflect_prob {
             let reflected = Vec3::reflect(unit_direction, rec.normal);
             let scattered = Ray::new(rec.p, reflected, r_in.time);
-            return Some((scattered, attenuation));
+            return Some(ScatterRecord::new_delta(scattered, attenuation));
         }
 
         let refracted = Vec3::refract(unit_direction, rec.normal, etai_over_etat);
         let scattered = Ray::new(rec.p, refracted, r_in.time);
-        Some((scattered, attenuation))
+        Some(ScatterRecord::new_delta(scattered, attenuation))
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some()
     }
 }
 
@@ -137,6 +433,495 @@ impl Default for Dielectric {
     }
 }
 
+/// Frosted glass: a dielectric interface with a GGX distribution of microfacets that both
+/// reflect and refract, weighted by the exact Fresnel equations. Roughness is read from a
+/// texture, as the average of its channels.
+pub struct RoughDielectric {
+    ref_idx: f64,
+    roughness: TexturePtr,
+}
+
+impl RoughDielectric {
+    pub fn new(ref_idx: f64, roughness: f64) -> RoughDielectric {
+        let roughness = SolidColor::new_from_rgb(roughness, roughness, roughness);
+        RoughDielectric::new_from_texture(ref_idx, Arc::new(roughness))
+    }
+
+    pub fn new_from_texture(ref_idx: f64, roughness: TexturePtr) -> RoughDielectric {
+        RoughDielectric { ref_idx, roughness }
+    }
+
+    /// The shading frame, the direction towards `r_in`'s origin in it, the relative index
+    /// of refraction across the surface and the roughness at the hit.
+    fn local_frame(&self, r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3, f64, f64) {
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-r_in.direction.unit_vector());
+        let eta = if rec.front_face {
+            self.ref_idx
+        } else {
+            1.0 / self.ref_idx
+        };
+        let alpha = Ggx::roughness_to_alpha(texture_scalar(&self.roughness, rec));
+        (uvw, wo, eta, alpha)
+    }
+
+    /// The BSDF times the cosine of `wi`, and the density of sampling `wi`. The density of
+    /// the sampled microfacet normal is converted to one over `wi` with the Jacobian of
+    /// the reflection or refraction around it.
+    fn evaluate(distribution: &Ggx, wo: &Vec3, wi: &Vec3, eta: f64) -> (f64, f64) {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return (0.0, 0.0);
+        }
+        let reflect = wi.z > 0.0;
+        let eta_wi = if reflect { 1.0 } else { eta };
+        let wh = *wi * eta_wi + *wo;
+        if wh.length_squared() == 0.0 {
+            return (0.0, 0.0);
+        }
+        let wh = wh.unit_vector();
+        let wh = if wh.z < 0.0 { -wh } else { wh };
+        // Microfacets facing away from either direction can't connect them.
+        if wh.dot(*wo) <= 0.0 || wh.dot(*wi) * wi.z <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let fresnel = fresnel_dielectric(wo.dot(wh), eta);
+        let d = distribution.d(&wh);
+        let g = distribution.g(wo, wi);
+        let pdf_wh = distribution.pdf_wh(wo, &wh);
+        if reflect {
+            let f = fresnel * d * g / (4.0 * wo.z);
+            let pdf = fresnel * pdf_wh / (4.0 * wo.dot(wh));
+            (f, pdf)
+        } else {
+            let denominator = wi.dot(wh) + wo.dot(wh) / eta;
+            let denominator = denominator * denominator;
+            // Radiance is compressed by eta squared when it crosses into a denser medium.
+            let f = (1.0 - fresnel) * d * g * (wi.dot(wh) * wo.dot(wh)).abs()
+                / (wo.z * denominator * eta * eta);
+            let pdf = (1.0 - fresnel) * pdf_wh * wi.dot(wh).abs() / denominator;
+            (f, pdf)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    /// Samples a visible microfacet normal, then reflects or refracts around it with the
+    /// probability given by its Fresnel reflectance.
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (uvw, wo, eta, alpha) = self.local_frame(r_in, rec);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let mut rng = vec3::rng::thread_rng();
+
+        if alpha < MIN_ALPHA {
+            let normal = Vec3::new(0.0, 0.0, 1.0);
+            let fresnel = fresnel_dielectric(wo.z, eta);
+            let (wi, weight) = match microfacet::refract(&wo, &normal, eta) {
+                Some(wt) if rng.gen::<f64>() >= fresnel => (wt, 1.0 / (eta * eta)),
+                _ => (microfacet::reflect(&wo, &normal), 1.0),
+            };
+            let scattered = Ray::new(rec.p, uvw.local_vec(&wi), r_in.time);
+            return Some(ScatterRecord::new_delta(
+                scattered,
+                Color::new(weight, weight, weight),
+            ));
+        }
+
+        let distribution = Ggx::new(alpha, alpha);
+        let wh = distribution.sample_wh(&wo, rng.gen(), rng.gen());
+        let fresnel = fresnel_dielectric(wo.dot(wh), eta);
+        let (wi, reflected) = match microfacet::refract(&wo, &wh, eta) {
+            Some(wt) if rng.gen::<f64>() >= fresnel => (wt, false),
+            _ => (microfacet::reflect(&wo, &wh), true),
+        };
+        // Samples that end up on the wrong side of the macro surface are absorbed.
+        if (wi.z > 0.0) != reflected {
+            return None;
+        }
+
+        let (f, pdf) = RoughDielectric::evaluate(&distribution, &wo, &wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = f / pdf;
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, uvw.local_vec(&wi), r_in.time),
+            attenuation: Color::new(weight, weight, weight),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (uvw, wo, eta, alpha) = self.local_frame(r_in, rec);
+        if alpha < MIN_ALPHA {
+            return Color::default();
+        }
+        let wi = uvw.to_local(&direction.unit_vector());
+        let (f, _) = RoughDielectric::evaluate(&Ggx::new(alpha, alpha), &wo, &wi, eta);
+        Color::new(f, f, f)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (uvw, wo, eta, alpha) = self.local_frame(r_in, rec);
+        if alpha < MIN_ALPHA {
+            return 0.0;
+        }
+        let wi = uvw.to_local(&direction.unit_vector());
+        RoughDielectric::evaluate(&Ggx::new(alpha, alpha), &wo, &wi, eta).1
+    }
+}
+
+/// The inputs of `Principled`. Every parameter is a texture, scalar ones use the average of
+/// its channels. All of them except `base_color` and `ior` are in [0, 1].
+pub struct PrincipledParameters {
+    pub base_color: TexturePtr,
+    pub metallic: TexturePtr,
+    pub roughness: TexturePtr,
+    /// Reflectance of the dielectric base at normal incidence, scaled so 0.5 is 4%.
+    pub specular: TexturePtr,
+    /// Tints the dielectric specular reflection towards the base color.
+    pub specular_tint: TexturePtr,
+    /// Extra grazing reflection for cloth.
+    pub sheen: TexturePtr,
+    pub sheen_tint: TexturePtr,
+    /// Strength of a second, white and glossy specular layer.
+    pub clearcoat: TexturePtr,
+    pub clearcoat_gloss: TexturePtr,
+    /// Blends the diffuse lobe towards a flattened approximation of subsurface scattering.
+    pub subsurface: TexturePtr,
+    /// How much of the dielectric base refracts instead of scattering diffusely.
+    pub transmission: TexturePtr,
+    /// Index of refraction used for transmission.
+    pub ior: TexturePtr,
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> Self {
+        let constant =
+            |value: f64| -> TexturePtr { Arc::new(SolidColor::new_from_rgb(value, value, value)) };
+        PrincipledParameters {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            subsurface: constant(0.0),
+            transmission: constant(0.0),
+            ior: constant(1.5),
+        }
+    }
+}
+
+/// Disney's principled BSDF: a diffuse base with sheen and a subsurface approximation, a
+/// GGX specular lobe that blends from dielectric to metal, rough transmission and a
+/// clearcoat layer. Scatter picks one lobe and weights the sample by the whole BSDF over
+/// the combined density, so every lobe can take part in light sampling.
+///
+/// The diffuse base is seen through the specular lobe and the specular lobe through the
+/// clearcoat, dimmed by their Schlick reflectance in both directions, so a white material
+/// doesn't reflect more light than it receives.
+///
+/// Rays leaving a transmissive object only see its rough dielectric interface.
+pub struct Principled {
+    parameters: PrincipledParameters,
+}
+
+/// The parameters of a `Principled` material at one hit, with the lobe weights derived
+/// from them.
+struct PrincipledLobes {
+    base_color: Color,
+    roughness: f64,
+    specular_color: Color,
+    sheen_color: Color,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    subsurface: f64,
+    eta: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+    distribution: Ggx,
+}
+
+impl Principled {
+    pub fn new(parameters: PrincipledParameters) -> Principled {
+        Principled { parameters }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let p = &self.parameters;
+        let scalar = |texture: &TexturePtr| num::clamp(texture_scalar(texture, rec), 0.0, 1.0);
+
+        let base_color = p.base_color.value_at(rec);
+        let metallic = scalar(&p.metallic);
+        let roughness = scalar(&p.roughness);
+        let transmission = scalar(&p.transmission);
+        let ior = texture_scalar(&p.ior, rec).max(1.0);
+
+        let luminance = luminance(&base_color);
+        let tint = if luminance > 0.0 {
+            base_color / luminance
+        } else {
+            Color::new(1.0, 1.0, 1.0)
+        };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let dielectric_specular =
+            lerp(white, tint, scalar(&p.specular_tint)) * (scalar(&p.specular) * 0.08);
+        let alpha = Ggx::roughness_to_alpha(roughness);
+
+        let mut diffuse_weight = (1.0 - metallic) * (1.0 - transmission);
+        let mut transmission_weight = (1.0 - metallic) * transmission;
+        let mut specular_weight = 1.0 - transmission_weight;
+        let mut clearcoat = scalar(&p.clearcoat);
+        if !rec.front_face && transmission_weight > 0.0 {
+            diffuse_weight = 0.0;
+            specular_weight = 0.0;
+            transmission_weight = 1.0;
+            clearcoat = 0.0;
+        }
+
+        PrincipledLobes {
+            base_color,
+            roughness,
+            specular_color: lerp(dielectric_specular, base_color, metallic),
+            sheen_color: lerp(white, tint, scalar(&p.sheen_tint)) * scalar(&p.sheen),
+            clearcoat,
+            clearcoat_gloss: scalar(&p.clearcoat_gloss),
+            subsurface: scalar(&p.subsurface),
+            eta: if rec.front_face { ior } else { 1.0 / ior },
+            diffuse_weight,
+            specular_weight,
+            transmission_weight,
+            distribution: Ggx::new(alpha, alpha),
+        }
+    }
+}
+
+impl PrincipledLobes {
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 + (0.001 - 0.1) * self.clearcoat_gloss
+    }
+
+    /// The fraction of light passing through the clearcoat at `cosine` to the normal.
+    fn coat_transmittance(&self, cosine: f64) -> f64 {
+        1.0 - 0.25 * self.clearcoat * (0.04 + 0.96 * schlick_weight(cosine))
+    }
+
+    /// The fraction of light passing through the specular layer to the diffuse base at
+    /// `cosine` to the normal.
+    fn specular_transmittance(&self, cosine: f64) -> f64 {
+        let f0 = luminance(&self.specular_color);
+        1.0 - self.specular_weight * (f0 + (1.0 - f0) * schlick_weight(cosine))
+    }
+
+    /// Probabilities of sampling the diffuse, specular, transmission and clearcoat lobes.
+    fn sampling_weights(&self) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight * luminance(&self.base_color).max(0.05),
+            self.specular_weight,
+            self.transmission_weight,
+            0.25 * self.clearcoat,
+        ];
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return [0.0; 4];
+        }
+        [
+            weights[0] / total,
+            weights[1] / total,
+            weights[2] / total,
+            weights[3] / total,
+        ]
+    }
+
+    /// The BSDF times the cosine of `wi` and the density of `Principled::scatter` sampling
+    /// `wi`, both in the local shading frame.
+    fn evaluate(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return (Color::default(), 0.0);
+        }
+        let probabilities = self.sampling_weights();
+        let mut f = Color::default();
+        let mut pdf = 0.0;
+
+        if wi.z > 0.0 {
+            let wh = (*wo + *wi).unit_vector();
+            let cos_d = wi.dot(wh);
+            let fh = schlick_weight(cos_d);
+            // Light reaching a layer has passed through the ones above it on the way in and
+            // out, which keeps the layers from reflecting more than they receive.
+            let coat_transmittance = self.coat_transmittance(wo.z) * self.coat_transmittance(wi.z);
+            let base_transmittance = coat_transmittance
+                * self.specular_transmittance(wo.z)
+                * self.specular_transmittance(wi.z);
+
+            if self.diffuse_weight > 0.0 {
+                // Burley's diffuse with retro-reflection, blended with Hanrahan-Krueger.
+                let fl = schlick_weight(wi.z);
+                let fv = schlick_weight(wo.z);
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+                let fss90 = self.roughness * cos_d * cos_d;
+                let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+                let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+                let diffuse = self.base_color * ((fd + (ss - fd) * self.subsurface) / PI);
+                let sheen = self.sheen_color * fh;
+                f += (diffuse + sheen) * (self.diffuse_weight * base_transmittance * wi.z);
+                pdf += probabilities[0] * wi.z / PI;
+            }
+
+            if self.specular_weight > 0.0 {
+                let fresnel = lerp(self.specular_color, Color::new(1.0, 1.0, 1.0), fh);
+                let d = self.distribution.d(&wh);
+                let g = self.distribution.g(wo, wi);
+                f += fresnel * (self.specular_weight * coat_transmittance * d * g / (4.0 * wo.z));
+                pdf += probabilities[1] * self.distribution.pdf_wh(wo, &wh) / (4.0 * wo.dot(wh));
+            }
+
+            if self.clearcoat > 0.0 {
+                let alpha = self.clearcoat_alpha();
+                let d = gtr1(wh.z, alpha);
+                let fresnel = 0.04 + 0.96 * fh;
+                let coat = Ggx::new(0.25, 0.25);
+                let g = coat.g1(wo) * coat.g1(wi);
+                f += Color::new(1.0, 1.0, 1.0)
+                    * (0.25 * self.clearcoat * d * fresnel * g / (4.0 * wo.z));
+                pdf += probabilities[3] * d * wh.z / (4.0 * wo.dot(wh));
+            }
+        }
+
+        if self.transmission_weight > 0.0 {
+            let (ft, pdf_t) = RoughDielectric::evaluate(&self.distribution, wo, wi, self.eta);
+            let tint = if wi.z < 0.0 {
+                self.base_color
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            f += tint * (self.transmission_weight * ft);
+            pdf += probabilities[2] * pdf_t;
+        }
+
+        (f, pdf)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-r_in.direction.unit_vector());
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let lobes = self.lobes(rec);
+        let probabilities = lobes.sampling_weights();
+
+        let mut rng = vec3::rng::thread_rng();
+        let mut choice = rng.gen::<f64>();
+        let lobe = probabilities
+            .iter()
+            .position(|&p| {
+                choice -= p;
+                choice < 0.0
+            })
+            .unwrap_or(3);
+
+        let (wi, reflected) = match lobe {
+            0 => (Vec3::random_cosine_direction(), true),
+            1 => {
+                let wh = lobes.distribution.sample_wh(&wo, rng.gen(), rng.gen());
+                (microfacet::reflect(&wo, &wh), true)
+            }
+            2 => {
+                let wh = lobes.distribution.sample_wh(&wo, rng.gen(), rng.gen());
+                let fresnel = fresnel_dielectric(wo.dot(wh), lobes.eta);
+                match microfacet::refract(&wo, &wh, lobes.eta) {
+                    Some(wt) if rng.gen::<f64>() >= fresnel => (wt, false),
+                    _ => (microfacet::reflect(&wo, &wh), true),
+                }
+            }
+            _ => {
+                let wh = sample_gtr1(lobes.clearcoat_alpha(), rng.gen(), rng.gen());
+                (microfacet::reflect(&wo, &wh), true)
+            }
+        };
+        // Samples that end up on the wrong side of the macro surface are absorbed.
+        if (wi.z > 0.0) != reflected {
+            return None;
+        }
+
+        let (f, pdf) = lobes.evaluate(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, uvw.local_vec(&wi), r_in.time),
+            attenuation: f / pdf,
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-r_in.direction.unit_vector());
+        let wi = uvw.to_local(&direction.unit_vector());
+        self.lobes(rec).evaluate(&wo, &wi).0
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let uvw = Onb::new_from_w(&rec.normal);
+        let wo = uvw.to_local(&-r_in.direction.unit_vector());
+        let wi = uvw.to_local(&direction.unit_vector());
+        self.lobes(rec).evaluate(&wo, &wi).1
+    }
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+fn luminance(color: &Color) -> f64 {
+    0.3 * color.x + 0.6 * color.y + 0.1 * color.z
+}
+
+/// Schlick's `(1 - cos)^5` Fresnel weight.
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - num::clamp(cosine, 0.0, 1.0)).powi(5)
+}
+
+/// The GTR1 (Berry) distribution of the clearcoat layer, for a half vector at `cos_h` to
+/// the normal.
+fn gtr1(cos_h: f64, alpha: f64) -> f64 {
+    if cos_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let t = 1.0 + (a2 - 1.0) * cos_h * cos_h;
+    (a2 - 1.0) / (PI * a2.ln() * t)
+}
+
+/// Samples a half vector from `gtr1` times its cosine.
+fn sample_gtr1(alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_h = ((1.0 - a2.powf(1.0 - u1)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Vec3::new(sin_h * phi.cos(), sin_h * phi.sin(), cos_h)
+}
+
+/// Reads a scalar parameter from a texture as the average of its channels.
+fn texture_scalar(texture: &TexturePtr, rec: &HitRecord) -> f64 {
+    let value = texture.value_at(rec);
+    (value.x + value.y + value.z) / 3.0
+}
+
 pub struct DiffuseLight {
     emit: TexturePtr,
 }
@@ -154,13 +939,17 @@ impl DiffuseLight {
 }
 
 impl Material for DiffuseLight {
-    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
         None
     }
 
     fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
         self.emit.value(u, v, p)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
 
 pub struct Isotropic {
@@ -180,10 +969,343 @@ impl Isotropic {
 }
 
 impl Material for Isotropic {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
-        Some((
-            Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time),
-            self.albedo.value(rec.u, rec.v, &rec.p),
-        ))
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let direction = Vec3::random_unit_vector();
+        Some(ScatterRecord {
+            pdf: self.pdf(r_in, rec, &direction),
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value_at(rec),
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value_at(rec) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Scattering events inside a medium, distributed by a phase function around the direction
+/// the light was travelling in.
+pub struct Volumetric {
+    albedo: TexturePtr,
+    phase_function: PhaseFunctionPtr,
+}
+
+impl Volumetric {
+    pub fn new(albedo: TexturePtr, phase_function: PhaseFunctionPtr) -> Self {
+        Volumetric {
+            albedo,
+            phase_function,
+        }
+    }
+}
+
+impl Material for Volumetric {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let mut rng = vec3::rng::thread_rng();
+        let (direction, pdf) = self.phase_function.sample(
+            &r_in.direction.unit_vector(),
+            rng.gen(),
+            rng.gen(),
+            rng.gen(),
+        );
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time),
+            attenuation: self.albedo.value_at(rec),
+            pdf,
+            is_delta: false,
+        })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.albedo.value_at(rec) * self.pdf(r_in, rec, direction)
+    }
+
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cos_theta = r_in.direction.unit_vector().dot(direction.unit_vector());
+        self.phase_function.p(cos_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Samples taken from `scatter`, and the square root of the number of directions the
+    /// sphere is divided into for integrating `pdf` and `eval`.
+    const SAMPLES: usize = 100_000;
+    const STRATA: usize = 300;
+
+    /// A hit at the origin on a surface facing +z, from outside or inside the object.
+    fn hit(front_face: bool) -> HitRecord {
+        let material: MaterialPtr = Arc::new(Lambertian::default());
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        HitRecord::new(
+            Point3::default(),
+            normal,
+            1.0,
+            0.5,
+            0.5,
+            front_face,
+            material,
+        )
+    }
+
+    /// A ray arriving at the origin at `theta` to the normal.
+    fn incoming(theta: f64) -> Ray {
+        let origin = Point3::new(theta.sin(), 0.0, theta.cos());
+        Ray::new(origin, -origin, 0.0)
+    }
+
+    /// Monte Carlo estimates of how a material scatters light arriving along `r_in`.
+    struct Estimates {
+        /// The integral of `pdf` over the sphere.
+        pdf_integral: f64,
+        /// The integral of `eval` over the sphere: the albedo computed from the BSDF.
+        eval_integral: Color,
+        /// The fraction of `scatter` calls returning a sample.
+        accepted: f64,
+        /// The mean weight of `scatter`, counting absorbed samples as zero.
+        albedo: Color,
+    }
+
+    /// Estimates the integrals with uniformly distributed directions, one in each of
+    /// equally sized cells of the sphere, and checks every sample of `scatter` against
+    /// `eval` and `pdf` on the way.
+    fn estimate(material: &dyn Material, r_in: &Ray, rec: &HitRecord) -> Estimates {
+        let mut rng = vec3::rng::thread_rng();
+        let mut pdf_sum = 0.0;
+        let mut eval_sum = Color::default();
+        for i in 0..STRATA {
+            for j in 0..STRATA {
+                let z = 1.0 - 2.0 * (i as f64 + rng.gen::<f64>()) / STRATA as f64;
+                let phi = 2.0 * PI * (j as f64 + rng.gen::<f64>()) / STRATA as f64;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                // The poles, where the cells are thin, point away from the lobes.
+                let direction = Vec3::new(r * phi.cos(), z, r * phi.sin());
+                pdf_sum += material.pdf(r_in, rec, &direction);
+                eval_sum += material.eval(r_in, rec, &direction);
+            }
+        }
+
+        let mut accepted = 0;
+        let mut albedo = Color::default();
+        for _ in 0..SAMPLES {
+            let scattered = match material.scatter(r_in, rec) {
+                Some(scattered) => scattered,
+                None => continue,
+            };
+            assert!(!scattered.is_delta);
+            let direction = scattered.ray.direction;
+            let pdf = material.pdf(r_in, rec, &direction);
+            assert!(pdf > 0.0);
+            assert!((scattered.pdf - pdf).abs() <= 1e-9 * pdf);
+            let expected = material.eval(r_in, rec, &direction) / pdf;
+            assert!(
+                (scattered.attenuation - expected).length() <= 1e-9 * (1.0 + expected.length()),
+                "{:?} != {:?}",
+                scattered.attenuation,
+                expected
+            );
+            accepted += 1;
+            albedo += scattered.attenuation;
+        }
+
+        let solid_angle = 4.0 * PI / (STRATA * STRATA) as f64;
+        Estimates {
+            pdf_integral: pdf_sum * solid_angle,
+            eval_integral: eval_sum * solid_angle,
+            accepted: accepted as f64 / SAMPLES as f64,
+            albedo: albedo / SAMPLES as f64,
+        }
+    }
+
+    /// Checks that `scatter` samples directions with the density `pdf` reports, that its
+    /// weights agree with `eval`, and that no energy is created.
+    fn check_material(material: &dyn Material, r_in: &Ray, rec: &HitRecord, tolerance: f64) {
+        let estimates = estimate(material, r_in, rec);
+        assert!(
+            (estimates.pdf_integral - estimates.accepted).abs() < tolerance,
+            "pdf integrates to {}, {} of the samples were kept",
+            estimates.pdf_integral,
+            estimates.accepted
+        );
+        assert!(estimates.pdf_integral < 1.0 + tolerance);
+        assert!(
+            (estimates.eval_integral - estimates.albedo).length() < tolerance,
+            "{:?} != {:?}",
+            estimates.eval_integral,
+            estimates.albedo
+        );
+        for &channel in [estimates.albedo.x, estimates.albedo.y, estimates.albedo.z].iter() {
+            assert!(channel <= 1.0 + tolerance, "albedo {:?}", estimates.albedo);
+        }
+    }
+
+    #[test]
+    fn lambertian_keeps_the_contract() {
+        vec3::rng::seed(1);
+        let material = Lambertian::new_from_color(&Color::new(1.0, 0.5, 0.25));
+        let estimates = estimate(&material, &incoming(0.7), &hit(true));
+        assert!((estimates.pdf_integral - 1.0).abs() < 0.01);
+        assert_eq!(estimates.accepted, 1.0);
+        assert!((estimates.albedo - Color::new(1.0, 0.5, 0.25)).length() < 1e-9);
+        assert!((estimates.eval_integral - estimates.albedo).length() < 0.01);
+    }
+
+    #[test]
+    fn fuzzy_metal_keeps_the_contract() {
+        vec3::rng::seed(2);
+        let material = Metal::new(Color::new(1.0, 1.0, 1.0), 0.8);
+        for &theta in [0.0, 0.8, 1.3].iter() {
+            check_material(&material, &incoming(theta), &hit(true), 0.02);
+        }
+    }
+
+    #[test]
+    fn rough_conductors_keep_the_contract() {
+        vec3::rng::seed(4);
+        let silver = Conductor::new_from_preset(ConductorPreset::Silver, 0.6);
+        let brushed = Conductor::new_anisotropic(
+            Color::new(0.155, 0.117, 0.138),
+            Color::new(4.828, 3.122, 2.147),
+            0.4,
+            0.8,
+        );
+        let mut tangent_hit = hit(true);
+        tangent_hit.tangent = Some(Vec3::new(1.0, 1.0, 0.5));
+        for &theta in [0.1, 0.9, 1.4].iter() {
+            check_material(&silver, &incoming(theta), &hit(true), 0.03);
+            check_material(&brushed, &incoming(theta), &tangent_hit, 0.03);
+        }
+        // Light coming from below the surface isn't reflected.
+        assert!(silver.scatter(&incoming(2.0), &hit(true)).is_none());
+    }
+
+    #[test]
+    fn smooth_conductors_reflect_with_their_fresnel_color() {
+        vec3::rng::seed(5);
+        let gold = Conductor::new_from_preset(ConductorPreset::Gold, 0.0);
+        let scattered = gold.scatter(&incoming(0.0), &hit(true)).unwrap();
+        assert!(scattered.is_delta);
+        let (eta, k) = ConductorPreset::Gold.ior();
+        let expected = Color::new(
+            fresnel_conductor(1.0, eta.x, k.x),
+            fresnel_conductor(1.0, eta.y, k.y),
+            fresnel_conductor(1.0, eta.z, k.z),
+        );
+        assert!((scattered.attenuation - expected).length() < 1e-9);
+        // Gold reflects red more than blue.
+        assert!(expected.x > expected.z);
+    }
+
+    #[test]
+    fn rough_dielectrics_keep_the_contract() {
+        vec3::rng::seed(6);
+        let glass = RoughDielectric::new(1.5, 0.5);
+        for &theta in [0.0, 0.8, 1.4].iter() {
+            check_material(&glass, &incoming(theta), &hit(true), 0.03);
+
+            // Leaving the glass, radiance is spread over a wider cone of refracted directions
+            // and the albedo may exceed one, so only the sampling is checked.
+            let estimates = estimate(&glass, &incoming(theta), &hit(false));
+            assert!(
+                (estimates.pdf_integral - estimates.accepted).abs() < 0.03,
+                "{} {}",
+                estimates.pdf_integral,
+                estimates.accepted
+            );
+            let ratio = estimates.eval_integral.x / estimates.albedo.x;
+            assert!((ratio - 1.0).abs() < 0.03, "{:?}", estimates.albedo);
+        }
+    }
+
+    #[test]
+    fn rough_dielectrics_reflect_and_refract() {
+        vec3::rng::seed(7);
+        let glass = RoughDielectric::new(1.5, 0.3);
+        let (r_in, rec) = (incoming(0.3), hit(true));
+        let (mut reflected, mut refracted) = (0, 0);
+        for _ in 0..10_000 {
+            if let Some(scattered) = glass.scatter(&r_in, &rec) {
+                if scattered.ray.direction.z > 0.0 {
+                    reflected += 1;
+                } else {
+                    refracted += 1;
+                }
+            }
+        }
+        // About 4% of the light is reflected near normal incidence.
+        let fraction = reflected as f64 / (reflected + refracted) as f64;
+        assert!(0.02 < fraction && fraction < 0.08, "{}", fraction);
+
+        // Without roughness the interface is a delta lobe.
+        let smooth = RoughDielectric::new(1.5, 0.0);
+        assert!(smooth.scatter(&r_in, &rec).unwrap().is_delta);
+    }
+
+    fn constant(value: f64) -> TexturePtr {
+        Arc::new(SolidColor::new_from_rgb(value, value, value))
+    }
+
+    #[test]
+    fn principled_keeps_the_contract() {
+        vec3::rng::seed(8);
+        let materials = [
+            Principled::new(PrincipledParameters::default()),
+            Principled::new(PrincipledParameters {
+                metallic: constant(1.0),
+                roughness: constant(0.6),
+                ..Default::default()
+            }),
+            Principled::new(PrincipledParameters {
+                base_color: constant(1.0),
+                roughness: constant(0.8),
+                subsurface: constant(1.0),
+                sheen: constant(1.0),
+                clearcoat: constant(1.0),
+                clearcoat_gloss: constant(0.5),
+                ..Default::default()
+            }),
+            Principled::new(PrincipledParameters {
+                metallic: constant(0.3),
+                transmission: constant(0.8),
+                ..Default::default()
+            }),
+        ];
+        for material in materials.iter() {
+            for &theta in [0.2, 1.0, 1.4].iter() {
+                check_material(material, &incoming(theta), &hit(true), 0.03);
+            }
+        }
+
+        // Inside a transmissive object only the rough interface is left.
+        let glass = Principled::new(PrincipledParameters {
+            base_color: constant(1.0),
+            transmission: constant(1.0),
+            ..Default::default()
+        });
+        let estimates = estimate(&glass, &incoming(0.5), &hit(false));
+        assert!((estimates.pdf_integral - estimates.accepted).abs() < 0.03);
+        let ratio = estimates.eval_integral.x / estimates.albedo.x;
+        assert!((ratio - 1.0).abs() < 0.03, "{:?}", estimates.albedo);
+    }
+
+    #[test]
+    fn delta_lobes_have_no_density() {
+        vec3::rng::seed(3);
+        let mirror = Metal::new(Color::new(0.9, 0.9, 0.9), 0.0);
+        let r_in = incoming(0.5);
+        let rec = hit(true);
+        let scattered = mirror.scatter(&r_in, &rec).unwrap();
+        assert!(scattered.is_delta);
+        let direction = scattered.ray.direction;
+        assert!((direction - Vec3::new(-(0.5f64).sin(), 0.0, (0.5f64).cos())).length() < 1e-9);
+        assert_eq!(mirror.pdf(&r_in, &rec, &direction), 0.0);
+        assert!(mirror.eval(&r_in, &rec, &direction).length() == 0.0);
     }
 }
@@ -0,0 +1,149 @@
+//! The GGX (Trowbridge-Reitz) microfacet distribution shared by the rough materials.
+//! Directions are in a local shading frame where the normal is +z.
+
+use std::f64::consts::PI;
+use vec3::Vec3;
+
+/// Below this alpha a surface is treated as perfectly smooth.
+pub(crate) const MIN_ALPHA: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    pub(crate) fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Ggx {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    /// Maps the perceptually linear roughness in [0, 1] to alpha.
+    pub(crate) fn roughness_to_alpha(roughness: f64) -> f64 {
+        let roughness = num::clamp(roughness, 0.0, 1.0);
+        roughness * roughness
+    }
+
+    /// Density of microfacet normals, per unit of projected area.
+    pub(crate) fn d(&self, wh: &Vec3) -> f64 {
+        if wh.z <= 0.0 {
+            return 0.0;
+        }
+        let x = wh.x / self.alpha_x;
+        let y = wh.y / self.alpha_y;
+        let denominator = x * x + y * y + wh.z * wh.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible microfacet area.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let x = w.x * self.alpha_x;
+        let y = w.y * self.alpha_y;
+        let alpha2_tan2 = (x * x + y * y) / (w.z * w.z);
+        (-1.0 + (1.0 + alpha2_tan2).sqrt()) / 2.0
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub(crate) fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both directions, with correlated heights.
+    pub(crate) fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal visible from `wo` (Heitz 2018), `wo.z` must be positive.
+    pub(crate) fn sample_wh(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch the view direction so the distribution becomes a hemisphere.
+        let vh = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+
+        let length_squared = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length_squared > 0.0 {
+            Vec3::new(-vh.y, vh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        // Sample the projected area of the visible half of the hemisphere.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let p2 = r * phi.sin();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * p2;
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        // Unstretch back to the ellipsoid.
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)).unit_vector()
+    }
+
+    /// Density of `sample_wh` returning `wh`.
+    pub(crate) fn pdf_wh(&self, wo: &Vec3, wh: &Vec3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(*wh).max(0.0) * self.d(wh) / wo.z
+    }
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction `eta + i k`.
+pub(crate) fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i = num::clamp(cos_i, 0.0, 1.0);
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Mirror `w` around `n`, both pointing away from the surface.
+pub(crate) fn reflect(w: &Vec3, n: &Vec3) -> Vec3 {
+    *n * 2.0 * w.dot(*n) - *w
+}
+
+/// Fresnel reflectance of a dielectric interface, where `eta` is the index of refraction
+/// on the far side over the one on the side of the incident direction.
+pub(crate) fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = num::clamp(cos_i, 0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection.
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Refracts `w` through a surface with normal `n` on the same side, with `eta` as in
+/// `fresnel_dielectric`. Returns None on total internal reflection.
+pub(crate) fn refract(w: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = w.dot(*n);
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*w / eta + *n * (cos_i / eta - cos_t))
+}
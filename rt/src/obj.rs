@@ -0,0 +1,625 @@
+//! Loader for Wavefront OBJ files and their MTL material libraries.
+//!
+//! Every group (`g`) or object (`o`) in the file becomes one `TriangleMesh`. Polygons are
+//! triangulated as a fan and `usemtl` selects a per-face material from the mesh's table.
+
+use crate::hit::HittableList;
+use crate::material::{Dielectric, DiffuseLight, Lambertian, MaterialPtr, Metal};
+use crate::objects::TriangleMesh;
+use crate::texture::ImageTexture;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vec3::{Color, Point3, Vec3};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io(_, err) => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// A named group of faces from an OBJ file.
+pub struct ObjGroup {
+    pub name: String,
+    pub mesh: Arc<TriangleMesh>,
+}
+
+/// Loads an OBJ file. Faces without a `usemtl` statement get `default_material`.
+pub fn load_obj(path: &Path, default_material: MaterialPtr) -> Result<Vec<ObjGroup>, ObjError> {
+    let file = File::open(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse_obj(BufReader::new(file), path, default_material)
+}
+
+/// Loads an OBJ file and adds all of its groups to a list.
+pub fn load_obj_into_list(
+    path: &Path,
+    default_material: MaterialPtr,
+) -> Result<HittableList, ObjError> {
+    let mut list = HittableList::default();
+    for group in load_obj(path, default_material)? {
+        list.add(group.mesh);
+    }
+    Ok(list)
+}
+
+/// Parses OBJ data. `path` is used for error messages and to resolve `mtllib` statements.
+pub fn parse_obj<R: BufRead>(
+    reader: R,
+    path: &Path,
+    default_material: MaterialPtr,
+) -> Result<Vec<ObjGroup>, ObjError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut library: HashMap<String, MaterialPtr> = HashMap::new();
+
+    let mut groups = Vec::new();
+    let mut current = GroupBuilder::new("default".to_string(), &default_material);
+    // Materials stay active across group statements.
+    let mut current_material: Option<(String, MaterialPtr)> = None;
+    let mut current_material_index = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = parse_floats(&args, 3, "v").map_err(error)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = parse_floats(&args, 3, "vn").map_err(error)?;
+                normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = parse_floats(&args, 1, "vt").map_err(error)?;
+                uvs.push((v[0], v.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        args.len()
+                    )));
+                }
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    let corner = parse_face_vertex(arg, positions.len(), uvs.len(), normals.len())
+                        .map_err(error)?;
+                    corners.push(current.vertex(corner, &positions, &uvs, &normals));
+                }
+                // Triangulate the polygon as a fan around its first vertex.
+                for i in 1..corners.len() - 1 {
+                    current
+                        .indices
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                    current.face_materials.push(current_material_index);
+                }
+            }
+            "g" | "o" => {
+                let name = if args.is_empty() {
+                    "default".to_string()
+                } else {
+                    args.join(" ")
+                };
+                let previous =
+                    std::mem::replace(&mut current, GroupBuilder::new(name, &default_material));
+                groups.extend(previous.build());
+                current_material_index = match &current_material {
+                    Some((name, material)) => current.material_index(name, material),
+                    None => 0,
+                };
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let material = library
+                    .get(&name)
+                    .ok_or_else(|| error(format!("unknown material '{}'", name)))?;
+                current_material_index = current.material_index(&name, material);
+                current_material = Some((name, Arc::clone(material)));
+            }
+            "mtllib" => {
+                for file in args.iter() {
+                    let mtl_path = base_dir.join(file);
+                    library.extend(load_mtl(&mtl_path)?);
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry don't affect the mesh.
+            _ => {}
+        }
+    }
+
+    groups.extend(current.build());
+    Ok(groups)
+}
+
+/// Loads an MTL material library, mapping every material to the closest existing `Material`.
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MaterialPtr>, ObjError> {
+    let file = File::open(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    parse_mtl(BufReader::new(file), path)
+}
+
+/// Parses MTL data. `path` is used for error messages and to resolve texture maps.
+pub fn parse_mtl<R: BufRead>(
+    reader: R,
+    path: &Path,
+) -> Result<HashMap<String, MaterialPtr>, ObjError> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    let mut current: Option<MtlMaterial> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) if !k.starts_with('#') => k,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some(m) = current.take() {
+                let (name, material) = m.build(path)?;
+                materials.insert(name, material);
+            }
+            current = Some(MtlMaterial::new(args.join(" "), line_number));
+            continue;
+        }
+
+        let m = current
+            .as_mut()
+            .ok_or_else(|| error(format!("'{}' before any newmtl", keyword)))?;
+        match keyword {
+            "Kd" => m.kd = parse_color(&args, keyword).map_err(error)?,
+            "Ks" => m.ks = parse_color(&args, keyword).map_err(error)?,
+            "Ke" => m.ke = parse_color(&args, keyword).map_err(error)?,
+            "Ni" => m.ni = parse_floats(&args, 1, keyword).map_err(error)?[0],
+            "Ns" => m.ns = parse_floats(&args, 1, keyword).map_err(error)?[0],
+            "d" => m.dissolve = parse_floats(&args, 1, keyword).map_err(error)?[0],
+            "Tr" => m.dissolve = 1.0 - parse_floats(&args, 1, keyword).map_err(error)?[0],
+            "illum" => {
+                m.illum = args
+                    .first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| error("illum needs an integer".to_string()))?;
+            }
+            "map_Kd" => {
+                // Texture options come before the file name, which is always last.
+                let file = args
+                    .last()
+                    .ok_or_else(|| error("map_Kd needs a file name".to_string()))?;
+                m.map_kd = Some(base_dir.join(file));
+            }
+            // Ambient color, transmission filter and the other maps have no equivalent.
+            _ => {}
+        }
+    }
+
+    if let Some(m) = current.take() {
+        let (name, material) = m.build(path)?;
+        materials.insert(name, material);
+    }
+
+    Ok(materials)
+}
+
+/// The statements of one `newmtl` block that map onto existing materials.
+struct MtlMaterial {
+    name: String,
+    line: usize,
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ni: f64,
+    ns: f64,
+    dissolve: f64,
+    illum: u32,
+    map_kd: Option<PathBuf>,
+}
+
+impl MtlMaterial {
+    fn new(name: String, line: usize) -> Self {
+        MtlMaterial {
+            name,
+            line,
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::default(),
+            ke: Color::default(),
+            ni: 1.0,
+            ns: 0.0,
+            dissolve: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    /// Emissive materials become lights, transparent or refracting ones glass, mirror
+    /// illumination models metal and everything else a diffuse surface.
+    fn build(self, path: &Path) -> Result<(String, MaterialPtr), ObjError> {
+        let is_black = |c: &Color| c.x <= 0.0 && c.y <= 0.0 && c.z <= 0.0;
+
+        let material: MaterialPtr = if !is_black(&self.ke) {
+            Arc::new(DiffuseLight::new_from_color(self.ke))
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectric::new(self.ni))
+        } else if matches!(self.illum, 3 | 5 | 8) || (is_black(&self.kd) && !is_black(&self.ks)) {
+            // Ns is the Phong exponent in [0, 1000], a high exponent means a sharp reflection.
+            let fuzz = 1.0 - num::clamp(self.ns / 1000.0, 0.0, 1.0);
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else if let Some(map) = &self.map_kd {
+            let texture =
+                ImageTexture::try_new_from_filename(map).map_err(|e| ObjError::Parse {
+                    path: path.to_path_buf(),
+                    line: self.line,
+                    message: format!("can't load map_Kd '{}': {}", map.display(), e),
+                })?;
+            Arc::new(Lambertian::new_from_texture(Arc::new(texture)))
+        } else {
+            Arc::new(Lambertian::new_from_color(&self.kd))
+        };
+
+        Ok((self.name, material))
+    }
+}
+
+/// One corner of a face: zero based indices into the position, uv and normal lists.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Collects the faces of one group, remapping the file's separate attribute indices
+/// to the single index buffer a `TriangleMesh` uses.
+struct GroupBuilder {
+    name: String,
+    vertex_map: HashMap<FaceVertex, usize>,
+    positions: Vec<Point3>,
+    uvs: Vec<Option<(f64, f64)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[usize; 3]>,
+    materials: Vec<MaterialPtr>,
+    material_names: HashMap<String, usize>,
+    face_materials: Vec<usize>,
+}
+
+impl GroupBuilder {
+    fn new(name: String, default_material: &MaterialPtr) -> Self {
+        GroupBuilder {
+            name,
+            vertex_map: HashMap::new(),
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+            materials: vec![Arc::clone(default_material)],
+            material_names: HashMap::new(),
+            face_materials: Vec::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: FaceVertex,
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(&index) = self.vertex_map.get(&corner) {
+            return index;
+        }
+        let index = self.positions.len();
+        self.positions.push(positions[corner.position]);
+        self.uvs.push(corner.uv.map(|i| uvs[i]));
+        self.normals.push(corner.normal.map(|i| normals[i]));
+        self.vertex_map.insert(corner, index);
+        index
+    }
+
+    fn material_index(&mut self, name: &str, material: &MaterialPtr) -> usize {
+        if let Some(&index) = self.material_names.get(name) {
+            return index;
+        }
+        let index = self.materials.len();
+        self.materials.push(Arc::clone(material));
+        self.material_names.insert(name.to_string(), index);
+        index
+    }
+
+    fn build(self) -> Option<ObjGroup> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        // A mesh has normals for every vertex or none at all, so a group where only some
+        // faces have normals is flat shaded. Missing uvs default to the texture's corner.
+        let normals = if self.normals.iter().all(|n| n.is_some()) {
+            self.normals.into_iter().flatten().collect()
+        } else {
+            Vec::new()
+        };
+        let uvs = if self.uvs.iter().any(|uv| uv.is_some()) {
+            self.uvs
+                .into_iter()
+                .map(|uv| uv.unwrap_or((0.0, 0.0)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Some(ObjGroup {
+            name: self.name,
+            mesh: Arc::new(TriangleMesh::new_with_attributes(
+                self.positions,
+                normals,
+                uvs,
+                self.indices,
+                self.materials,
+                self.face_materials,
+            )),
+        })
+    }
+}
+
+fn parse_floats(args: &[&str], min_count: usize, keyword: &str) -> Result<Vec<f64>, String> {
+    if args.len() < min_count {
+        return Err(format!(
+            "'{}' needs at least {} numbers, found {}",
+            keyword,
+            min_count,
+            args.len()
+        ));
+    }
+    args.iter()
+        .map(|a| {
+            a.parse::<f64>()
+                .map_err(|_| format!("invalid number '{}' in '{}'", a, keyword))
+        })
+        .collect()
+}
+
+fn parse_color(args: &[&str], keyword: &str) -> Result<Color, String> {
+    // A single value is a grey color.
+    let v = parse_floats(args, 1, keyword)?;
+    match v.len() {
+        1 | 2 => Ok(Color::new(v[0], v[0], v[0])),
+        _ => Ok(Color::new(v[0], v[1], v[2])),
+    }
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, resolving negative indices relative to the
+/// number of elements read so far.
+fn parse_face_vertex(
+    arg: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    let mut parts = arg.split('/');
+    let position = parse_index(parts.next(), position_count, "vertex", arg)?
+        .ok_or_else(|| format!("face vertex '{}' has no position", arg))?;
+    let uv = parse_index(parts.next(), uv_count, "texture coordinate", arg)?;
+    let normal = parse_index(parts.next(), normal_count, "normal", arg)?;
+    if parts.next().is_some() {
+        return Err(format!("face vertex '{}' has too many components", arg));
+    }
+    Ok(FaceVertex {
+        position,
+        uv,
+        normal,
+    })
+}
+
+fn parse_index(
+    part: Option<&str>,
+    count: usize,
+    kind: &str,
+    arg: &str,
+) -> Result<Option<usize>, String> {
+    let part = match part {
+        Some(p) if !p.is_empty() => p,
+        _ => return Ok(None),
+    };
+    let index: i64 = part
+        .parse()
+        .map_err(|_| format!("invalid {} index in face vertex '{}'", kind, arg))?;
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} out of range in face vertex '{}'",
+            kind, index, arg
+        ));
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::Hittable;
+    use crate::ray::Ray;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn gray() -> MaterialPtr {
+        Arc::new(Lambertian::new_from_color(&Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn parse(data: &str) -> Result<Vec<ObjGroup>, ObjError> {
+        parse_obj(Cursor::new(data), Path::new("test.obj"), gray())
+    }
+
+    #[test]
+    fn splits_groups_and_shares_vertices() {
+        let groups = parse(
+            "# two groups\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             g quad\nf 1/1 2/2 3/3 4/4\n\
+             o tail\nf -4 -3 -1\n",
+        )
+        .unwrap();
+        assert_eq!(groups.len(), 2);
+        let (quad, tail) = (&groups[0], &groups[1]);
+        assert_eq!((quad.name.as_str(), tail.name.as_str()), ("quad", "tail"));
+        assert_eq!(quad.mesh.positions().len(), 4);
+        assert_eq!(quad.mesh.indices(), &[[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(tail.mesh.indices(), &[[0, 1, 2]]);
+
+        let r = Ray::new(Point3::new(0.25, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = quad.mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.u - 0.25).abs() < 1e-9 && (rec.v - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn maps_mtl_materials() {
+        let materials = parse_mtl(
+            Cursor::new(
+                "newmtl lamp\nKe 4 4 4\n\
+                 newmtl glass\nNi 1.5\nd 0.2\n\
+                 newmtl paint\nKd 0.1 0.2 0.3\nillum 2\n",
+            ),
+            Path::new("test.mtl"),
+        )
+        .unwrap();
+        assert_eq!(materials.len(), 3);
+        assert!(materials["lamp"].is_emissive());
+        assert!(!materials["paint"].is_emissive());
+    }
+
+    #[test]
+    fn usemtl_selects_materials_from_mtllib() {
+        let dir = std::env::temp_dir().join(format!("rt-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("lights.mtl"), "newmtl lamp\nKe 1 1 1\n").unwrap();
+        let path = dir.join("lit.obj");
+        fs::write(
+            &path,
+            "mtllib lights.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             g dark\nf 1 2 3\ng lit\nusemtl lamp\nf 1 2 3\n",
+        )
+        .unwrap();
+        let groups = load_obj(&path, gray()).unwrap();
+        assert!(!groups[0].mesh.is_emissive());
+        assert!(groups[1].mesh.is_emissive());
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\n";
+        let cases = [
+            ("v 0 0\n", 5, "needs at least 3 numbers"),
+            ("v 0 zero 0\n", 5, "invalid number 'zero'"),
+            ("vt\n", 5, "needs at least 1 numbers"),
+            ("f 1 2\n", 5, "at least 3 vertices"),
+            ("f 1 2 4\n", 5, "vertex index 4 out of range"),
+            ("f 1 2 0\n", 5, "vertex index 0 out of range"),
+            ("f 1 2 -4\n", 5, "vertex index -4 out of range"),
+            ("f 1 2 x\n", 5, "invalid vertex index"),
+            ("f 1/1 2 3\n", 5, "texture coordinate index 1 out of range"),
+            ("f 1//2 2 3\n", 5, "normal index 2 out of range"),
+            ("f 1/ 2 /1/1\n", 5, "has no position"),
+            ("f 1//1/1 2 3\n", 5, "too many components"),
+            ("usemtl missing\n", 5, "unknown material 'missing'"),
+        ];
+        for (tail, line, message) in cases.iter() {
+            match parse(&format!("{}{}", vertices, tail)) {
+                Err(ObjError::Parse {
+                    line: l,
+                    message: m,
+                    ..
+                }) => {
+                    assert_eq!(l, *line, "{}", m);
+                    assert!(m.contains(message), "{:?} doesn't mention {:?}", m, message);
+                }
+                _ => panic!("expected a parse error mentioning {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_mtl_files() {
+        let cases = [
+            ("Kd 1 1 1\n", 1, "'Kd' before any newmtl"),
+            ("newmtl a\nKd red\n", 2, "invalid number 'red'"),
+            ("newmtl a\nillum two\n", 2, "illum needs an integer"),
+            ("newmtl a\nmap_Kd\n", 2, "map_Kd needs a file name"),
+            ("newmtl a\nmap_Kd missing.png\n", 1, "can't load map_Kd"),
+        ];
+        for (data, line, message) in cases.iter() {
+            match parse_mtl(Cursor::new(data), Path::new("test.mtl")) {
+                Err(ObjError::Parse {
+                    line: l,
+                    message: m,
+                    ..
+                }) => {
+                    assert_eq!(l, *line, "{}", m);
+                    assert!(m.contains(message), "{:?} doesn't mention {:?}", m, message);
+                }
+                _ => panic!("expected a parse error mentioning {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let path = Path::new("/nonexistent/mesh.obj");
+        assert!(matches!(load_obj(path, gray()), Err(ObjError::Io(p, _)) if p == path));
+        let obj = "mtllib /nonexistent/library.mtl\n";
+        assert!(matches!(parse(obj), Err(ObjError::Io(..))));
+    }
+}
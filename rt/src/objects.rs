@@ -1,5 +1,10 @@
+use crate::aabb::Aabb;
+use crate::density::DensityPtr;
+use crate::flat_bvh::BvhLayout;
 use crate::hit::{HitRecord, Hittable, HittableList, HittablePtr};
-use crate::material::{Isotropic, Lambertian, Material};
+use crate::material::{DiffuseLight, Isotropic, Lambertian, Material, Volumetric};
+use crate::onb::Onb;
+use crate::phase::PhaseFunctionPtr;
 use crate::ray::Ray;
 use crate::texture::TexturePtr;
 use rand::Rng;
@@ -24,7 +29,7 @@ impl Sphere {
         }
     }
 
-    /// Returns the u,v coordinates for a given point as a tuple (u,v).
+    /// Returns the u,v coordinates for a given point on the unit sphere as a tuple (u,v).
     pub fn get_sphere_uv(&self, p: &Vec3) -> (f64, f64) {
         let phi = p.z.atan2(p.x);
         let theta = p.y.asin();
@@ -32,6 +37,12 @@ impl Sphere {
     }
 }
 
+/// The direction of increasing `u` at a point on the unit sphere, which is westward
+/// around the y axis. Vanishes at the poles.
+fn sphere_tangent(p: &Vec3) -> Vec3 {
+    Vec3::new(p.z, 0.0, -p.x)
+}
+
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let oc: Vec3 = r.origin - self.center;
@@ -51,9 +62,10 @@ impl Hittable for Sphere {
                 let outward_normal: Vec3 = (hit_record.p - self.center) / self.radius;
                 hit_record.set_face_normal(r, &outward_normal);
                 hit_record.mat_ptr = Arc::clone(&self.mat_ptr);
-                let (u, v) = self.get_sphere_uv(&((hit_record.p - self.center) / self.radius));
+                let (u, v) = self.get_sphere_uv(&outward_normal);
                 hit_record.u = u;
                 hit_record.v = v;
+                hit_record.tangent = Some(sphere_tangent(&outward_normal));
                 return Some(hit_record);
             }
 
@@ -64,15 +76,58 @@ impl Hittable for Sphere {
                 let outward_normal: Vec3 = (hit_record.p - self.center) / self.radius;
                 hit_record.set_face_normal(r, &outward_normal);
                 hit_record.mat_ptr = Arc::clone(&self.mat_ptr);
-                let (u, v) = self.get_sphere_uv(&((hit_record.p - self.center) / self.radius));
+                let (u, v) = self.get_sphere_uv(&outward_normal);
                 hit_record.u = u;
                 hit_record.v = v;
+                hit_record.tangent = Some(sphere_tangent(&outward_normal));
                 return Some(hit_record);
             }
         }
 
         None
     }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    /// Directions are sampled uniformly in the cone the sphere subtends, which only exists
+    /// from outside the sphere.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let distance_squared = (self.center - *origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return 0.0;
+        }
+        if self
+            .hit(&Ray::new(*origin, *direction, time), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vector();
+        }
+
+        let mut rng = vec3::rng::thread_rng();
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+
+        Onb::new_from_w(&direction).local(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+    }
 }
 
 impl Default for Sphere {
@@ -149,6 +204,13 @@ impl Hittable for MovingSphere {
 
         None
     }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        let box0 = Aabb::new(self.center(t0) - radius, self.center(t0) + radius);
+        let box1 = Aabb::new(self.center(t1) - radius, self.center(t1) + radius);
+        Some(Aabb::surrounding_box(&box0, &box1))
+    }
 }
 
 pub struct XyRect {
@@ -195,9 +257,38 @@ impl Hittable for XyRect {
         );
         let outward_normal = Vec3::new(0.0, 0.0, 1.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Some(Vec3::new(1.0, 0.0, 0.0));
 
         Some(rec)
     }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        // The bounding box must have non-zero width in each dimension, so pad the Z dimension a small amount.
+        Some(Aabb::new(
+            Point3::new(self.x0, self.y0, self.k - 0.0001),
+            Point3::new(self.x1, self.y1, self.k + 0.0001),
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, time), 0.001, f64::INFINITY) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+                solid_angle_pdf(direction, rec.t, &rec.normal, area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let mut rng = vec3::rng::thread_rng();
+        let random_point = Point3::new(
+            rng.gen_range(self.x0, self.x1),
+            rng.gen_range(self.y0, self.y1),
+            self.k,
+        );
+        random_point - *origin
+    }
 }
 
 pub struct XzRect {
@@ -244,9 +335,37 @@ impl Hittable for XzRect {
         );
         let outward_normal = Vec3::new(0.0, 1.0, 0.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Some(Vec3::new(1.0, 0.0, 0.0));
 
         Some(rec)
     }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        Some(Aabb::new(
+            Point3::new(self.x0, self.k - 0.0001, self.z0),
+            Point3::new(self.x1, self.k + 0.0001, self.z1),
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, time), 0.001, f64::INFINITY) {
+            Some(rec) => {
+                let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+                solid_angle_pdf(direction, rec.t, &rec.normal, area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let mut rng = vec3::rng::thread_rng();
+        let random_point = Point3::new(
+            rng.gen_range(self.x0, self.x1),
+            self.k,
+            rng.gen_range(self.z0, self.z1),
+        );
+        random_point - *origin
+    }
 }
 
 pub struct YzRect {
@@ -293,22 +412,50 @@ impl Hittable for YzRect {
         );
         let outward_normal = Vec3::new(1.0, 0.0, 0.0);
         rec.set_face_normal(r, &outward_normal);
+        rec.tangent = Some(Vec3::new(0.0, 1.0, 0.0));
 
         Some(rec)
     }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        Some(Aabb::new(
+            Point3::new(self.k - 0.0001, self.y0, self.z0),
+            Point3::new(self.k + 0.0001, self.y1, self.z1),
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        match self.hit(&Ray::new(*origin, *direction, time), 0.001, f64::INFINITY) {
+            Some(rec) => {
+                let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+                solid_angle_pdf(direction, rec.t, &rec.normal, area)
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let mut rng = vec3::rng::thread_rng();
+        let random_point = Point3::new(
+            self.k,
+            rng.gen_range(self.y0, self.y1),
+            rng.gen_range(self.z0, self.z1),
+        );
+        random_point - *origin
+    }
 }
 
 pub struct Cube {
-    _cube_min: Point3,
-    _cube_max: Point3,
+    cube_min: Point3,
+    cube_max: Point3,
     sides: HittableList,
 }
 
 impl Cube {
     pub fn new(p0: Point3, p1: Point3, mat: MaterialPtr) -> Self {
         let mut cube = Cube {
-            _cube_min: p0,
-            _cube_max: p1,
+            cube_min: p0,
+            cube_max: p1,
             sides: HittableList::default(),
         };
 
@@ -370,6 +517,18 @@ impl Hittable for Cube {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         self.sides.hit(r, t_min, t_max)
     }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        Some(Aabb::new(self.cube_min, self.cube_max))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.sides.pdf_value(origin, direction, time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        self.sides.random(origin, time)
+    }
 }
 
 pub struct ConstantMedium {
@@ -394,47 +553,658 @@ impl ConstantMedium {
             phase_function: Arc::new(Isotropic::new_from_color(c)),
         }
     }
+
+    /// A medium that scatters according to `phase_function` instead of uniformly.
+    pub fn new_with_phase_function(
+        boundary: HittablePtr,
+        d: f64,
+        a: TexturePtr,
+        phase_function: PhaseFunctionPtr,
+    ) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / d,
+            phase_function: Arc::new(Volumetric::new(a, phase_function)),
+        }
+    }
 }
 
 impl Hittable for ConstantMedium {
     fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut rng = rand::thread_rng();
+        let mut rng = vec3::rng::thread_rng();
+        let (t0, t1) = boundary_span(&self.boundary, r, t_min, t_max)?;
 
-        if let Some(mut rec1) = self.boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY) {
-            if let Some(mut rec2) = self.boundary.hit(r, rec1.t + 0.0001, f64::INFINITY) {
-                if rec1.t < t_min {
-                    rec1.t = t_min;
-                }
-                if rec2.t > t_max {
-                    rec2.t = t_max;
-                }
-                if rec1.t > rec2.t {
-                    return None;
-                }
-                if rec1.t < 0.0 {
-                    rec1.t = 0.0;
-                }
+        let ray_length = r.direction.length();
+        let distance_inside_boundary = (t1 - t0) * ray_length;
+        let hit_distance = self.neg_inv_density * rng.gen::<f64>().log(E);
 
-                let ray_length = r.direction.length();
-                let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-                let hit_distance = self.neg_inv_density * rng.gen::<f64>().log(E);
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
 
-                if hit_distance > distance_inside_boundary {
-                    return None;
-                }
+        Some(medium_record(
+            r,
+            t0 + hit_distance / ray_length,
+            &self.phase_function,
+        ))
+    }
 
-                let mut rec = HitRecord::default();
-                rec.t = rec1.t + hit_distance / ray_length;
-                rec.p = r.at(rec.t);
-                rec.normal = Vec3::new(1.0, 0.0, 0.0); // Arbitrary
-                rec.front_face = true; // Arbitrary.
-                rec.mat_ptr = Arc::clone(&self.phase_function);
-                return Some(rec);
-            } else {
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+/// A medium whose density varies in space, inside a closed boundary. Light is absorbed and
+/// scattered in proportion to the density, and absorbing parts can glow like fire.
+///
+/// Collisions are found with delta tracking: tentative distances are drawn against the
+/// maximum density, and each one is a real collision with the chance the local density
+/// has of reaching it. Real collisions scatter or absorb in proportion to the two
+/// coefficients, and absorption ends the path with the emitted light.
+pub struct HeterogeneousMedium {
+    boundary: HittablePtr,
+    density: DensityPtr,
+    sigma_a: f64,
+    sigma_s: f64,
+    phase_function: MaterialPtr,
+    emission: MaterialPtr,
+}
+
+impl HeterogeneousMedium {
+    /// `sigma_a` and `sigma_s` are the absorption and scattering coefficients where the
+    /// density is 1, `albedo` tints the scattered light and `phase_function` spreads it.
+    pub fn new(
+        boundary: HittablePtr,
+        density: DensityPtr,
+        sigma_a: f64,
+        sigma_s: f64,
+        albedo: TexturePtr,
+        phase_function: PhaseFunctionPtr,
+    ) -> Self {
+        HeterogeneousMedium {
+            boundary,
+            density,
+            sigma_a: sigma_a.max(0.0),
+            sigma_s: sigma_s.max(0.0),
+            phase_function: Arc::new(Volumetric::new(albedo, phase_function)),
+            emission: Arc::new(DiffuseLight::new_from_color(Color::default())),
+        }
+    }
+
+    /// Radiance emitted by absorbing parts of the medium, looked up at each point.
+    pub fn with_emission(mut self, emission: TexturePtr) -> Self {
+        self.emission = Arc::new(DiffuseLight::new_from_texture(emission));
+        self
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        let majorant = sigma_t * self.density.max_density();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let mut rng = vec3::rng::thread_rng();
+        let (mut t0, mut t1) = boundary_span(&self.boundary, r, t_min, t_max)?;
+        // Skip the empty space around densities that are zero outside a box.
+        if let Some(bbox) = self.density.bounding_box() {
+            let (b0, b1) = bbox.span(r, t0, t1)?;
+            t0 = b0;
+            t1 = b1;
+        }
+
+        let ray_length = r.direction.length();
+        let mut t = t0;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * ray_length);
+            if t >= t1 {
                 return None;
             }
+            let density = self.density.density(&r.at(t));
+            if rng.gen::<f64>() * majorant < density * sigma_t {
+                let material = if rng.gen::<f64>() * sigma_t < self.sigma_s {
+                    &self.phase_function
+                } else {
+                    &self.emission
+                };
+                return Some(medium_record(r, t, material));
+            }
+        }
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let bbox = self.boundary.bounding_box(t0, t1)?;
+        match self.density.bounding_box() {
+            Some(density_box) => Aabb::intersection(&bbox, &density_box).or(Some(bbox)),
+            None => Some(bbox),
+        }
+    }
+}
+
+/// The part of `r` between `t_min` and `t_max` that's inside `boundary`, which must be
+/// convex. Rays starting inside start the span at their origin.
+fn boundary_span(boundary: &HittablePtr, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+    let rec1 = boundary.hit(r, f64::NEG_INFINITY, f64::INFINITY)?;
+    let rec2 = boundary.hit(r, rec1.t + 0.0001, f64::INFINITY)?;
+    let t0 = rec1.t.max(t_min).max(0.0);
+    let t1 = rec2.t.min(t_max);
+    if t0 > t1 {
+        return None;
+    }
+    Some((t0, t1))
+}
+
+/// A collision inside a medium at `t` along `r`. Media have no surface, so the normal is
+/// arbitrary.
+fn medium_record(r: &Ray, t: f64, material: &MaterialPtr) -> HitRecord {
+    HitRecord {
+        t,
+        p: r.at(t),
+        normal: Vec3::new(1.0, 0.0, 0.0),
+        front_face: true,
+        mat_ptr: Arc::clone(material),
+        ..Default::default()
+    }
+}
+
+/// Möller–Trumbore ray/triangle intersection.
+/// Returns (t, b1, b2) where b1 and b2 are the barycentric weights of p1 and p2.
+pub(crate) fn intersect_triangle(
+    p0: &Point3,
+    p1: &Point3,
+    p2: &Point3,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *p1 - *p0;
+    let edge2 = *p2 - *p0;
+    let pvec = r.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+
+    // The ray is parallel to the triangle's plane.
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin - *p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = r.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+/// Fills in a hit record for a triangle hit at distance t with barycentric weights b1, b2.
+#[allow(clippy::too_many_arguments)]
+fn triangle_hit_record(
+    r: &Ray,
+    t: f64,
+    b1: f64,
+    b2: f64,
+    vertices: &[Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f64, f64); 3],
+    mat_ptr: MaterialPtr,
+) -> HitRecord {
+    let [p0, p1, p2] = vertices;
+    let b0 = 1.0 - b1 - b2;
+
+    let [uv0, uv1, uv2] = uvs;
+    let mut rec = HitRecord::new(
+        r.at(t),
+        Vec3::default(),
+        t,
+        b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+        b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        true,
+        mat_ptr,
+    );
+    let mut outward_normal = (*p1 - *p0).cross(*p2 - *p0).unit_vector();
+
+    match normals {
+        Some([n0, n1, n2]) => {
+            // Vertex normals are more reliable than the winding order to tell which side
+            // is outside, so the geometric normal is flipped to agree with them.
+            let shading_normal = (n0 * b0 + n1 * b1 + n2 * b2).unit_vector();
+            if outward_normal.dot(shading_normal) < 0.0 {
+                outward_normal = -outward_normal;
+            }
+            rec.set_face_normal(r, &outward_normal);
+            rec.normal = if rec.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        None => rec.set_face_normal(r, &outward_normal),
+    }
+
+    // Solve the edges for the derivative of the position with respect to u.
+    let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+    let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+    let determinant = du1 * dv2 - dv1 * du2;
+    if determinant.abs() > 1e-12 {
+        rec.tangent = Some(((*p1 - *p0) * dv2 - (*p2 - *p0) * dv1) / determinant);
+    }
+
+    rec
+}
+
+fn triangle_bounding_box(p0: &Point3, p1: &Point3, p2: &Point3) -> Aabb {
+    let padding = Vec3::new(0.0001, 0.0001, 0.0001);
+    let minimum = Point3::new(
+        p0.x.min(p1.x).min(p2.x),
+        p0.y.min(p1.y).min(p2.y),
+        p0.z.min(p1.z).min(p2.z),
+    );
+    let maximum = Point3::new(
+        p0.x.max(p1.x).max(p2.x),
+        p0.y.max(p1.y).max(p2.y),
+        p0.z.max(p1.z).max(p2.z),
+    );
+    Aabb::new(minimum - padding, maximum + padding)
+}
+
+fn triangle_area(p0: &Point3, p1: &Point3, p2: &Point3) -> f64 {
+    (*p1 - *p0).cross(*p2 - *p0).length() / 2.0
+}
+
+/// A uniformly distributed point on a triangle.
+fn random_point_on_triangle(p0: &Point3, p1: &Point3, p2: &Point3) -> Point3 {
+    let mut rng = vec3::rng::thread_rng();
+    let su0 = rng.gen::<f64>().sqrt();
+    let u1: f64 = rng.gen();
+    let b0 = 1.0 - su0;
+    let b1 = u1 * su0;
+    *p0 * b0 + *p1 * b1 + *p2 * (1.0 - b0 - b1)
+}
+
+/// Converts the density of picking a point uniformly on a surface with the given area into
+/// a density with respect to solid angle, for a point hit at distance `t` along `direction`.
+fn solid_angle_pdf(direction: &Vec3, t: f64, normal: &Vec3, area: f64) -> f64 {
+    let distance_squared = t * t * direction.length_squared();
+    let cosine = (direction.dot(*normal) / direction.length()).abs();
+    if cosine <= 0.0 || area <= 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: [(f64, f64); 3],
+    mat_ptr: MaterialPtr,
+}
+
+impl Triangle {
+    /// A flat shaded triangle whose u,v coordinates are the barycentric coordinates of the hit.
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat_ptr: MaterialPtr) -> Self {
+        Triangle::new_with_attributes([v0, v1, v2], None, None, mat_ptr)
+    }
+
+    /// A triangle with optional per-vertex shading normals and texture coordinates.
+    pub fn new_with_attributes(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        mat_ptr: MaterialPtr,
+    ) -> Self {
+        Triangle {
+            vertices,
+            normals,
+            uvs: uvs.unwrap_or(BARYCENTRIC_UVS),
+            mat_ptr,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, b1, b2) = intersect_triangle(p0, p1, p2, r, t_min, t_max)?;
+        Some(triangle_hit_record(
+            r,
+            t,
+            b1,
+            b2,
+            &self.vertices,
+            self.normals,
+            self.uvs,
+            Arc::clone(&self.mat_ptr),
+        ))
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        let [p0, p1, p2] = &self.vertices;
+        Some(triangle_bounding_box(p0, p1, p2))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let [p0, p1, p2] = &self.vertices;
+        let r = Ray::new(*origin, *direction, time);
+        match intersect_triangle(p0, p1, p2, &r, 0.001, f64::INFINITY) {
+            Some((t, _, _)) => {
+                let normal = (*p1 - *p0).cross(*p2 - *p0).unit_vector();
+                solid_angle_pdf(direction, t, &normal, triangle_area(p0, p1, p2))
+            }
+            None => 0.0,
+        }
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let [p0, p1, p2] = &self.vertices;
+        random_point_on_triangle(p0, p1, p2) - *origin
+    }
+}
+
+/// The default texture coordinates of a triangle without UVs: its barycentric coordinates.
+const BARYCENTRIC_UVS: [(f64, f64); 3] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)];
+
+/// A triangle mesh with shared vertex attribute buffers and an index buffer. The faces are
+/// stored in their own hierarchy, so the whole mesh acts as a single object in the world.
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    /// Empty, or one normal per position.
+    normals: Vec<Vec3>,
+    /// Empty, or one texture coordinate per position.
+    uvs: Vec<(f64, f64)>,
+    /// Empty, or one color per position, for `VertexColorTexture`.
+    colors: Vec<Color>,
+    indices: Vec<[usize; 3]>,
+    materials: Vec<MaterialPtr>,
+    /// Empty when every face uses the first material, otherwise an index into `materials`
+    /// for each face.
+    face_materials: Vec<usize>,
+    bvh: BvhLayout,
+    /// Faces with an emissive material and the running total of their areas, used to
+    /// sample points on the mesh proportionally to area when it's used as a light.
+    emissive_faces: Vec<usize>,
+    emissive_area_cdf: Vec<f64>,
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Point3>, indices: Vec<[usize; 3]>, mat_ptr: MaterialPtr) -> Self {
+        TriangleMesh::new_with_attributes(
+            positions,
+            Vec::new(),
+            Vec::new(),
+            indices,
+            vec![mat_ptr],
+            Vec::new(),
+        )
+    }
+
+    /// Panics when an index points outside of the vertex or material buffers.
+    pub fn new_with_attributes(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        materials: Vec<MaterialPtr>,
+        face_materials: Vec<usize>,
+    ) -> Self {
+        assert!(
+            normals.is_empty() || normals.len() == positions.len(),
+            "A mesh needs either no normals or one per vertex."
+        );
+        assert!(
+            uvs.is_empty() || uvs.len() == positions.len(),
+            "A mesh needs either no uvs or one per vertex."
+        );
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "Mesh vertex index out of range."
+        );
+        assert!(!materials.is_empty(), "A mesh needs at least one material.");
+        assert!(
+            face_materials.is_empty() || face_materials.len() == indices.len(),
+            "A mesh needs either no face materials or one per face."
+        );
+        assert!(
+            face_materials.iter().all(|&m| m < materials.len()),
+            "Mesh material index out of range."
+        );
+
+        let boxes: Vec<Aabb> = indices
+            .iter()
+            .map(|[i0, i1, i2]| {
+                triangle_bounding_box(&positions[*i0], &positions[*i1], &positions[*i2])
+            })
+            .collect();
+        let bvh = BvhLayout::new(&boxes);
+
+        let mut mesh = TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            colors: Vec::new(),
+            indices,
+            materials,
+            face_materials,
+            bvh,
+            emissive_faces: Vec::new(),
+            emissive_area_cdf: Vec::new(),
+        };
+
+        let mut total_area = 0.0;
+        for face in 0..mesh.indices.len() {
+            if mesh.face_material(face).is_emissive() {
+                let [p0, p1, p2] = mesh.face_vertices(face);
+                total_area += triangle_area(&p0, &p1, &p2);
+                mesh.emissive_faces.push(face);
+                mesh.emissive_area_cdf.push(total_area);
+            }
+        }
+
+        mesh
+    }
+
+    /// Colors for each vertex, interpolated across the faces into the `vertex_color` of
+    /// hits. Panics unless there's one color per position.
+    pub fn with_vertex_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(
+            colors.len(),
+            self.positions.len(),
+            "A mesh needs one color per vertex."
+        );
+        self.colors = colors;
+        self
+    }
+
+    /// Whether any face has an emissive material, which makes the mesh usable as a light.
+    pub fn is_emissive(&self) -> bool {
+        !self.emissive_faces.is_empty()
+    }
+
+    fn emissive_area(&self) -> f64 {
+        self.emissive_area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    fn face_material(&self, face: usize) -> &MaterialPtr {
+        match self.face_materials.get(face) {
+            Some(&m) => &self.materials[m],
+            None => &self.materials[0],
+        }
+    }
+
+    /// Returns (face, t, b1, b2) for the closest face hit by the ray.
+    fn closest_face(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(usize, f64, f64, f64)> {
+        let mut closest = None;
+        self.bvh.traverse(r, t_min, t_max, |face, closest_so_far| {
+            let [p0, p1, p2] = self.face_vertices(face);
+            let (t, b1, b2) = intersect_triangle(&p0, &p1, &p2, r, t_min, closest_so_far)?;
+            closest = Some((face, t, b1, b2));
+            Some(t)
+        });
+        closest
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    fn face_vertices(&self, face: usize) -> [Point3; 3] {
+        let [i0, i1, i2] = self.indices[face];
+        [self.positions[i0], self.positions[i1], self.positions[i2]]
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Only remember the closest face, the record is built once at the end.
+        let (face, t, b1, b2) = self.closest_face(r, t_min, t_max)?;
+        let [i0, i1, i2] = self.indices[face];
+        let normals = if self.normals.is_empty() {
+            None
         } else {
-            return None;
+            Some([self.normals[i0], self.normals[i1], self.normals[i2]])
+        };
+        let uvs = if self.uvs.is_empty() {
+            BARYCENTRIC_UVS
+        } else {
+            [self.uvs[i0], self.uvs[i1], self.uvs[i2]]
+        };
+        let material = self.face_material(face);
+
+        let mut rec = triangle_hit_record(
+            r,
+            t,
+            b1,
+            b2,
+            &self.face_vertices(face),
+            normals,
+            uvs,
+            Arc::clone(material),
+        );
+        if !self.colors.is_empty() {
+            let [c0, c1, c2] = [self.colors[i0], self.colors[i1], self.colors[i2]];
+            rec.vertex_color = Some(c0 * (1.0 - b1 - b2) + c1 * b1 + c2 * b2);
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self, _t0: f64, _t1: f64) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+
+    /// Only emissive faces are sampled, with a probability proportional to their area.
+    /// `random` can pick any point along the direction, hidden or not, so every emissive
+    /// face the ray crosses adds to the density.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        if !self.is_emissive() {
+            return 0.0;
         }
+        let r = Ray::new(*origin, *direction, time);
+        let mut pdf = 0.0;
+        self.bvh.traverse(&r, 0.001, f64::INFINITY, |face, _| {
+            if self.face_material(face).is_emissive() {
+                let [p0, p1, p2] = self.face_vertices(face);
+                if let Some((t, _, _)) = intersect_triangle(&p0, &p1, &p2, &r, 0.001, f64::INFINITY)
+                {
+                    let normal = (p1 - p0).cross(p2 - p0).unit_vector();
+                    pdf += solid_angle_pdf(direction, t, &normal, self.emissive_area());
+                }
+            }
+            None
+        });
+        pdf
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        if !self.is_emissive() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let target = vec3::rng::thread_rng().gen::<f64>() * self.emissive_area();
+        let index = self
+            .emissive_area_cdf
+            .partition_point(|&area| area < target)
+            .min(self.emissive_faces.len() - 1);
+        let [p0, p1, p2] = self.face_vertices(self.emissive_faces[index]);
+        random_point_on_triangle(&p0, &p1, &p2) - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::Transform;
+    use vec3::Mat4;
+
+    fn gray() -> MaterialPtr {
+        Arc::new(Lambertian::new_from_color(&Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn down(x: f64, y: f64) -> Ray {
+        Ray::new(Point3::new(x, y, 2.0), Vec3::new(0.0, 0.0, -1.0), 0.0)
+    }
+
+    fn assert_parallel(a: Vec3, b: Vec3) {
+        let cos = a.unit_vector().dot(b.unit_vector());
+        assert!((cos - 1.0).abs() < 1e-9, "{:?} isn't along {:?}", a, b);
+    }
+
+    #[test]
+    fn triangle_tangent_follows_texture_coordinates() {
+        let vertices = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let barycentric = Triangle::new(vertices[0], vertices[1], vertices[2], gray());
+        let rec = barycentric.hit(&down(0.2, 0.2), 0.001, 10.0).unwrap();
+        assert_parallel(rec.tangent.unwrap(), vertices[1] - vertices[0]);
+
+        // u runs along the second edge and v backwards along the first.
+        let uvs = [(0.0, 1.0), (0.0, 0.0), (1.0, 1.0)];
+        let textured = Triangle::new_with_attributes(vertices, None, Some(uvs), gray());
+        let rec = textured.hit(&down(0.2, 0.2), 0.001, 10.0).unwrap();
+        assert_parallel(rec.tangent.unwrap(), vertices[2] - vertices[0]);
+
+        let flat = [(0.5, 0.5); 3];
+        let degenerate = Triangle::new_with_attributes(vertices, None, Some(flat), gray());
+        assert!(degenerate
+            .hit(&down(0.2, 0.2), 0.001, 10.0)
+            .unwrap()
+            .tangent
+            .is_none());
+    }
+
+    #[test]
+    fn sphere_tangent_follows_u() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, gray());
+        let rec = sphere.hit(&down(0.3, 0.4), 0.001, 10.0).unwrap();
+        let tangent = rec.tangent.unwrap();
+        assert!(tangent.dot(rec.normal).abs() < 1e-9);
+        let (u, _) = sphere.get_sphere_uv(&(rec.p + tangent * 1e-4).unit_vector());
+        assert!(u > rec.u);
+    }
+
+    #[test]
+    fn transforms_carry_the_tangent() {
+        let rect: HittablePtr = Arc::new(XyRect::new(-1.0, 1.0, -1.0, 1.0, 0.0, gray()));
+        let rotation = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), PI / 2.0);
+        let rotated = Transform::new(rect, rotation).unwrap();
+        let rec = rotated.hit(&down(0.1, 0.2), 0.001, 10.0).unwrap();
+        assert_parallel(rec.tangent.unwrap(), Vec3::new(0.0, 1.0, 0.0));
     }
 }
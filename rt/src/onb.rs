@@ -0,0 +1,51 @@
+use vec3::Vec3;
+
+/// An orthonormal basis, used to move directions sampled around the z axis to be around
+/// an arbitrary direction.
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis whose `w` axis points along `n`.
+    pub fn new_from_w(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).unit_vector();
+        let u = w.cross(v);
+        Onb { u, v, w }
+    }
+
+    /// Builds a basis whose `w` axis points along `n` and whose `u` axis is `tangent` made
+    /// perpendicular to it. Falls back to an arbitrary `u` when `tangent` is parallel to `n`.
+    pub fn new_from_w_and_u(n: &Vec3, tangent: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = *tangent - w * w.dot(*tangent);
+        if u.length_squared() <= 1e-12 * tangent.length_squared() {
+            return Onb::new_from_w(n);
+        }
+        let u = u.unit_vector();
+        let v = w.cross(u);
+        Onb { u, v, w }
+    }
+
+    /// Converts coordinates in this basis to world space.
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        self.u * a + self.v * b + self.w * c
+    }
+
+    pub fn local_vec(&self, a: &Vec3) -> Vec3 {
+        self.local(a.x, a.y, a.z)
+    }
+
+    /// Converts a world space vector to coordinates in this basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
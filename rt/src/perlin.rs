@@ -13,12 +13,18 @@ pub struct Perlin {
     perm_z: Perm,
 }
 
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Perlin {
     pub fn new() -> Self {
         let mut ranvec = [Vec3::default(); POINT_COUNT];
 
-        for i in 0..POINT_COUNT {
-            ranvec[i] = Vec3::random_range(-1.0, 1.0).unit_vector();
+        for v in ranvec.iter_mut() {
+            *v = Vec3::random_range(-1.0, 1.0).unit_vector();
         }
 
         Perlin {
@@ -31,34 +37,36 @@ impl Perlin {
 
     pub fn turb(&self, p: &Point3, depth: u8) -> f64 {
         let mut accum: f64 = 0.0;
-        let mut temp_p = p.clone();
+        let mut temp_p = *p;
         let mut weight = 1.0;
 
         for _ in 0..depth {
             accum += weight * self.noise(&temp_p);
             weight *= 0.5;
-            temp_p = temp_p * 2.0;
+            temp_p *= 2.0;
         }
 
         accum.abs()
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn noise(&self, p: &Point3) -> f64 {
         let u = p.x - p.x.floor();
         let v = p.y - p.y.floor();
         let w = p.z - p.z.floor();
 
-        let i = p.x.floor() as usize;
-        let j = p.y.floor() as usize;
-        let k = p.z.floor() as usize;
+        // Wrap negative cells too, casting them to usize directly would clamp them to 0.
+        let i = p.x.floor() as i64 as usize;
+        let j = p.y.floor() as i64 as usize;
+        let k = p.z.floor() as i64 as usize;
         let mut c = [[[Vec3::default(); 2]; 2]; 3];
 
         for di in 0..2 {
             for dj in 0..2 {
                 for dk in 0..2 {
-                    c[di][dj][dk] = self.ranvec[self.perm_x[(i + di) & 255]
-                        ^ self.perm_y[(j + dj) & 255]
-                        ^ self.perm_z[(k + dk) & 255]]
+                    c[di][dj][dk] = self.ranvec[self.perm_x[i.wrapping_add(di) & 255]
+                        ^ self.perm_y[j.wrapping_add(dj) & 255]
+                        ^ self.perm_z[k.wrapping_add(dk) & 255]]
                 }
             }
         }
@@ -66,6 +74,7 @@ impl Perlin {
         Perlin::perlin_interp(c, u, v, w)
     }
 
+    #[allow(clippy::needless_range_loop)]
     fn perlin_interp(c: [[[Vec3; 2]; 2]; 3], u: f64, v: f64, w: f64) -> f64 {
         let uu = u * u * (3.0 - 2.0 * u);
         let vv = v * v * (3.0 - 2.0 * v);
@@ -89,8 +98,8 @@ impl Perlin {
 
     fn perlin_generate_perm() -> Perm {
         let mut p: Perm = [0; POINT_COUNT];
-        for i in 0..POINT_COUNT {
-            p[i] = i as usize;
+        for (i, v) in p.iter_mut().enumerate() {
+            *v = i;
         }
 
         Perlin::permute(&mut p, POINT_COUNT);
@@ -99,12 +108,10 @@ impl Perlin {
     }
 
     fn permute(p: &mut Perm, n: usize) {
-        let mut rng = rand::thread_rng();
+        let mut rng = vec3::rng::thread_rng();
         for i in (1..n).rev() {
             let target = rng.gen_range(0, i);
-            let tmp = p[i];
-            p[i] = p[target];
-            p[target] = tmp;
+            p.swap(i, target);
         }
     }
 }
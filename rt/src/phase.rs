@@ -0,0 +1,239 @@
+//! Phase functions: how light scattering inside a medium is distributed over directions.
+//! Angles are measured between the direction light was travelling in and the direction it
+//! leaves in, so forward scattering keeps light going the same way.
+
+use crate::onb::Onb;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use vec3::Vec3;
+
+pub trait PhaseFunction {
+    /// Density per solid angle of scattering by an angle whose cosine is `cos_theta`. It
+    /// integrates to one over the sphere.
+    fn p(&self, cos_theta: f64) -> f64;
+
+    /// Picks the cosine of the scattering angle with `u1` and `u2` in [0, 1),
+    /// proportionally to `p`.
+    fn sample_cos_theta(&self, u1: f64, u2: f64) -> f64;
+
+    /// Samples the direction light travelling along `direction` scatters into, returning it
+    /// with its density.
+    fn sample(&self, direction: &Vec3, u1: f64, u2: f64, u3: f64) -> (Vec3, f64) {
+        let cos_theta = num::clamp(self.sample_cos_theta(u1, u2), -1.0, 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u3;
+        let frame = Onb::new_from_w(direction);
+        let scattered = frame.local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+        (scattered, self.p(cos_theta))
+    }
+}
+
+pub type PhaseFunctionPtr = Arc<dyn PhaseFunction + Send + Sync>;
+
+/// Scatters equally in every direction.
+pub struct IsotropicPhase;
+
+impl PhaseFunction for IsotropicPhase {
+    fn p(&self, _cos_theta: f64) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample_cos_theta(&self, u1: f64, _u2: f64) -> f64 {
+        1.0 - 2.0 * u1
+    }
+}
+
+/// The Henyey–Greenstein phase function. The asymmetry `g` in (-1, 1) is the average
+/// cosine of the scattering angle: positive values scatter forward, like haze and clouds,
+/// negative ones back.
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        HenyeyGreenstein {
+            g: num::clamp(g, -0.999, 0.999),
+        }
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        henyey_greenstein(cos_theta, self.g)
+    }
+
+    fn sample_cos_theta(&self, u1: f64, _u2: f64) -> f64 {
+        sample_henyey_greenstein(u1, self.g)
+    }
+}
+
+/// A mix of two Henyey–Greenstein lobes, usually a forward and a backward one, which fits
+/// measured phase functions better than a single lobe.
+pub struct DoubleHenyeyGreenstein {
+    g1: f64,
+    g2: f64,
+    /// Weight of the first lobe.
+    weight: f64,
+}
+
+impl DoubleHenyeyGreenstein {
+    pub fn new(g1: f64, g2: f64, weight: f64) -> Self {
+        DoubleHenyeyGreenstein {
+            g1: num::clamp(g1, -0.999, 0.999),
+            g2: num::clamp(g2, -0.999, 0.999),
+            weight: num::clamp(weight, 0.0, 1.0),
+        }
+    }
+}
+
+impl PhaseFunction for DoubleHenyeyGreenstein {
+    fn p(&self, cos_theta: f64) -> f64 {
+        self.weight * henyey_greenstein(cos_theta, self.g1)
+            + (1.0 - self.weight) * henyey_greenstein(cos_theta, self.g2)
+    }
+
+    fn sample_cos_theta(&self, u1: f64, u2: f64) -> f64 {
+        let g = if u2 < self.weight { self.g1 } else { self.g2 };
+        sample_henyey_greenstein(u1, g)
+    }
+}
+
+/// Scattering by particles much smaller than the wavelength, like air molecules. Forward
+/// and backward scattering are equally likely and twice as likely as sideways.
+pub struct Rayleigh;
+
+impl PhaseFunction for Rayleigh {
+    fn p(&self, cos_theta: f64) -> f64 {
+        3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta)
+    }
+
+    fn sample_cos_theta(&self, u1: f64, _u2: f64) -> f64 {
+        // The cdf is (3μ + μ³ + 4) / 8, so μ is the real root of μ³ + 3μ + 4 - 8u, found
+        // with Cardano's formula.
+        let q = 4.0 - 8.0 * u1;
+        let d = (q * q / 4.0 + 1.0).sqrt();
+        (-q / 2.0 + d).cbrt() + (-q / 2.0 - d).cbrt()
+    }
+}
+
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+}
+
+fn sample_henyey_greenstein(u: f64, g: f64) -> f64 {
+    if g.abs() < 1e-3 {
+        return 1.0 - 2.0 * u;
+    }
+    let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+    (1.0 + g * g - s * s) / (2.0 * g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BINS: usize = 20;
+    const SAMPLES: usize = 160_000;
+
+    fn phase_functions() -> Vec<(&'static str, PhaseFunctionPtr)> {
+        vec![
+            ("isotropic", Arc::new(IsotropicPhase)),
+            ("forward", Arc::new(HenyeyGreenstein::new(0.7))),
+            ("backward", Arc::new(HenyeyGreenstein::new(-0.4))),
+            ("nearly isotropic", Arc::new(HenyeyGreenstein::new(1e-4))),
+            (
+                "double",
+                Arc::new(DoubleHenyeyGreenstein::new(0.8, -0.3, 0.7)),
+            ),
+            ("rayleigh", Arc::new(Rayleigh)),
+        ]
+    }
+
+    /// Integral of `p` over the directions whose cosine is between `a` and `b`.
+    fn integrate(phase: &dyn PhaseFunction, a: f64, b: f64) -> f64 {
+        let n = 10_000;
+        let width = (b - a) / n as f64;
+        (0..n)
+            .map(|i| 2.0 * PI * phase.p(a + (i as f64 + 0.5) * width) * width)
+            .sum()
+    }
+
+    #[test]
+    fn densities_integrate_to_one() {
+        for (name, phase) in phase_functions() {
+            let total = integrate(phase.as_ref(), -1.0, 1.0);
+            assert!((total - 1.0).abs() < 1e-4, "{}: {}", name, total);
+        }
+    }
+
+    #[test]
+    fn samples_follow_the_density() {
+        for (name, phase) in phase_functions() {
+            let mut counts = [0; BINS];
+            // A Fibonacci lattice, so both numbers are spread evenly on their own too.
+            for i in 0..SAMPLES {
+                let u1 = (i as f64 + 0.5) / SAMPLES as f64;
+                let u2 = (i as f64 * 0.618_033_988_749_895).fract();
+                let cos_theta = phase.sample_cos_theta(u1, u2);
+                assert!((-1.0..=1.0).contains(&cos_theta), "{}: {}", name, cos_theta);
+                let bin = ((cos_theta + 1.0) / 2.0 * BINS as f64) as usize;
+                counts[bin.min(BINS - 1)] += 1;
+            }
+            for (bin, &count) in counts.iter().enumerate() {
+                let a = -1.0 + 2.0 * bin as f64 / BINS as f64;
+                let expected = integrate(phase.as_ref(), a, a + 2.0 / BINS as f64);
+                let measured = count as f64 / SAMPLES as f64;
+                assert!(
+                    (measured - expected).abs() < 1e-3,
+                    "{}: bin {} has {} instead of {}",
+                    name,
+                    bin,
+                    measured,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_directions_come_with_their_density() {
+        let direction = Vec3::new(1.0, 2.0, -2.0).unit_vector();
+        for (name, phase) in phase_functions() {
+            for &(u1, u2, u3) in [(0.1, 0.2, 0.3), (0.5, 0.9, 0.7), (0.95, 0.4, 0.05)].iter() {
+                let (scattered, pdf) = phase.sample(&direction, u1, u2, u3);
+                assert!((scattered.length() - 1.0).abs() < 1e-9, "{}", name);
+                let cos_theta = scattered.dot(direction);
+                assert!((cos_theta - phase.sample_cos_theta(u1, u2)).abs() < 1e-9);
+                assert!((pdf - phase.p(cos_theta)).abs() < 1e-9 * pdf, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn rayleigh_inverts_its_cdf() {
+        let cdf = |mu: f64| (3.0 * mu + mu * mu * mu + 4.0) / 8.0;
+        assert!((Rayleigh.sample_cos_theta(0.0, 0.0) + 1.0).abs() < 1e-12);
+        assert!(Rayleigh.sample_cos_theta(0.5, 0.0).abs() < 1e-12);
+        assert!((Rayleigh.sample_cos_theta(1.0, 0.0) - 1.0).abs() < 1e-12);
+        for i in 0..=1000 {
+            let u = i as f64 / 1000.0;
+            let mu = Rayleigh.sample_cos_theta(u, 0.0);
+            assert!((cdf(mu) - u).abs() < 1e-12, "{}: {}", u, mu);
+        }
+    }
+
+    #[test]
+    fn asymmetry_is_the_average_cosine() {
+        for &g in [-0.6, 0.3, 0.9].iter() {
+            let phase = HenyeyGreenstein::new(g);
+            let n = 100_000;
+            let mean: f64 = (0..n)
+                .map(|i| phase.sample_cos_theta((i as f64 + 0.5) / n as f64, 0.0))
+                .sum::<f64>()
+                / n as f64;
+            assert!((mean - g).abs() < 1e-3, "{}: {}", g, mean);
+        }
+    }
+}
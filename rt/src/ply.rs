@@ -0,0 +1,668 @@
+//! Loader for Stanford PLY files in the ascii, binary_little_endian and binary_big_endian
+//! encodings.
+//!
+//! Reads vertex positions, normals, texture coordinates and colors plus the face list.
+//! Any other elements and properties are skipped.
+
+use crate::material::{Lambertian, MaterialPtr};
+use crate::objects::TriangleMesh;
+use crate::texture::VertexColorTexture;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vec3::{Color, Point3, Vec3};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            PlyError::Format(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io(_, err) => Some(err),
+            PlyError::Format(..) => None,
+        }
+    }
+}
+
+/// The raw data read from a PLY file. Attribute lists are either empty or have one entry
+/// per position.
+pub struct PlyMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl PlyMesh {
+    /// Builds a mesh using one material for every face.
+    pub fn into_mesh(self, mat_ptr: MaterialPtr) -> TriangleMesh {
+        TriangleMesh::new_with_attributes(
+            self.positions,
+            self.normals,
+            self.uvs,
+            self.indices,
+            vec![mat_ptr],
+            Vec::new(),
+        )
+    }
+
+    /// Builds a diffuse mesh colored by its vertex colors, keeping the colors in a buffer
+    /// on the mesh that a single `VertexColorTexture` reads. Returns None when the file has
+    /// no colors.
+    pub fn into_vertex_colored_mesh(mut self) -> Option<TriangleMesh> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        let colors = std::mem::take(&mut self.colors);
+        let material = Arc::new(Lambertian::new_from_texture(Arc::new(VertexColorTexture)));
+        Some(self.into_mesh(material).with_vertex_colors(colors))
+    }
+}
+
+pub fn load_ply(path: &Path) -> Result<PlyMesh, PlyError> {
+    let data = fs::read(path).map_err(|e| PlyError::Io(path.to_path_buf(), e))?;
+    parse_ply(&data).map_err(|message| PlyError::Format(path.to_path_buf(), message))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::Int8),
+            "uchar" | "uint8" => Ok(ScalarType::Uint8),
+            "short" | "int16" => Ok(ScalarType::Int16),
+            "ushort" | "uint16" => Ok(ScalarType::Uint16),
+            "int" | "int32" => Ok(ScalarType::Int32),
+            "uint" | "uint32" => Ok(ScalarType::Uint32),
+            "float" | "float32" => Ok(ScalarType::Float32),
+            "double" | "float64" => Ok(ScalarType::Float64),
+            other => Err(format!("unknown property type '{}'", other)),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::Uint8 => 1,
+            ScalarType::Int16 | ScalarType::Uint16 => 2,
+            ScalarType::Int32 | ScalarType::Uint32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    /// The value integer colors are divided by to map them to [0, 1].
+    fn color_scale(&self) -> f64 {
+        match self {
+            ScalarType::Int8 => 127.0,
+            ScalarType::Uint8 => 255.0,
+            ScalarType::Int16 => 32767.0,
+            ScalarType::Uint16 => 65535.0,
+            ScalarType::Int32 => 2147483647.0,
+            ScalarType::Uint32 => 4294967295.0,
+            ScalarType::Float32 | ScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+enum PropertyKind {
+    Scalar(ScalarType),
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyKind,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads scalars from the body of the file in either encoding.
+struct BodyReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    encoding: Encoding,
+}
+
+impl<'a> BodyReader<'a> {
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String> {
+        match self.encoding {
+            Encoding::Ascii => self.read_ascii(),
+            _ => self.read_binary(ty),
+        }
+    }
+
+    fn read_ascii(&mut self) -> Result<f64, String> {
+        while self.position < self.data.len() && self.data[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        let start = self.position;
+        while self.position < self.data.len() && !self.data[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err("unexpected end of file".to_string());
+        }
+        let token = std::str::from_utf8(&self.data[start..self.position])
+            .map_err(|_| "invalid ascii data".to_string())?;
+        token
+            .parse::<f64>()
+            .map_err(|_| format!("invalid number '{}'", token))
+    }
+
+    fn read_binary(&mut self, ty: ScalarType) -> Result<f64, String> {
+        let size = ty.size();
+        if self.position + size > self.data.len() {
+            return Err("unexpected end of file".to_string());
+        }
+        let bytes = &self.data[self.position..self.position + size];
+        self.position += size;
+
+        macro_rules! decode {
+            ($t:ty, $n:expr) => {{
+                let mut b = [0u8; $n];
+                b.copy_from_slice(bytes);
+                if self.encoding == Encoding::BinaryLittleEndian {
+                    <$t>::from_le_bytes(b) as f64
+                } else {
+                    <$t>::from_be_bytes(b) as f64
+                }
+            }};
+        }
+
+        Ok(match ty {
+            ScalarType::Int8 => decode!(i8, 1),
+            ScalarType::Uint8 => decode!(u8, 1),
+            ScalarType::Int16 => decode!(i16, 2),
+            ScalarType::Uint16 => decode!(u16, 2),
+            ScalarType::Int32 => decode!(i32, 4),
+            ScalarType::Uint32 => decode!(u32, 4),
+            ScalarType::Float32 => decode!(f32, 4),
+            ScalarType::Float64 => decode!(f64, 8),
+        })
+    }
+
+    fn read_index(&mut self, ty: ScalarType) -> Result<usize, String> {
+        let value = self.read(ty)?;
+        if value < 0.0 || value.fract() != 0.0 {
+            return Err(format!("invalid index {}", value));
+        }
+        Ok(value as usize)
+    }
+}
+
+/// Parses a complete PLY file from memory.
+pub fn parse_ply(data: &[u8]) -> Result<PlyMesh, String> {
+    let (encoding, elements, body_start) = parse_header(data)?;
+    let mut reader = BodyReader {
+        data,
+        position: body_start,
+        encoding,
+    };
+
+    let mut mesh = PlyMesh {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        colors: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for element in elements.iter() {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut reader, element, &mut mesh)?,
+            "face" => read_faces(&mut reader, element, &mut mesh)?,
+            _ => skip_element(&mut reader, element)?,
+        }
+    }
+
+    if let Some(index) = mesh
+        .indices
+        .iter()
+        .flatten()
+        .find(|&&i| i >= mesh.positions.len())
+    {
+        return Err(format!(
+            "face index {} out of range, there are {} vertices",
+            index,
+            mesh.positions.len()
+        ));
+    }
+
+    Ok(mesh)
+}
+
+fn parse_header(data: &[u8]) -> Result<(Encoding, Vec<Element>, usize), String> {
+    // Only a line of its own ends the header, comments may mention end_header too. The
+    // body starts after its line break.
+    let mut line_start = 0;
+    let (end, body_start) = loop {
+        if line_start >= data.len() {
+            return Err("missing end_header".to_string());
+        }
+        let line_end = data[line_start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(data.len(), |offset| line_start + offset);
+        let next_line = (line_end + 1).min(data.len());
+        if data[line_start..line_end].trim_ascii() == b"end_header" {
+            break (line_start, next_line);
+        }
+        line_start = next_line;
+    };
+    let header =
+        std::str::from_utf8(&data[..end]).map_err(|_| "header is not valid text".to_string())?;
+
+    let mut lines = header.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line.trim() == "ply" => {}
+        _ => return Err("not a PLY file, missing 'ply' magic".to_string()),
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    for (index, line) in lines {
+        let error = |message: String| format!("header line {}: {}", index + 1, message);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    other => return Err(error(format!("unknown format '{}'", other))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let kind = PropertyKind::List(
+                    ScalarType::parse(count_type).map_err(error)?,
+                    ScalarType::parse(item_type).map_err(error)?,
+                );
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        kind,
+                    });
+            }
+            ["property", ty, name] => {
+                let kind = PropertyKind::Scalar(ScalarType::parse(ty).map_err(error)?);
+                elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element".to_string()))?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        kind,
+                    });
+            }
+            _ => return Err(error(format!("can't parse '{}'", line.trim()))),
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| "missing format line".to_string())?;
+    Ok((encoding, elements, body_start))
+}
+
+fn read_vertices(
+    reader: &mut BodyReader,
+    element: &Element,
+    mesh: &mut PlyMesh,
+) -> Result<(), String> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    };
+    let (x, y, z) = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+        (Some(x), Some(y), Some(z)) => (x, y, z),
+        _ => return Err("vertex element needs x, y and z properties".to_string()),
+    };
+    let normal = match (find(&["nx"]), find(&["ny"]), find(&["nz"])) {
+        (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+        _ => None,
+    };
+    let uv = match (
+        find(&["u", "s", "texture_u", "texture_s"]),
+        find(&["v", "t", "texture_v", "texture_t"]),
+    ) {
+        (Some(u), Some(v)) => Some((u, v)),
+        _ => None,
+    };
+    let color = match (
+        find(&["red", "r"]),
+        find(&["green", "g"]),
+        find(&["blue", "b"]),
+    ) {
+        (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+        _ => None,
+    };
+
+    let is_color = |i: usize| matches!(color, Some((r, g, b)) if i == r || i == g || i == b);
+
+    let mut values = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            match property.kind {
+                PropertyKind::Scalar(ty) => {
+                    values[i] = reader.read(ty)?;
+                    if is_color(i) {
+                        values[i] /= ty.color_scale();
+                    }
+                }
+                PropertyKind::List(count_type, item_type) => {
+                    skip_list(reader, count_type, item_type)?
+                }
+            }
+        }
+
+        mesh.positions
+            .push(Point3::new(values[x], values[y], values[z]));
+        if let Some((nx, ny, nz)) = normal {
+            mesh.normals
+                .push(Vec3::new(values[nx], values[ny], values[nz]));
+        }
+        if let Some((u, v)) = uv {
+            mesh.uvs.push((values[u], values[v]));
+        }
+        if let Some((r, g, b)) = color {
+            mesh.colors
+                .push(Color::new(values[r], values[g], values[b]));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_faces(
+    reader: &mut BodyReader,
+    element: &Element,
+    mesh: &mut PlyMesh,
+) -> Result<(), String> {
+    let index_property = element
+        .properties
+        .iter()
+        .position(|p| p.name == "vertex_indices" || p.name == "vertex_index")
+        .ok_or_else(|| "face element needs a vertex_indices property".to_string())?;
+
+    let mut polygon = Vec::new();
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            match property.kind {
+                PropertyKind::List(count_type, item_type) if i == index_property => {
+                    let count = reader.read_index(count_type)?;
+                    if count < 3 {
+                        return Err(format!("face needs at least 3 vertices, found {}", count));
+                    }
+                    polygon.clear();
+                    for _ in 0..count {
+                        polygon.push(reader.read_index(item_type)?);
+                    }
+                    // Triangulate the polygon as a fan around its first vertex.
+                    for j in 1..polygon.len() - 1 {
+                        mesh.indices.push([polygon[0], polygon[j], polygon[j + 1]]);
+                    }
+                }
+                PropertyKind::List(count_type, item_type) => {
+                    skip_list(reader, count_type, item_type)?
+                }
+                PropertyKind::Scalar(ty) => {
+                    reader.read(ty)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn skip_element(reader: &mut BodyReader, element: &Element) -> Result<(), String> {
+    for _ in 0..element.count {
+        for property in element.properties.iter() {
+            match property.kind {
+                PropertyKind::Scalar(ty) => {
+                    reader.read(ty)?;
+                }
+                PropertyKind::List(count_type, item_type) => {
+                    skip_list(reader, count_type, item_type)?
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn skip_list(
+    reader: &mut BodyReader,
+    count_type: ScalarType,
+    item_type: ScalarType,
+) -> Result<(), String> {
+    let count = reader.read_index(count_type)?;
+    for _ in 0..count {
+        reader.read(item_type)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hit::Hittable;
+    use crate::ray::Ray;
+
+    const QUAD: &str = "ply
+format ascii 1.0
+comment a unit square in the xy plane
+element vertex 4
+property float x
+property float y
+property float z
+property float u
+property float v
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 255 0 0
+1 0 0 1 0 0 255 0
+1 1 0 1 1 0 0 255
+0 1 0 0 1 255 255 255
+4 0 1 2 3
+";
+
+    fn assert_close(a: Color, b: Color) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn parses_ascii_attributes() {
+        let mesh = parse_ply(QUAD.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.uvs[2], (1.0, 1.0));
+        assert_close(mesh.colors[1], Color::new(0.0, 1.0, 0.0));
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn parses_binary_little_endian() {
+        let mut data = b"ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+property float x\nproperty float y\nproperty float z\n\
+element face 1\nproperty list uchar uint vertex_indices\nend_header\n"
+            .to_vec();
+        for p in &[[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.0]] {
+            for c in p {
+                data.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+        data.push(3);
+        for i in 0..3u32 {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        let mesh = parse_ply(&data).unwrap();
+        assert_close(mesh.positions[2], Point3::new(0.0, 2.0, 0.0));
+        assert_eq!(mesh.indices, vec![[0, 1, 2]]);
+        assert!(mesh.into_vertex_colored_mesh().is_none());
+
+        data.truncate(data.len() - 2);
+        assert_eq!(parse_ply(&data).err().unwrap(), "unexpected end of file");
+    }
+
+    #[test]
+    fn end_header_in_a_comment_does_not_end_the_header() {
+        let data = QUAD
+            .replace(
+                "comment a unit square",
+                "comment exported before end_header\nobj_info end_header\ncomment a unit square",
+            )
+            .replace("end_header\n0 0 0", "end_header \r\n0 0 0");
+        let mesh = parse_ply(data.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+
+        let data = "ply\nformat ascii 1.0\ncomment no end_header here\nelement vertex 0\n";
+        assert_eq!(
+            parse_ply(data.as_bytes()).err().unwrap(),
+            "missing end_header"
+        );
+    }
+
+    #[test]
+    fn vertex_colored_mesh_interpolates_colors_and_keeps_uvs() {
+        let mesh = parse_ply(QUAD.as_bytes())
+            .unwrap()
+            .into_vertex_colored_mesh()
+            .unwrap();
+        let r = Ray::new(Point3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let rec = mesh.hit(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.u - 0.75).abs() < 1e-9 && (rec.v - 0.25).abs() < 1e-9);
+        // In the first triangle, 1/4 of vertex 0, 1/2 of vertex 1 and 1/4 of vertex 2.
+        let expected = Color::new(0.25, 0.5, 0.25);
+        assert_close(rec.vertex_color.unwrap(), expected);
+        let scatter = rec.mat_ptr.scatter(&r, &rec).unwrap();
+        assert_close(scatter.attenuation, expected);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+property float y\nproperty float z\nelement face 1\n\
+property list uchar int vertex_indices\nend_header\n";
+        let body = "0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let cases: Vec<(String, &str)> = vec![
+            (
+                format!("{}{}", header.replace("ply\n", "plx\n"), body),
+                "magic",
+            ),
+            (header.replace("end_header\n", ""), "missing end_header"),
+            (
+                format!("{}{}", header.replace("format ascii 1.0\n", ""), body),
+                "missing format",
+            ),
+            (
+                format!("{}{}", header.replace("ascii", "utf8"), body),
+                "unknown format 'utf8'",
+            ),
+            (
+                format!("{}{}", header.replace("vertex 3", "vertex -3"), body),
+                "invalid element count",
+            ),
+            (
+                format!("{}{}", header.replace("float x", "half x"), body),
+                "half",
+            ),
+            (
+                format!("{}{}", header.replace("property float z\n", ""), body),
+                "needs x, y and z",
+            ),
+            (
+                format!("{}{}", header.replace("vertex_indices", "corners"), body),
+                "needs a vertex_indices",
+            ),
+            (
+                format!("{}{}", header, "0 0 0\n1 0 0\n0 1 0\n3 0 1"),
+                "unexpected end of file",
+            ),
+            (
+                format!("{}{}", header, "0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n"),
+                "out of range",
+            ),
+            (
+                format!("{}{}", header, "0 0 0\n1 0 0\n0 1 0\n2 0 1\n"),
+                "at least 3 vertices",
+            ),
+            (
+                format!("{}{}", header, "0 0 0\n1 0 0\n0 1 0\n3 0 -1 2\n"),
+                "invalid index",
+            ),
+            (
+                format!("{}{}", header, "0 0 0\n1 zero 0\n0 1 0\n3 0 1 2\n"),
+                "invalid number 'zero'",
+            ),
+        ];
+        for (data, message) in cases {
+            match parse_ply(data.as_bytes()) {
+                Err(e) => assert!(e.contains(message), "{:?} doesn't mention {:?}", e, message),
+                Ok(_) => panic!("expected an error mentioning {:?}", message),
+            }
+        }
+    }
+
+    #[test]
+    fn load_reports_the_path() {
+        let path = Path::new("/nonexistent/mesh.ply");
+        match load_ply(path) {
+            Err(PlyError::Io(p, _)) => assert_eq!(p, path),
+            _ => panic!("expected an io error"),
+        }
+    }
+}
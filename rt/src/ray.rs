@@ -4,6 +4,8 @@ pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
     pub time: f64,
+    /// Wavelength in nanometres when rendering spectrally, None when rendering in RGB.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -12,9 +14,16 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
         }
     }
 
+    /// The same ray, carrying light of a single wavelength.
+    pub fn with_wavelength(mut self, wavelength: f64) -> Ray {
+        self.wavelength = Some(wavelength);
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         // This works bc I implemented operator overloaders in the vec lib.
         self.origin + self.direction * t
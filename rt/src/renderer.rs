@@ -0,0 +1,188 @@
+use crate::camera::{Camera, CameraSettings};
+use crate::environment::EnvironmentPtr;
+use crate::flat_bvh::FlatBvh;
+use crate::framebuffer::Framebuffer;
+use crate::hit::{Hittable, HittableList};
+use crate::integrator::{IntegratorPtr, PathTracer};
+use crate::light::LightList;
+use crate::scene::Scene;
+use crate::scheduler::{TileOrder, TileScheduler};
+use rand::Rng;
+use std::sync::Arc;
+use vec3::{Color, Point3, Vec3};
+
+/// The scene as integrators see it: the objects behind an acceleration structure, the
+/// lights that can be sampled and what rays that escape see.
+pub struct RenderScene {
+    pub world: FlatBvh,
+    pub lights: HittableList,
+    pub delta_lights: LightList,
+    pub background: Color,
+    /// Replaces `background` when set, and is sampled along with `lights`.
+    pub environment: Option<EnvironmentPtr>,
+}
+
+impl RenderScene {
+    /// Builds the acceleration structure for objects moving during `time0..time1`.
+    pub fn new(scene: &Scene, time0: f64, time1: f64) -> Self {
+        RenderScene {
+            world: FlatBvh::new(&scene.world, time0, time1),
+            lights: HittableList::new(scene.lights.objects().to_vec()),
+            delta_lights: scene.delta_lights.clone(),
+            background: scene.background,
+            environment: scene.environment.clone(),
+        }
+    }
+
+    /// Radiance arriving along a ray that leaves the scene in `direction`.
+    pub fn escaped_radiance(&self, direction: &Vec3) -> Color {
+        match &self.environment {
+            Some(environment) => environment.radiance(direction),
+            None => self.background,
+        }
+    }
+
+    /// Whether there's anything for `sample_light` to sample.
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty() || self.environment.is_some()
+    }
+
+    /// Samples a direction from `origin` towards the lights, as they are at `time`, or into
+    /// the environment.
+    pub fn sample_light(&self, origin: &Point3, time: f64) -> Vec3 {
+        let mut rng = vec3::rng::thread_rng();
+        match &self.environment {
+            Some(environment) if rng.gen::<f64>() < self.environment_probability() => {
+                environment.sample(rng.gen(), rng.gen()).0
+            }
+            _ => self.lights.random(origin, time),
+        }
+    }
+
+    /// Density of `sample_light` picking `direction` from `origin` at `time`.
+    pub fn light_pdf(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let environment_probability = self.environment_probability();
+        let environment_pdf = match &self.environment {
+            Some(environment) => environment_probability * environment.pdf(direction),
+            None => 0.0,
+        };
+        let lights_pdf = if self.lights.is_empty() {
+            0.0
+        } else {
+            (1.0 - environment_probability) * self.lights.pdf_value(origin, direction, time)
+        };
+        environment_pdf + lights_pdf
+    }
+
+    /// The environment and the lights are picked equally often when both are there.
+    fn environment_probability(&self) -> f64 {
+        match (&self.environment, self.lights.is_empty()) {
+            (None, _) => 0.0,
+            (Some(_), true) => 1.0,
+            (Some(_), false) => 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    /// Number of worker threads.
+    pub threads: usize,
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    /// Makes the render reproducible when set.
+    pub seed: Option<u64>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 640,
+            height: 360,
+            samples_per_pixel: 100,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            tile_size: 32,
+            tile_order: TileOrder::Spiral,
+            seed: None,
+        }
+    }
+}
+
+/// Renders a scene into a linear float framebuffer, see `Framebuffer::to_rgb_image` to
+/// get something that can be saved.
+pub struct Renderer {
+    scene: RenderScene,
+    camera: Camera,
+    settings: RenderSettings,
+    integrator: IntegratorPtr,
+}
+
+impl Renderer {
+    /// Renders with a `PathTracer`. The aspect ratio of the camera is replaced by the one of
+    /// the image so pixels stay square.
+    pub fn new(scene: &Scene, camera: &CameraSettings, settings: RenderSettings) -> Self {
+        let camera = CameraSettings {
+            aspect_ratio: settings.width as f64 / settings.height as f64,
+            ..*camera
+        };
+        Renderer {
+            scene: RenderScene::new(scene, camera.t0, camera.t1),
+            camera: Camera::new_from_settings(&camera),
+            settings,
+            integrator: Arc::new(PathTracer::default()),
+        }
+    }
+
+    pub fn with_integrator(mut self, integrator: IntegratorPtr) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
+    pub fn scene(&self) -> &RenderScene {
+        &self.scene
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn render(&self) -> Framebuffer {
+        self.render_with_progress(|_, _| {})
+    }
+
+    /// Like `render`, but calls `progress(finished_tiles, total_tiles)` after every tile.
+    pub fn render_with_progress<P>(&self, progress: P) -> Framebuffer
+    where
+        P: Fn(usize, usize) + Sync,
+    {
+        let settings = &self.settings;
+        let mut scheduler =
+            TileScheduler::new(settings.threads, settings.tile_size, settings.tile_order);
+        if let Some(seed) = settings.seed {
+            scheduler = scheduler.with_seed(seed);
+        }
+
+        let framebuffer = Framebuffer::new(settings.width, settings.height);
+        scheduler.render_with_progress(&framebuffer, |x, y| self.render_pixel(x, y), progress);
+        framebuffer
+    }
+
+    /// Averages `samples_per_pixel` jittered samples of the pixel, with row 0 at the top.
+    pub fn render_pixel(&self, x: u32, y: u32) -> Color {
+        let settings = &self.settings;
+        let mut rng = vec3::rng::thread_rng();
+        // Since the camera's origin is the bottom left corner we need to inverse y
+        let j = settings.height - 1 - y;
+        let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        for _s in 0..settings.samples_per_pixel {
+            let u = (x as f64 + rng.gen::<f64>()) / (settings.width - 1).max(1) as f64;
+            let v = (j as f64 + rng.gen::<f64>()) / (settings.height - 1).max(1) as f64;
+            let r = self.camera.get_ray(u, v);
+            pixel_color += self.integrator.radiance(&r, &self.scene);
+        }
+        pixel_color / settings.samples_per_pixel as f64
+    }
+}
@@ -0,0 +1,1549 @@
+//! Loader for TOML scene description files.
+//!
+//! A scene file has a `[camera]` table with the parameters of `Camera::new`, an optional
+//! `background` color or `[environment]` and named `[textures.*]`, `[materials.*]`,
+//! `[transforms.*]` and `[objects.*]` tables. Everything in the `world` array is added to
+//! the scene, either defined inline or as an `instance` of a named object, which shares its
+//! geometry.
+//!
+//! ```toml
+//! background = [0.0, 0.0, 0.0]
+//!
+//! [camera]
+//! lookfrom = [278.0, 278.0, -800.0]
+//! lookat = [278.0, 278.0, 0.0]
+//! vfov = 40.0
+//!
+//! [materials.white]
+//! type = "lambertian"
+//! color = [0.73, 0.73, 0.73]
+//!
+//! [objects.tall_box]
+//! type = "cube"
+//! p0 = [0.0, 0.0, 0.0]
+//! p1 = [165.0, 330.0, 165.0]
+//! material = "white"
+//!
+//! [[world]]
+//! type = "instance"
+//! object = "tall_box"
+//! transforms = [{ rotate_y = 15.0 }, { translate = [265.0, 0.0, 295.0] }]
+//! ```
+//!
+//! Texture types: `solid`, `checker`, `noise`, `image`.
+//! Material types: `lambertian`, `metal`, `conductor`, `dielectric`, `rough_dielectric`,
+//! `principled`, `diffuse_light`, `isotropic`.
+//! Object types: `sphere`, `moving_sphere`, `xy_rect`, `xz_rect`, `yz_rect`, `cube`,
+//! `triangle`, `obj`, `ply`, `constant_medium`, `heterogeneous_medium` and `instance`.
+//! Materials and objects with a field their type doesn't use are rejected.
+//! Relative file paths are resolved against the directory of the scene file.
+//!
+//! Transform steps are `translate`, `rotate_x`, `rotate_y` and `rotate_z` in degrees,
+//! per-axis `scale` and a `matrix` given as four rows, the last one `[0, 0, 0, 1]`. Steps
+//! apply in order and the chain is folded into one matrix.
+//!
+//! Any object can be animated with `keyframes`, an array of poses at a `time`, each with an
+//! optional `translate`, `rotate` as angles around x, then y, then z in degrees, and
+//! per-axis `scale`. Poses are interpolated between keyframes and applied after the
+//! transforms, and the object is motion blurred over the camera's `time0` to `time1`.
+//!
+//! A `heterogeneous_medium` fills its `boundary` with a `field`, either `{ type = "perlin",
+//! frequency, octaves }`, `{ type = "texture", texture }` naming a 3D texture or `{ type =
+//! "voxels", path }` loading a NRRD file. Voxel grids need no boundary. The field,
+//! scaled by `density`, multiplies the `absorption` and `scattering` coefficients, which
+//! default to 0 and 1. `color` tints the scattered light and absorbing parts glow with
+//! `emission`.
+//!
+//! Both kinds of media scatter uniformly unless given a `phase`: `{ type = "isotropic" }`,
+//! `{ type = "henyey_greenstein", g }`, `{ type = "double_henyey_greenstein", g1, g2,
+//! weight }` with the weight of the first lobe, or `{ type = "rayleigh" }`.
+//!
+//! Spheres, rects, cubes, triangles and meshes with an emissive material are also collected
+//! in `Scene::lights`, so integrators can sample them directly, transformed and animated
+//! along with the object.
+//!
+//! The `[[lights]]` array adds lights without geometry. Light types: `point` with a
+//! `position` and an `intensity`, `spot` which also has a `direction`, an `outer_angle` in
+//! degrees, an optional `inner_angle` and a `falloff` exponent between them, and
+//! `directional` with the `direction` the light travels in and its `irradiance`.
+//!
+//! Environment types: `image`, an equirectangular `.hdr` or `.exr` file given by `path` with
+//! an optional `rotation` in degrees around the y axis, and `sky`, a daylight sky with
+//! optional `sun_direction`, `turbidity` between 1.7 and 10, `ground_albedo` and
+//! `sun_angular_diameter` in degrees. Both take an `intensity` multiplier.
+
+use crate::camera::CameraSettings;
+use crate::density::{DensityPtr, PerlinDensity, TextureDensity};
+use crate::environment::{EnvironmentMap, EnvironmentPtr};
+use crate::hit::{HittableList, HittablePtr};
+use crate::light::{DirectionalLight, LightList, LightPtr, PointLight, SpotLight};
+use crate::material::{
+    Conductor, ConductorPreset, Dielectric, DiffuseLight, Isotropic, Lambertian, MaterialPtr,
+    Metal, Principled, PrincipledParameters, RoughDielectric,
+};
+use crate::obj::load_obj;
+use crate::objects::{
+    ConstantMedium, Cube, HeterogeneousMedium, MovingSphere, Sphere, Triangle, XyRect, XzRect,
+    YzRect,
+};
+use crate::phase::{
+    DoubleHenyeyGreenstein, HenyeyGreenstein, IsotropicPhase, PhaseFunctionPtr, Rayleigh,
+};
+use crate::ply::load_ply;
+use crate::sky::{Sky, SkyParameters, TURBIDITY_RANGE};
+use crate::spectrum::Dispersion;
+use crate::texture::{CheckerPattern, ImageTexture, NoiseTexture, SolidColor, TexturePtr};
+use crate::transform::{AnimatedTransform, Keyframe, Transform};
+use crate::voxel::load_nrrd;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::Spanned;
+use vec3::{Color, Mat4, Quat, Vec3};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            SceneError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io(_, err) => Some(err),
+            SceneError::Parse { .. } => None,
+        }
+    }
+}
+
+/// A loaded scene: the objects to render and how to look at them.
+pub struct Scene {
+    pub world: HittableList,
+    /// The emissive objects of the world that can be sampled directly.
+    pub lights: HittableList,
+    /// Point, spot and directional lights, which have no geometry.
+    pub delta_lights: LightList,
+    pub camera: CameraSettings,
+    pub background: Color,
+    /// Lights the scene instead of `background` when set.
+    pub environment: Option<EnvironmentPtr>,
+}
+
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    parse_scene(&source, path)
+}
+
+/// Parses a scene from a string. `path` is used for error messages and to resolve the
+/// relative paths of images and meshes.
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let file: SceneFile = toml::from_str(source).map_err(|e| {
+        let (line, column) = e.line_col().unwrap_or((0, 0));
+        SceneError::Parse {
+            path: path.to_path_buf(),
+            line: line + 1,
+            column: column + 1,
+            // The toml error message already ends with the position, which we report ourselves.
+            message: e
+                .to_string()
+                .split(" at line ")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        }
+    })?;
+
+    let mut builder = SceneBuilder {
+        file: &file,
+        source,
+        path,
+        base_dir: path.parent().unwrap_or_else(|| Path::new("")),
+        textures: HashMap::new(),
+        materials: HashMap::new(),
+        objects: HashMap::new(),
+        resolving: Vec::new(),
+    };
+
+    let mut world = HittableList::default();
+    let mut lights = HittableList::default();
+    for desc in file.world.iter() {
+        let (object, light) = builder.object(desc)?;
+        world.add(object);
+        if let Some(light) = light {
+            lights.add(light);
+        }
+    }
+
+    let mut delta_lights = LightList::default();
+    for desc in file.lights.iter() {
+        delta_lights.add(builder.light(desc)?);
+    }
+
+    let environment = match &file.environment {
+        Some(desc) => Some(builder.environment(desc)?),
+        None => None,
+    };
+
+    let c = &file.camera;
+    let camera = CameraSettings {
+        lookfrom: vec(c.lookfrom),
+        lookat: vec(c.lookat),
+        vup: vec(c.vup),
+        vfov: c.vfov,
+        aspect_ratio: c.aspect_ratio,
+        aperture: c.aperture,
+        focus_dist: c.focus_dist,
+        t0: c.time0,
+        t1: c.time1,
+    };
+
+    Ok(Scene {
+        world,
+        lights,
+        delta_lights,
+        camera,
+        background: vec(file.background),
+        environment,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    camera: CameraDesc,
+    #[serde(default)]
+    background: [f64; 3],
+    environment: Option<EnvironmentDesc>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    #[serde(default)]
+    transforms: BTreeMap<String, Vec<TransformStep>>,
+    #[serde(default)]
+    objects: BTreeMap<String, ObjectDesc>,
+    #[serde(default)]
+    world: Vec<ObjectDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    path: Option<Spanned<String>>,
+    /// Degrees around the y axis.
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "default_one")]
+    intensity: f64,
+    sun_direction: Option<[f64; 3]>,
+    turbidity: Option<f64>,
+    ground_albedo: Option<[f64; 3]>,
+    sun_angular_diameter: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    position: Option<[f64; 3]>,
+    direction: Option<[f64; 3]>,
+    intensity: Option<[f64; 3]>,
+    irradiance: Option<[f64; 3]>,
+    inner_angle: Option<f64>,
+    outer_angle: Option<f64>,
+    #[serde(default = "default_one")]
+    falloff: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    #[serde(default = "default_vup")]
+    vup: [f64; 3],
+    vfov: f64,
+    #[serde(default = "default_one")]
+    aspect_ratio: f64,
+    #[serde(default)]
+    aperture: f64,
+    #[serde(default = "default_focus_dist")]
+    focus_dist: f64,
+    #[serde(default)]
+    time0: f64,
+    #[serde(default = "default_one")]
+    time1: f64,
+}
+
+fn default_vup() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn default_one() -> f64 {
+    1.0
+}
+
+fn default_focus_dist() -> f64 {
+    10.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    color: Option<[f64; 3]>,
+    odd: Option<[f64; 3]>,
+    even: Option<[f64; 3]>,
+    odd_texture: Option<Spanned<String>>,
+    even_texture: Option<Spanned<String>>,
+    scale: Option<f64>,
+    path: Option<Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    color: Option<[f64; 3]>,
+    texture: Option<Spanned<String>>,
+    fuzz: Option<f64>,
+    ref_idx: Option<f64>,
+    cauchy: Option<[f64; 2]>,
+    sellmeier_b: Option<[f64; 3]>,
+    sellmeier_c: Option<[f64; 3]>,
+    metal: Option<Spanned<String>>,
+    eta: Option<[f64; 3]>,
+    k: Option<[f64; 3]>,
+    roughness: Option<f64>,
+    roughness_texture: Option<Spanned<String>>,
+    roughness_u: Option<f64>,
+    roughness_v: Option<f64>,
+    metallic: Option<f64>,
+    metallic_texture: Option<Spanned<String>>,
+    specular: Option<f64>,
+    specular_texture: Option<Spanned<String>>,
+    specular_tint: Option<f64>,
+    specular_tint_texture: Option<Spanned<String>>,
+    sheen: Option<f64>,
+    sheen_texture: Option<Spanned<String>>,
+    sheen_tint: Option<f64>,
+    sheen_tint_texture: Option<Spanned<String>>,
+    clearcoat: Option<f64>,
+    clearcoat_texture: Option<Spanned<String>>,
+    clearcoat_gloss: Option<f64>,
+    clearcoat_gloss_texture: Option<Spanned<String>>,
+    subsurface: Option<f64>,
+    subsurface_texture: Option<Spanned<String>>,
+    transmission: Option<f64>,
+    transmission_texture: Option<Spanned<String>>,
+    ior: Option<f64>,
+    ior_texture: Option<Spanned<String>>,
+}
+
+/// One step of a transform chain, exactly one of the fields must be set.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformStep {
+    translate: Option<[f64; 3]>,
+    rotate_x: Option<f64>,
+    rotate_y: Option<f64>,
+    rotate_z: Option<f64>,
+    scale: Option<[f64; 3]>,
+    matrix: Option<[[f64; 4]; 4]>,
+}
+
+impl TransformStep {
+    /// The matrix of the step, or None unless exactly one field is set.
+    fn matrix(&self) -> Option<Mat4> {
+        let steps = [
+            self.translate.map(|offset| Mat4::translation(vec(offset))),
+            self.rotate_x
+                .map(|angle| Mat4::rotation(Vec3::new(1.0, 0.0, 0.0), angle.to_radians())),
+            self.rotate_y
+                .map(|angle| Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angle.to_radians())),
+            self.rotate_z
+                .map(|angle| Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), angle.to_radians())),
+            self.scale.map(|factors| Mat4::scaling(vec(factors))),
+            self.matrix.map(Mat4::new),
+        ];
+        let mut set = steps.iter().flatten();
+        match (set.next(), set.next()) {
+            (Some(matrix), None) => Some(*matrix),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    material: Option<Spanned<String>>,
+    center: Option<[f64; 3]>,
+    center0: Option<[f64; 3]>,
+    center1: Option<[f64; 3]>,
+    time0: Option<f64>,
+    time1: Option<f64>,
+    radius: Option<f64>,
+    x0: Option<f64>,
+    x1: Option<f64>,
+    y0: Option<f64>,
+    y1: Option<f64>,
+    z0: Option<f64>,
+    z1: Option<f64>,
+    k: Option<f64>,
+    p0: Option<[f64; 3]>,
+    p1: Option<[f64; 3]>,
+    v0: Option<[f64; 3]>,
+    v1: Option<[f64; 3]>,
+    v2: Option<[f64; 3]>,
+    path: Option<Spanned<String>>,
+    boundary: Option<Spanned<String>>,
+    density: Option<f64>,
+    color: Option<[f64; 3]>,
+    texture: Option<Spanned<String>>,
+    absorption: Option<f64>,
+    scattering: Option<f64>,
+    emission: Option<[f64; 3]>,
+    emission_texture: Option<Spanned<String>>,
+    field: Option<FieldDesc>,
+    phase: Option<PhaseDesc>,
+    object: Option<Spanned<String>>,
+    transform: Option<Spanned<String>>,
+    #[serde(default)]
+    transforms: Vec<TransformStep>,
+    #[serde(default)]
+    keyframes: Vec<KeyframeDesc>,
+}
+
+/// A pose of an animated object.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    translate: Option<[f64; 3]>,
+    rotate: Option<[f64; 3]>,
+    scale: Option<[f64; 3]>,
+}
+
+/// How a medium scatters light.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PhaseDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    g: Option<f64>,
+    g1: Option<f64>,
+    g2: Option<f64>,
+    weight: Option<f64>,
+}
+
+/// Where the density of a heterogeneous medium comes from.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FieldDesc {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    texture: Option<Spanned<String>>,
+    frequency: Option<f64>,
+    octaves: Option<u8>,
+    path: Option<Spanned<String>>,
+}
+
+fn vec(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// The names of the listed optional fields that are set.
+macro_rules! set_fields {
+    ($desc:expr, $($field:ident),* $(,)?) => {{
+        let mut fields = Vec::new();
+        $(
+            if $desc.$field.is_some() {
+                fields.push(stringify!($field));
+            }
+        )*
+        fields
+    }};
+}
+
+impl ObjectDesc {
+    /// The fields that only some types of object take.
+    fn typed_fields(&self) -> Vec<&'static str> {
+        set_fields!(
+            self,
+            material,
+            center,
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            x0,
+            x1,
+            y0,
+            y1,
+            z0,
+            z1,
+            k,
+            p0,
+            p1,
+            v0,
+            v1,
+            v2,
+            path,
+            boundary,
+            density,
+            color,
+            texture,
+            absorption,
+            scattering,
+            emission,
+            emission_texture,
+            field,
+            phase,
+            object,
+        )
+    }
+
+    /// The fields `typed_fields` may return for a type, or None for unknown types.
+    fn allowed_fields(kind: &str) -> Option<&'static [&'static str]> {
+        Some(match kind {
+            "sphere" => &["material", "center", "radius"],
+            "moving_sphere" => &["material", "center0", "center1", "time0", "time1", "radius"],
+            "xy_rect" => &["material", "x0", "x1", "y0", "y1", "k"],
+            "xz_rect" => &["material", "x0", "x1", "z0", "z1", "k"],
+            "yz_rect" => &["material", "y0", "y1", "z0", "z1", "k"],
+            "cube" => &["material", "p0", "p1"],
+            "triangle" => &["material", "v0", "v1", "v2"],
+            "obj" | "ply" => &["material", "path"],
+            "constant_medium" => &["boundary", "density", "color", "texture", "phase"],
+            "heterogeneous_medium" => &[
+                "field",
+                "boundary",
+                "density",
+                "color",
+                "texture",
+                "absorption",
+                "scattering",
+                "emission",
+                "emission_texture",
+                "phase",
+            ],
+            "instance" => &["object"],
+            _ => return None,
+        })
+    }
+}
+
+impl MaterialDesc {
+    /// The fields that only some types of material take.
+    fn typed_fields(&self) -> Vec<&'static str> {
+        set_fields!(
+            self,
+            color,
+            texture,
+            fuzz,
+            ref_idx,
+            cauchy,
+            sellmeier_b,
+            sellmeier_c,
+            metal,
+            eta,
+            k,
+            roughness,
+            roughness_texture,
+            roughness_u,
+            roughness_v,
+            metallic,
+            metallic_texture,
+            specular,
+            specular_texture,
+            specular_tint,
+            specular_tint_texture,
+            sheen,
+            sheen_texture,
+            sheen_tint,
+            sheen_tint_texture,
+            clearcoat,
+            clearcoat_texture,
+            clearcoat_gloss,
+            clearcoat_gloss_texture,
+            subsurface,
+            subsurface_texture,
+            transmission,
+            transmission_texture,
+            ior,
+            ior_texture,
+        )
+    }
+
+    /// The fields `typed_fields` may return for a type, or None for unknown types.
+    fn allowed_fields(kind: &str) -> Option<&'static [&'static str]> {
+        Some(match kind {
+            "lambertian" | "diffuse_light" | "isotropic" => &["color", "texture"],
+            "metal" => &["color", "fuzz"],
+            "conductor" => &[
+                "metal",
+                "eta",
+                "k",
+                "roughness",
+                "roughness_u",
+                "roughness_v",
+            ],
+            "dielectric" => &["ref_idx", "cauchy", "sellmeier_b", "sellmeier_c"],
+            "rough_dielectric" => &["ref_idx", "roughness", "roughness_texture"],
+            "principled" => &[
+                "color",
+                "texture",
+                "metallic",
+                "metallic_texture",
+                "roughness",
+                "roughness_texture",
+                "specular",
+                "specular_texture",
+                "specular_tint",
+                "specular_tint_texture",
+                "sheen",
+                "sheen_texture",
+                "sheen_tint",
+                "sheen_tint_texture",
+                "clearcoat",
+                "clearcoat_texture",
+                "clearcoat_gloss",
+                "clearcoat_gloss_texture",
+                "subsurface",
+                "subsurface_texture",
+                "transmission",
+                "transmission_texture",
+                "ior",
+                "ior_texture",
+            ],
+            _ => return None,
+        })
+    }
+}
+
+/// An object and, when it can be sampled as a light, the part of it that emits.
+type BuiltObject = (HittablePtr, Option<HittablePtr>);
+
+/// Turns the deserialized descriptions into objects, resolving names on first use.
+struct SceneBuilder<'a> {
+    file: &'a SceneFile,
+    source: &'a str,
+    path: &'a Path,
+    base_dir: &'a Path,
+    textures: HashMap<String, TexturePtr>,
+    materials: HashMap<String, MaterialPtr>,
+    objects: HashMap<String, BuiltObject>,
+    /// Names currently being resolved, to detect reference cycles.
+    resolving: Vec<String>,
+}
+
+impl<'a> SceneBuilder<'a> {
+    /// Creates an error pointing at the given span of the source.
+    fn error(&self, span: (usize, usize), message: String) -> SceneError {
+        let before = &self.source[..span.0.min(self.source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        SceneError::Parse {
+            path: self.path.to_path_buf(),
+            line,
+            column,
+            message,
+        }
+    }
+
+    fn missing(&self, kind: &Spanned<String>, field: &str) -> SceneError {
+        self.error(
+            kind.span(),
+            format!("'{}' needs a '{}' field", kind.get_ref(), field),
+        )
+    }
+
+    fn required<T>(
+        &self,
+        value: Option<T>,
+        kind: &Spanned<String>,
+        field: &str,
+    ) -> Result<T, SceneError> {
+        value.ok_or_else(|| self.missing(kind, field))
+    }
+
+    /// Rejects fields that the type doesn't use, which would otherwise be silently ignored.
+    fn check_fields(
+        &self,
+        kind: &Spanned<String>,
+        fields: &[&str],
+        allowed: Option<&[&str]>,
+    ) -> Result<(), SceneError> {
+        let allowed = match allowed {
+            Some(allowed) => allowed,
+            // Unknown types are reported when they're built.
+            None => return Ok(()),
+        };
+        match fields.iter().find(|field| !allowed.contains(field)) {
+            Some(field) => Err(self.error(
+                kind.span(),
+                format!("'{}' doesn't take a '{}' field", kind.get_ref(), field),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn enter(&mut self, name: &Spanned<String>) -> Result<(), SceneError> {
+        if self.resolving.contains(name.get_ref()) {
+            return Err(self.error(
+                name.span(),
+                format!("'{}' refers to itself", name.get_ref()),
+            ));
+        }
+        self.resolving.push(name.get_ref().clone());
+        Ok(())
+    }
+
+    fn texture(&mut self, name: &Spanned<String>) -> Result<TexturePtr, SceneError> {
+        if let Some(texture) = self.textures.get(name.get_ref()) {
+            return Ok(Arc::clone(texture));
+        }
+        let file = self.file;
+        let desc = file.textures.get(name.get_ref()).ok_or_else(|| {
+            self.error(name.span(), format!("unknown texture '{}'", name.get_ref()))
+        })?;
+
+        self.enter(name)?;
+        let texture = self.build_texture(desc)?;
+        self.resolving.pop();
+
+        self.textures
+            .insert(name.get_ref().clone(), Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    fn build_texture(&mut self, desc: &TextureDesc) -> Result<TexturePtr, SceneError> {
+        let kind = &desc.kind;
+        Ok(match kind.get_ref().as_str() {
+            "solid" => {
+                let color = desc.color.ok_or_else(|| self.missing(kind, "color"))?;
+                Arc::new(SolidColor::new_from_color(vec(color)))
+            }
+            "checker" => {
+                let odd = self.texture_or_color(kind, &desc.odd_texture, desc.odd, "odd")?;
+                let even = self.texture_or_color(kind, &desc.even_texture, desc.even, "even")?;
+                Arc::new(CheckerPattern::new_from_textures(odd, even))
+            }
+            "noise" => Arc::new(NoiseTexture::new(desc.scale.unwrap_or(1.0))),
+            "image" => {
+                let path = desc
+                    .path
+                    .as_ref()
+                    .ok_or_else(|| self.missing(kind, "path"))?;
+                let texture =
+                    ImageTexture::try_new_from_filename(&self.base_dir.join(path.get_ref()))
+                        .map_err(|e| {
+                            self.error(
+                                path.span(),
+                                format!("can't load image '{}': {}", path.get_ref(), e),
+                            )
+                        })?;
+                Arc::new(texture)
+            }
+            other => {
+                return Err(self.error(kind.span(), format!("unknown texture type '{}'", other)))
+            }
+        })
+    }
+
+    /// Resolves a pair of `<field>_texture` and `<field>` fields, preferring the texture.
+    fn texture_or_color(
+        &mut self,
+        kind: &Spanned<String>,
+        texture: &Option<Spanned<String>>,
+        color: Option<[f64; 3]>,
+        field: &str,
+    ) -> Result<TexturePtr, SceneError> {
+        match (texture, color) {
+            (Some(name), _) => self.texture(name),
+            (None, Some(color)) => Ok(Arc::new(SolidColor::new_from_color(vec(color)))),
+            (None, None) => Err(self.missing(kind, field)),
+        }
+    }
+
+    fn phase_function(&self, desc: &PhaseDesc) -> Result<PhaseFunctionPtr, SceneError> {
+        let kind = &desc.kind;
+        Ok(match kind.get_ref().as_str() {
+            "isotropic" => Arc::new(IsotropicPhase),
+            "henyey_greenstein" => {
+                Arc::new(HenyeyGreenstein::new(self.required(desc.g, kind, "g")?))
+            }
+            "double_henyey_greenstein" => Arc::new(DoubleHenyeyGreenstein::new(
+                self.required(desc.g1, kind, "g1")?,
+                self.required(desc.g2, kind, "g2")?,
+                self.required(desc.weight, kind, "weight")?,
+            )),
+            "rayleigh" => Arc::new(Rayleigh),
+            other => return Err(self.error(kind.span(), format!("unknown phase type '{}'", other))),
+        })
+    }
+
+    fn density_field(&mut self, desc: &FieldDesc) -> Result<DensityPtr, SceneError> {
+        let kind = &desc.kind;
+        Ok(match kind.get_ref().as_str() {
+            "texture" => {
+                let name = self.required(desc.texture.as_ref(), kind, "texture")?;
+                Arc::new(TextureDensity::new(self.texture(name)?))
+            }
+            "perlin" => Arc::new(PerlinDensity::new(
+                desc.frequency.unwrap_or(1.0),
+                desc.octaves.unwrap_or(5),
+            )),
+            "voxels" => {
+                let path = self.required(desc.path.as_ref(), kind, "path")?;
+                let grid = load_nrrd(&self.base_dir.join(path.get_ref()))
+                    .map_err(|e| self.error(path.span(), e.to_string()))?;
+                Arc::new(grid)
+            }
+            other => return Err(self.error(kind.span(), format!("unknown field type '{}'", other))),
+        })
+    }
+
+    fn environment(&self, desc: &EnvironmentDesc) -> Result<EnvironmentPtr, SceneError> {
+        let kind = &desc.kind;
+        Ok(match kind.get_ref().as_str() {
+            "image" => {
+                let path = self.required(desc.path.as_ref(), kind, "path")?;
+                let environment = EnvironmentMap::load(&self.base_dir.join(path.get_ref()))
+                    .map_err(|e| {
+                        self.error(
+                            path.span(),
+                            format!("can't load environment map '{}': {}", path.get_ref(), e),
+                        )
+                    })?;
+                Arc::new(
+                    environment
+                        .with_rotation(desc.rotation)
+                        .with_intensity(desc.intensity),
+                )
+            }
+            "sky" => {
+                let defaults = SkyParameters::default();
+                let sun_direction = desc.sun_direction.map_or(defaults.sun_direction, vec);
+                if sun_direction.length_squared() == 0.0 {
+                    return Err(
+                        self.error(kind.span(), "sky 'sun_direction' can't be zero".to_string())
+                    );
+                }
+                let turbidity = desc.turbidity.unwrap_or(defaults.turbidity);
+                if !TURBIDITY_RANGE.contains(&turbidity) {
+                    return Err(self.error(
+                        kind.span(),
+                        format!(
+                            "sky 'turbidity' must be between {} and {}, found {}",
+                            TURBIDITY_RANGE.start(),
+                            TURBIDITY_RANGE.end(),
+                            turbidity
+                        ),
+                    ));
+                }
+                Arc::new(Sky::new(SkyParameters {
+                    sun_direction,
+                    turbidity,
+                    ground_albedo: desc.ground_albedo.map_or(defaults.ground_albedo, vec),
+                    sun_angular_diameter: desc
+                        .sun_angular_diameter
+                        .unwrap_or(defaults.sun_angular_diameter),
+                    intensity: desc.intensity,
+                }))
+            }
+            other => {
+                return Err(self.error(kind.span(), format!("unknown environment type '{}'", other)))
+            }
+        })
+    }
+
+    fn light(&self, desc: &LightDesc) -> Result<LightPtr, SceneError> {
+        let kind = &desc.kind;
+        Ok(match kind.get_ref().as_str() {
+            "point" => Arc::new(PointLight::new(
+                self.required(desc.position.map(vec), kind, "position")?,
+                self.required(desc.intensity.map(vec), kind, "intensity")?,
+            )),
+            "spot" => {
+                let outer_angle = self.required(desc.outer_angle, kind, "outer_angle")?;
+                Arc::new(SpotLight::new(
+                    self.required(desc.position.map(vec), kind, "position")?,
+                    self.required(desc.direction.map(vec), kind, "direction")?,
+                    self.required(desc.intensity.map(vec), kind, "intensity")?,
+                    desc.inner_angle.unwrap_or(outer_angle),
+                    outer_angle,
+                    desc.falloff,
+                ))
+            }
+            "directional" => Arc::new(DirectionalLight::new(
+                self.required(desc.direction.map(vec), kind, "direction")?,
+                self.required(desc.irradiance.map(vec), kind, "irradiance")?,
+            )),
+            other => return Err(self.error(kind.span(), format!("unknown light type '{}'", other))),
+        })
+    }
+
+    fn material(&mut self, name: &Spanned<String>) -> Result<MaterialPtr, SceneError> {
+        if let Some(material) = self.materials.get(name.get_ref()) {
+            return Ok(Arc::clone(material));
+        }
+        let file = self.file;
+        let desc = file.materials.get(name.get_ref()).ok_or_else(|| {
+            self.error(
+                name.span(),
+                format!("unknown material '{}'", name.get_ref()),
+            )
+        })?;
+
+        let kind = &desc.kind;
+        self.check_fields(
+            kind,
+            &desc.typed_fields(),
+            MaterialDesc::allowed_fields(kind.get_ref()),
+        )?;
+        let material: MaterialPtr = match kind.get_ref().as_str() {
+            "lambertian" => Arc::new(Lambertian::new_from_texture(self.texture_or_color(
+                kind,
+                &desc.texture,
+                desc.color,
+                "color",
+            )?)),
+            "metal" => {
+                let color = desc.color.ok_or_else(|| self.missing(kind, "color"))?;
+                Arc::new(Metal::new(vec(color), desc.fuzz.unwrap_or(0.0)))
+            }
+            "conductor" => self.conductor(desc)?,
+            "principled" => self.principled(desc)?,
+            "dielectric" => self.dielectric(desc)?,
+            "rough_dielectric" => {
+                let ref_idx = desc.ref_idx.ok_or_else(|| self.missing(kind, "ref_idx"))?;
+                let roughness = desc.roughness.map(|r| [r, r, r]);
+                let roughness =
+                    self.texture_or_color(kind, &desc.roughness_texture, roughness, "roughness")?;
+                Arc::new(RoughDielectric::new_from_texture(ref_idx, roughness))
+            }
+            "diffuse_light" => Arc::new(DiffuseLight::new_from_texture(self.texture_or_color(
+                kind,
+                &desc.texture,
+                desc.color,
+                "color",
+            )?)),
+            "isotropic" => Arc::new(Isotropic::new_from_texture(self.texture_or_color(
+                kind,
+                &desc.texture,
+                desc.color,
+                "color",
+            )?)),
+            other => {
+                return Err(self.error(kind.span(), format!("unknown material type '{}'", other)))
+            }
+        };
+
+        self.materials
+            .insert(name.get_ref().clone(), Arc::clone(&material));
+        Ok(material)
+    }
+
+    /// Every parameter of a principled material is optional and can be given either as a
+    /// value or as `<parameter>_texture`. The base color uses `color` and `texture`.
+    fn principled(&mut self, desc: &MaterialDesc) -> Result<MaterialPtr, SceneError> {
+        let defaults = PrincipledParameters::default();
+        let base_color = match (&desc.texture, desc.color) {
+            (None, None) => defaults.base_color,
+            (texture, color) => self.texture_or_color(&desc.kind, texture, color, "color")?,
+        };
+        Ok(Arc::new(Principled::new(PrincipledParameters {
+            base_color,
+            metallic: self.parameter(&desc.metallic_texture, desc.metallic, defaults.metallic)?,
+            roughness: self.parameter(
+                &desc.roughness_texture,
+                desc.roughness,
+                defaults.roughness,
+            )?,
+            specular: self.parameter(&desc.specular_texture, desc.specular, defaults.specular)?,
+            specular_tint: self.parameter(
+                &desc.specular_tint_texture,
+                desc.specular_tint,
+                defaults.specular_tint,
+            )?,
+            sheen: self.parameter(&desc.sheen_texture, desc.sheen, defaults.sheen)?,
+            sheen_tint: self.parameter(
+                &desc.sheen_tint_texture,
+                desc.sheen_tint,
+                defaults.sheen_tint,
+            )?,
+            clearcoat: self.parameter(
+                &desc.clearcoat_texture,
+                desc.clearcoat,
+                defaults.clearcoat,
+            )?,
+            clearcoat_gloss: self.parameter(
+                &desc.clearcoat_gloss_texture,
+                desc.clearcoat_gloss,
+                defaults.clearcoat_gloss,
+            )?,
+            subsurface: self.parameter(
+                &desc.subsurface_texture,
+                desc.subsurface,
+                defaults.subsurface,
+            )?,
+            transmission: self.parameter(
+                &desc.transmission_texture,
+                desc.transmission,
+                defaults.transmission,
+            )?,
+            ior: self.parameter(&desc.ior_texture, desc.ior, defaults.ior)?,
+        })))
+    }
+
+    /// A scalar material parameter given as a texture, a value or not at all.
+    fn parameter(
+        &mut self,
+        texture: &Option<Spanned<String>>,
+        value: Option<f64>,
+        default: TexturePtr,
+    ) -> Result<TexturePtr, SceneError> {
+        match (texture, value) {
+            (Some(name), _) => self.texture(name),
+            (None, Some(value)) => Ok(Arc::new(SolidColor::new_from_rgb(value, value, value))),
+            (None, None) => Ok(default),
+        }
+    }
+
+    /// A dielectric takes a constant `ref_idx`, or a dispersive index of refraction from
+    /// the `cauchy` coefficients `[a, b]` or from `sellmeier_b` and `sellmeier_c`.
+    fn dielectric(&self, desc: &MaterialDesc) -> Result<MaterialPtr, SceneError> {
+        let kind = &desc.kind;
+        let dispersion =
+            match (desc.cauchy, desc.sellmeier_b, desc.sellmeier_c) {
+                (None, None, None) => None,
+                (Some([a, b]), None, None) => Some(Dispersion::Cauchy { a, b }),
+                (None, Some(b), Some(c)) => Some(Dispersion::Sellmeier { b, c }),
+                (None, Some(_), None) => return Err(self.missing(kind, "sellmeier_c")),
+                (None, None, Some(_)) => return Err(self.missing(kind, "sellmeier_b")),
+                (Some(_), _, _) => return Err(self.error(
+                    kind.span(),
+                    "a dielectric takes either 'cauchy' or the sellmeier coefficients, not both"
+                        .to_string(),
+                )),
+            };
+
+        match (dispersion, desc.ref_idx) {
+            (Some(dispersion), None) => Ok(Arc::new(Dielectric::new_dispersive(dispersion))),
+            (None, Some(ref_idx)) => Ok(Arc::new(Dielectric::new(ref_idx))),
+            (None, None) => Err(self.missing(kind, "ref_idx")),
+            (Some(_), Some(_)) => Err(self.error(
+                kind.span(),
+                "a dispersive dielectric doesn't take 'ref_idx'".to_string(),
+            )),
+        }
+    }
+
+    /// A conductor is either a named `metal` or a complex IOR given as `eta` and `k`, with
+    /// `roughness` or a `roughness_u` and `roughness_v` pair for anisotropic metals.
+    fn conductor(&self, desc: &MaterialDesc) -> Result<MaterialPtr, SceneError> {
+        let kind = &desc.kind;
+        let (eta, k) = match (&desc.metal, desc.eta, desc.k) {
+            (Some(metal), None, None) => metal
+                .get_ref()
+                .parse::<ConductorPreset>()
+                .map_err(|e| self.error(metal.span(), e))?
+                .ior(),
+            (None, Some(eta), Some(k)) => (vec(eta), vec(k)),
+            (None, _, None) => return Err(self.missing(kind, "metal")),
+            (None, None, _) => return Err(self.missing(kind, "eta")),
+            (Some(_), _, _) => {
+                return Err(self.error(
+                    kind.span(),
+                    "a conductor takes either 'metal' or 'eta' and 'k', not both".to_string(),
+                ))
+            }
+        };
+
+        let roughness = desc.roughness.unwrap_or(0.0);
+        let roughness_u = desc.roughness_u.unwrap_or(roughness);
+        let roughness_v = desc.roughness_v.unwrap_or(roughness);
+        Ok(Arc::new(Conductor::new_anisotropic(
+            eta,
+            k,
+            roughness_u,
+            roughness_v,
+        )))
+    }
+
+    /// Returns a named object. Every reference to the same name shares one object.
+    fn named_object(&mut self, name: &Spanned<String>) -> Result<BuiltObject, SceneError> {
+        if let Some((object, light)) = self.objects.get(name.get_ref()) {
+            return Ok((Arc::clone(object), light.clone()));
+        }
+        let file = self.file;
+        let desc = file.objects.get(name.get_ref()).ok_or_else(|| {
+            self.error(name.span(), format!("unknown object '{}'", name.get_ref()))
+        })?;
+
+        self.enter(name)?;
+        let object = self.object(desc)?;
+        self.resolving.pop();
+
+        self.objects.insert(name.get_ref().clone(), object.clone());
+        Ok(object)
+    }
+
+    /// Builds an object and wraps it in its transforms: first the named transform, then the
+    /// inline steps, then the keyframes.
+    fn object(&mut self, desc: &ObjectDesc) -> Result<BuiltObject, SceneError> {
+        let mut object = self.shape(desc)?;
+
+        if let Some(name) = &desc.transform {
+            let file = self.file;
+            let steps = file.transforms.get(name.get_ref()).ok_or_else(|| {
+                self.error(
+                    name.span(),
+                    format!("unknown transform '{}'", name.get_ref()),
+                )
+            })?;
+            object = self.apply_transforms(object, steps, &desc.kind)?;
+        }
+        let object = self.apply_transforms(object, &desc.transforms, &desc.kind)?;
+        self.animate(object, &desc.keyframes, &desc.kind)
+    }
+
+    /// Moves an object through its keyframes. Its light moves along with it and is sampled
+    /// in the pose at the time of each shading ray.
+    fn animate(
+        &self,
+        (object, light): BuiltObject,
+        keyframes: &[KeyframeDesc],
+        kind: &Spanned<String>,
+    ) -> Result<BuiltObject, SceneError> {
+        if keyframes.is_empty() {
+            return Ok((object, light));
+        }
+        let keyframes: Vec<Keyframe> = keyframes
+            .iter()
+            .map(|k| {
+                let [x, y, z] = k.rotate.unwrap_or([0.0; 3]);
+                Keyframe::new(
+                    k.time,
+                    k.translate.map_or(Vec3::default(), vec),
+                    Quat::from_euler(x.to_radians(), y.to_radians(), z.to_radians()),
+                    k.scale.map_or(Vec3::new(1.0, 1.0, 1.0), vec),
+                )
+            })
+            .collect();
+        let animate = |object: HittablePtr| -> Option<HittablePtr> {
+            Some(Arc::new(AnimatedTransform::new(object, keyframes.clone())?))
+        };
+        let object = animate(object)
+            .ok_or_else(|| self.error(kind.span(), "keyframes can't scale by zero".to_string()))?;
+        Ok((object, light.and_then(animate)))
+    }
+
+    fn apply_transforms(
+        &self,
+        (mut object, mut light): BuiltObject,
+        steps: &[TransformStep],
+        kind: &Spanned<String>,
+    ) -> Result<BuiltObject, SceneError> {
+        if steps.is_empty() {
+            return Ok((object, light));
+        }
+        // The whole chain becomes a single matrix, applied by one wrapper.
+        let mut matrix = Mat4::identity();
+        for step in steps.iter() {
+            let step = step.matrix().ok_or_else(|| {
+                self.error(
+                    kind.span(),
+                    "every transform step needs exactly one of 'translate', 'rotate_x', \
+                     'rotate_y', 'rotate_z', 'scale' or 'matrix'"
+                        .to_string(),
+                )
+            })?;
+            matrix = step * matrix;
+        }
+        if matrix.m[3] != [0.0, 0.0, 0.0, 1.0] {
+            return Err(self.error(
+                kind.span(),
+                "transform matrices must end with the row [0, 0, 0, 1]".to_string(),
+            ));
+        }
+        let transform = |object: HittablePtr| -> Option<HittablePtr> {
+            Some(Arc::new(Transform::new(object, matrix)?))
+        };
+        object = transform(object)
+            .ok_or_else(|| self.error(kind.span(), "transform can't be inverted".to_string()))?;
+        light = light.and_then(transform);
+        Ok((object, light))
+    }
+
+    /// Builds the untransformed object. Objects that can be sampled are their own light when
+    /// their material is emissive.
+    fn shape(&mut self, desc: &ObjectDesc) -> Result<BuiltObject, SceneError> {
+        let kind = &desc.kind;
+        self.check_fields(
+            kind,
+            &desc.typed_fields(),
+            ObjectDesc::allowed_fields(kind.get_ref()),
+        )?;
+
+        let sampleable: HittablePtr = match kind.get_ref().as_str() {
+            "sphere" => Arc::new(Sphere::new(
+                self.required(desc.center.map(vec), kind, "center")?,
+                self.required(desc.radius, kind, "radius")?,
+                self.object_material(desc)?,
+            )),
+            "moving_sphere" => {
+                let sphere = MovingSphere::new(
+                    self.required(desc.center0.map(vec), kind, "center0")?,
+                    self.required(desc.center1.map(vec), kind, "center1")?,
+                    desc.time0.unwrap_or(0.0),
+                    desc.time1.unwrap_or(1.0),
+                    self.required(desc.radius, kind, "radius")?,
+                    self.object_material(desc)?,
+                );
+                return Ok((Arc::new(sphere), None));
+            }
+            "xy_rect" => Arc::new(XyRect::new(
+                self.required(desc.x0, kind, "x0")?,
+                self.required(desc.x1, kind, "x1")?,
+                self.required(desc.y0, kind, "y0")?,
+                self.required(desc.y1, kind, "y1")?,
+                self.required(desc.k, kind, "k")?,
+                self.object_material(desc)?,
+            )),
+            "xz_rect" => Arc::new(XzRect::new(
+                self.required(desc.x0, kind, "x0")?,
+                self.required(desc.x1, kind, "x1")?,
+                self.required(desc.z0, kind, "z0")?,
+                self.required(desc.z1, kind, "z1")?,
+                self.required(desc.k, kind, "k")?,
+                self.object_material(desc)?,
+            )),
+            "yz_rect" => Arc::new(YzRect::new(
+                self.required(desc.y0, kind, "y0")?,
+                self.required(desc.y1, kind, "y1")?,
+                self.required(desc.z0, kind, "z0")?,
+                self.required(desc.z1, kind, "z1")?,
+                self.required(desc.k, kind, "k")?,
+                self.object_material(desc)?,
+            )),
+            "cube" => Arc::new(Cube::new(
+                self.required(desc.p0.map(vec), kind, "p0")?,
+                self.required(desc.p1.map(vec), kind, "p1")?,
+                self.object_material(desc)?,
+            )),
+            "triangle" => Arc::new(Triangle::new(
+                self.required(desc.v0.map(vec), kind, "v0")?,
+                self.required(desc.v1.map(vec), kind, "v1")?,
+                self.required(desc.v2.map(vec), kind, "v2")?,
+                self.object_material(desc)?,
+            )),
+            "obj" => {
+                let path = desc
+                    .path
+                    .as_ref()
+                    .ok_or_else(|| self.missing(kind, "path"))?;
+                let material = self.object_material(desc)?;
+                let groups = load_obj(&self.base_dir.join(path.get_ref()), material)
+                    .map_err(|e| self.error(path.span(), e.to_string()))?;
+
+                let mut list = HittableList::default();
+                let mut lights = HittableList::default();
+                for group in groups {
+                    if group.mesh.is_emissive() {
+                        lights.add(Arc::clone(&group.mesh) as HittablePtr);
+                    }
+                    list.add(group.mesh);
+                }
+                let lights: Option<HittablePtr> = if lights.is_empty() {
+                    None
+                } else {
+                    Some(Arc::new(lights))
+                };
+                return Ok((Arc::new(list), lights));
+            }
+            "ply" => {
+                let path = desc
+                    .path
+                    .as_ref()
+                    .ok_or_else(|| self.missing(kind, "path"))?;
+                let ply = load_ply(&self.base_dir.join(path.get_ref()))
+                    .map_err(|e| self.error(path.span(), e.to_string()))?;
+                // Without a material the mesh is colored by its vertex colors.
+                let mesh = match &desc.material {
+                    Some(name) => ply.into_mesh(self.material(name)?),
+                    None => ply.into_vertex_colored_mesh().ok_or_else(|| {
+                        self.error(
+                            path.span(),
+                            "PLY file has no vertex colors, the object needs a material"
+                                .to_string(),
+                        )
+                    })?,
+                };
+                let emissive = mesh.is_emissive();
+                let mesh: HittablePtr = Arc::new(mesh);
+                let light = if emissive {
+                    Some(Arc::clone(&mesh))
+                } else {
+                    None
+                };
+                return Ok((mesh, light));
+            }
+            "constant_medium" => {
+                let boundary = desc
+                    .boundary
+                    .as_ref()
+                    .ok_or_else(|| self.missing(kind, "boundary"))?;
+                let (boundary, _) = self.named_object(boundary)?;
+                let density = self.required(desc.density, kind, "density")?;
+                let texture = self.texture_or_color(kind, &desc.texture, desc.color, "color")?;
+                let medium = match &desc.phase {
+                    Some(phase) => ConstantMedium::new_with_phase_function(
+                        boundary,
+                        density,
+                        texture,
+                        self.phase_function(phase)?,
+                    ),
+                    None => ConstantMedium::new_from_texture(boundary, density, texture),
+                };
+                return Ok((Arc::new(medium), None));
+            }
+            "heterogeneous_medium" => {
+                let field = self.required(desc.field.as_ref(), kind, "field")?;
+                let density = self.density_field(field)?;
+                // Fields that vanish outside a box fill it when there's no boundary.
+                let boundary = match (&desc.boundary, density.bounding_box()) {
+                    (Some(name), _) => self.named_object(name)?.0,
+                    (None, Some(bbox)) => Arc::new(Cube::new(
+                        bbox.minimum,
+                        bbox.maximum,
+                        Arc::new(Lambertian::new_from_color(&Color::default())),
+                    )),
+                    (None, None) => return Err(self.missing(kind, "boundary")),
+                };
+                let scale = desc.density.unwrap_or(1.0);
+                let albedo = match (&desc.texture, desc.color) {
+                    (None, None) => Arc::new(SolidColor::new_from_rgb(1.0, 1.0, 1.0)),
+                    (texture, color) => self.texture_or_color(kind, texture, color, "color")?,
+                };
+                let mut medium = HeterogeneousMedium::new(
+                    boundary,
+                    density,
+                    desc.absorption.unwrap_or(0.0) * scale,
+                    desc.scattering.unwrap_or(1.0) * scale,
+                    albedo,
+                    match &desc.phase {
+                        Some(phase) => self.phase_function(phase)?,
+                        None => Arc::new(IsotropicPhase),
+                    },
+                );
+                if desc.emission.is_some() || desc.emission_texture.is_some() {
+                    let emission = self.texture_or_color(
+                        kind,
+                        &desc.emission_texture,
+                        desc.emission,
+                        "emission",
+                    )?;
+                    medium = medium.with_emission(emission);
+                }
+                return Ok((Arc::new(medium), None));
+            }
+            "instance" => {
+                let name = desc
+                    .object
+                    .as_ref()
+                    .ok_or_else(|| self.missing(kind, "object"))?;
+                return self.named_object(name);
+            }
+            other => {
+                return Err(self.error(kind.span(), format!("unknown object type '{}'", other)))
+            }
+        };
+
+        let light = if self.object_material(desc)?.is_emissive() {
+            Some(Arc::clone(&sampleable))
+        } else {
+            None
+        };
+        Ok((sampleable, light))
+    }
+
+    fn object_material(&mut self, desc: &ObjectDesc) -> Result<MaterialPtr, SceneError> {
+        let name = desc
+            .material
+            .as_ref()
+            .ok_or_else(|| self.missing(&desc.kind, "material"))?;
+        self.material(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str =
+        "[camera]\nlookfrom = [0.0, 0.0, 1.0]\nlookat = [0.0, 0.0, 0.0]\nvfov = 40.0\n";
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(&format!("{}{}", CAMERA, source), Path::new("test.toml"))
+    }
+
+    /// The line, counted from the end of the camera table, column and message of an error.
+    fn error(source: &str) -> (usize, usize, String) {
+        match parse(source) {
+            Err(SceneError::Parse {
+                line,
+                column,
+                message,
+                ..
+            }) => (line - CAMERA.lines().count(), column, message),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn loads_every_builtin_scene() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../scenes");
+        let mut count = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension() == Some("toml".as_ref()) {
+                if let Err(e) = load_scene(&path) {
+                    panic!("{}", e);
+                }
+                count += 1;
+            }
+        }
+        assert!(count >= 12);
+    }
+
+    #[test]
+    fn reports_the_position_of_bad_references() {
+        let source = "[materials.red]\ntype = \"lambertian\"\ncolor = [1.0, 0.0, 0.0]\n\n\
+                      [[world]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                      material = \"blue\"\n";
+        let (line, column, message) = error(source);
+        assert_eq!((line, column), (9, 12));
+        assert_eq!(message, "unknown material 'blue'");
+
+        let (line, column, message) = error("[[world]]\ntype = \"instance\"\nobject = \"box\"\n");
+        assert_eq!((line, column), (3, 10));
+        assert_eq!(message, "unknown object 'box'");
+
+        let (line, column, message) = error("[[world]]\ntype = \"cone\"\n");
+        assert_eq!((line, column), (2, 8));
+        assert_eq!(message, "unknown object type 'cone'");
+
+        let (line, _, message) = error("[[world]]\ntype = \"sphere\"\nradius = 1.0\n");
+        assert_eq!(line, 2);
+        assert_eq!(message, "'sphere' needs a 'center' field");
+    }
+
+    #[test]
+    fn reports_the_position_of_syntax_errors() {
+        let (line, column, message) =
+            error("[materials.red]\ntype = \"lambertian\"\ncolor = [1.0, 0.0\n");
+        assert_eq!((line, column), (4, 1));
+        assert_eq!(message, "expected a right bracket, found eof");
+
+        let (line, _, message) = error("[materials.red]\ntype = \"lambertian\"\nshade = 1.0\n");
+        assert_eq!(line, 1);
+        assert!(message.contains("unknown field `shade`"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_reference_cycles() {
+        let source = "[objects.a]\ntype = \"instance\"\nobject = \"b\"\n\n\
+                      [objects.b]\ntype = \"instance\"\nobject = \"a\"\n\n\
+                      [[world]]\ntype = \"instance\"\nobject = \"a\"\n";
+        let (line, column, message) = error(source);
+        assert_eq!((line, column), (7, 10));
+        assert_eq!(message, "'a' refers to itself");
+
+        let source =
+            "[textures.a]\ntype = \"checker\"\nodd_texture = \"a\"\neven = [0.0, 0.0, 0.0]\n\n\
+                      [materials.m]\ntype = \"lambertian\"\ntexture = \"a\"\n\n\
+                      [[world]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                      material = \"m\"\n";
+        let (line, _, message) = error(source);
+        assert_eq!(line, 3);
+        assert_eq!(message, "'a' refers to itself");
+    }
+
+    #[test]
+    fn rejects_fields_of_other_types() {
+        let source = "[[world]]\ntype = \"cube\"\np0 = [0.0, 0.0, 0.0]\np1 = [1.0, 1.0, 1.0]\n\
+                      radius = 2.0\nmaterial = \"m\"\n\n\
+                      [materials.m]\ntype = \"lambertian\"\ncolor = [1.0, 1.0, 1.0]\n";
+        let (line, column, message) = error(source);
+        assert_eq!((line, column), (2, 8));
+        assert_eq!(message, "'cube' doesn't take a 'radius' field");
+
+        let source = "[[world]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+                      material = \"m\"\n\n\
+                      [materials.m]\ntype = \"metal\"\ncolor = [1.0, 1.0, 1.0]\nref_idx = 1.5\n";
+        let (line, _, message) = error(source);
+        assert_eq!(line, 8);
+        assert_eq!(message, "'metal' doesn't take a 'ref_idx' field");
+    }
+
+    #[test]
+    fn rejects_bad_sky_parameters() {
+        let sky = "[environment]\ntype = \"sky\"\n";
+        assert!(parse(&format!("{}sun_direction = [0.0, -1.0, 0.0]\n", sky)).is_ok());
+
+        let (line, column, message) = error(&format!("{}sun_direction = [0.0, 0.0, 0.0]\n", sky));
+        assert_eq!((line, column), (2, 8));
+        assert!(
+            message.contains("'sun_direction' can't be zero"),
+            "{}",
+            message
+        );
+
+        for turbidity in &["1.0", "12.0"] {
+            let (line, _, message) = error(&format!("{}turbidity = {}\n", sky, turbidity));
+            assert_eq!(line, 2);
+            assert!(
+                message.contains("'turbidity' must be between"),
+                "{}",
+                message
+            );
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::framebuffer::Framebuffer;
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+use vec3::Color;
+
+/// The order in which tiles are handed out to the workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Row by row, starting at the top left.
+    Scanline,
+    /// Outwards from the center of the image, so the subject usually shows up first.
+    Spiral,
+    /// Along a Hilbert curve, which keeps consecutive tiles close together.
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!(
+                "unknown tile order '{}', expected scanline, spiral or hilbert",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TileOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileOrder::Scanline => write!(f, "scanline"),
+            TileOrder::Spiral => write!(f, "spiral"),
+            TileOrder::Hilbert => write!(f, "hilbert"),
+        }
+    }
+}
+
+/// A rectangle of pixels, covering `x0..x1` and `y0..y1` with row 0 at the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+/// Splits an image into tiles and renders them on a fixed number of worker threads.
+#[derive(Debug, Clone, Copy)]
+pub struct TileScheduler {
+    threads: usize,
+    tile_size: u32,
+    order: TileOrder,
+    seed: Option<u64>,
+}
+
+impl TileScheduler {
+    pub fn new(threads: usize, tile_size: u32, order: TileOrder) -> Self {
+        assert!(threads > 0, "a scheduler needs at least one worker");
+        assert!(tile_size > 0, "tiles can't be empty");
+        TileScheduler {
+            threads,
+            tile_size,
+            order,
+            seed: None,
+        }
+    }
+
+    /// Reseeds the random number generator of the worker at the start of every tile, so
+    /// the image only depends on the seed and not on the number of workers.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The tiles covering a `width` by `height` image, in render order.
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<Tile> {
+        let columns = width.div_ceil(self.tile_size);
+        let rows = height.div_ceil(self.tile_size);
+
+        let grid = match self.order {
+            TileOrder::Scanline => (0..rows)
+                .flat_map(|ty| (0..columns).map(move |tx| (tx, ty)))
+                .collect(),
+            TileOrder::Spiral => spiral_order(columns, rows),
+            TileOrder::Hilbert => hilbert_order(columns, rows),
+        };
+
+        grid.into_iter()
+            .map(|(tx, ty)| Tile {
+                x0: tx * self.tile_size,
+                y0: ty * self.tile_size,
+                x1: ((tx + 1) * self.tile_size).min(width),
+                y1: ((ty + 1) * self.tile_size).min(height),
+            })
+            .collect()
+    }
+
+    /// Fills the framebuffer with `shade(x, y)` for every pixel.
+    pub fn render<F>(&self, framebuffer: &Framebuffer, shade: F)
+    where
+        F: Fn(u32, u32) -> Color + Sync,
+    {
+        self.render_with_progress(framebuffer, shade, |_, _| {});
+    }
+
+    /// Like `render`, but calls `progress(finished_tiles, total_tiles)` after every tile.
+    pub fn render_with_progress<F, P>(&self, framebuffer: &Framebuffer, shade: F, progress: P)
+    where
+        F: Fn(u32, u32) -> Color + Sync,
+        P: Fn(usize, usize) + Sync,
+    {
+        let tiles = self.tiles(framebuffer.width(), framebuffer.height());
+        let next_tile = AtomicUsize::new(0);
+        let finished_tiles = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| loop {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let tile = match tiles.get(index) {
+                        Some(tile) => tile,
+                        None => break,
+                    };
+
+                    if let Some(seed) = self.seed {
+                        vec3::rng::seed(tile_seed(seed, tile));
+                    }
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            framebuffer.set(x, y, shade(x, y));
+                        }
+                    }
+
+                    let finished = finished_tiles.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress(finished, tiles.len());
+                });
+            }
+        });
+    }
+}
+
+/// Mixes the tile position into the seed so neighbouring tiles and neighbouring seeds
+/// don't end up with overlapping random sequences.
+fn tile_seed(seed: u64, tile: &Tile) -> u64 {
+    let position = ((tile.y0 as u64) << 32) | tile.x0 as u64;
+    seed ^ (position + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// Walks a square spiral around the center tile, skipping positions outside the grid.
+fn spiral_order(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let total = columns as usize * rows as usize;
+    let mut order = Vec::with_capacity(total);
+    let (mut x, mut y) = (((columns as i64) - 1) / 2, ((rows as i64) - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut direction = 0;
+    let mut run = 1;
+
+    let visit = |x: i64, y: i64, order: &mut Vec<(u32, u32)>| {
+        if x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64 {
+            order.push((x as u32, y as u32));
+        }
+    };
+
+    visit(x, y, &mut order);
+    while order.len() < total {
+        // Every run length is walked twice before it grows.
+        for _ in 0..2 {
+            let (dx, dy) = directions[direction];
+            for _ in 0..run {
+                x += dx;
+                y += dy;
+                visit(x, y, &mut order);
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+
+    order
+}
+
+/// Sorts the tiles by their distance along a Hilbert curve covering the grid.
+fn hilbert_order(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    let side = columns.max(rows).next_power_of_two();
+    let mut order: Vec<(u32, u32)> = (0..rows)
+        .flat_map(|ty| (0..columns).map(move |tx| (tx, ty)))
+        .collect();
+    order.sort_by_key(|&(x, y)| hilbert_index(side, x, y));
+    order
+}
+
+/// Position of cell `(x, y)` along the Hilbert curve through a `side` by `side` grid,
+/// where `side` is a power of two.
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0u64;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        index += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve inside it has the right orientation.
+        if ry == 0 {
+            if rx == 1 {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    const ORDERS: [TileOrder; 3] = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let (width, height) = (37, 23);
+        for &order in ORDERS.iter() {
+            let tiles = TileScheduler::new(1, 8, order).tiles(width, height);
+            assert_eq!(tiles.len(), 5 * 3, "{}", order);
+            let mut covered = vec![0; (width * height) as usize];
+            for tile in tiles.iter() {
+                assert!(tile.x0 < tile.x1 && tile.x1 <= width);
+                assert!(tile.y0 < tile.y1 && tile.y1 <= height);
+                for y in tile.y0..tile.y1 {
+                    for x in tile.x0..tile.x1 {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+            }
+            assert!(covered.iter().all(|&count| count == 1), "{}", order);
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_middle() {
+        let tiles = TileScheduler::new(1, 8, TileOrder::Spiral).tiles(40, 24);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (16, 8));
+        let (x, y) = (tiles[1].x0, tiles[1].y0);
+        assert_eq!((x as i64 - 16).abs() + (y as i64 - 8).abs(), 8);
+    }
+
+    #[test]
+    fn hilbert_steps_to_neighbouring_tiles() {
+        let tiles = TileScheduler::new(1, 8, TileOrder::Hilbert).tiles(64, 64);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (0, 0));
+        for pair in tiles.windows(2) {
+            let dx = (pair[0].x0 as i64 - pair[1].x0 as i64).abs();
+            let dy = (pair[0].y0 as i64 - pair[1].y0 as i64).abs();
+            assert_eq!(dx + dy, 8, "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn renders_on_the_given_number_of_workers() {
+        let threads = 3;
+        let framebuffer = Framebuffer::new(64, 64);
+        let workers = Mutex::new(HashSet::new());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        TileScheduler::new(threads, 8, TileOrder::Scanline).render(&framebuffer, |x, y| {
+            let id = thread::current().id();
+            let first = workers.lock().unwrap().insert(id);
+            // Every worker holds on to its first tile until the others have started, so
+            // one of them can't finish the whole image alone.
+            while first && workers.lock().unwrap().len() < threads && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Color::new(x as f64, y as f64, 1.0)
+        });
+        assert_eq!(workers.lock().unwrap().len(), threads);
+        for y in 0..64 {
+            for x in 0..64 {
+                let color = framebuffer.get(x, y);
+                assert_eq!((color.x, color.y, color.z), (x as f64, y as f64, 1.0));
+            }
+        }
+    }
+}
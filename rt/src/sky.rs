@@ -0,0 +1,410 @@
+//! A procedural daylight sky using the analytic model of Preetham, Shirley and Smits, "A
+//! Practical Analytic Model for Daylight" (1999), with a sun disk of finite size.
+
+use crate::environment::{Environment, EnvironmentMap};
+use crate::onb::Onb;
+use crate::spectrum::xyz_to_srgb;
+use std::f64::consts::PI;
+use vec3::{Color, Vec3};
+
+/// Converts the luminances of the model, in kcd/m², to the units of the renderer, so that a
+/// white surface facing a high sun comes out about 1.
+const LUMINANCE_SCALE: f64 = 0.025;
+/// Illuminance of the sun above the atmosphere, in klx.
+const SUN_ILLUMINANCE: f64 = 109.0;
+/// Wavelengths in micrometres standing for the red, green and blue channels.
+const RGB_WAVELENGTHS: [f64; 3] = [0.65, 0.57, 0.475];
+/// Resolution of the table used to importance sample the sky.
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+/// Where the sun sets when it points straight down, or nowhere.
+const HORIZON: Vec3 = Vec3 {
+    x: 0.0,
+    y: 0.0,
+    z: -1.0,
+};
+/// The range of turbidities the model was fitted to.
+pub const TURBIDITY_RANGE: std::ops::RangeInclusive<f64> = 1.7..=10.0;
+
+/// Parameters of `Sky::new`.
+#[derive(Debug, Clone, Copy)]
+pub struct SkyParameters {
+    /// Points towards the sun. It's clamped to the horizon if it's below it, and set on
+    /// it towards -z if it points straight down or is zero.
+    pub sun_direction: Vec3,
+    /// Haziness of the atmosphere, from 2 for a very clear sky to about 10 for a hazy one.
+    /// The model is only meaningful in `TURBIDITY_RANGE`.
+    pub turbidity: f64,
+    /// Reflectance of the ground, which fills the lower half of the sky.
+    pub ground_albedo: Color,
+    /// Apparent diameter of the sun in degrees.
+    pub sun_angular_diameter: f64,
+    /// Scales the brightness of the sky and the sun.
+    pub intensity: f64,
+}
+
+impl Default for SkyParameters {
+    fn default() -> Self {
+        SkyParameters {
+            sun_direction: Vec3::new(0.3, 0.6, -0.5),
+            turbidity: 3.0,
+            ground_albedo: Color::new(0.3, 0.3, 0.3),
+            sun_angular_diameter: 0.53,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// The Perez coefficients of one of the Y, x and y channels, and its value at the zenith.
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    coefficients: [f64; 5],
+    zenith: f64,
+}
+
+impl Channel {
+    fn new(coefficients: [f64; 5], zenith: f64, sun_theta: f64) -> Self {
+        let mut channel = Channel {
+            coefficients,
+            zenith: 1.0,
+        };
+        // Scale the distribution so that it matches the zenith value.
+        channel.zenith = zenith / channel.perez(0.0, sun_theta);
+        channel
+    }
+
+    /// The Perez sky luminance distribution, for a direction at `theta` from the zenith and
+    /// `gamma` from the sun.
+    fn perez(&self, theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.coefficients;
+        let cos_theta = theta.cos().max(0.01);
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+
+    fn value(&self, theta: f64, gamma: f64) -> f64 {
+        self.zenith * self.perez(theta, gamma)
+    }
+}
+
+/// A clear or hazy daylight sky lit by the sun, for outdoor scenes. Directions below the
+/// horizon see a uniformly lit ground.
+///
+/// The sky is importance sampled from a table of its luminance, and the sun by picking
+/// directions uniformly in its disk.
+pub struct Sky {
+    sun_direction: Vec3,
+    luminance: Channel,
+    x: Channel,
+    y: Channel,
+    intensity: f64,
+    ground: Color,
+    sun_radiance: Color,
+    /// Cosine of the angular radius of the sun.
+    sun_cos_radius: f64,
+    sun_frame: Onb,
+    /// The sky without the sun, used for sampling.
+    table: EnvironmentMap,
+    sun_probability: f64,
+}
+
+impl Sky {
+    pub fn new(params: SkyParameters) -> Self {
+        let mut sun_direction = params.sun_direction.unit_vector();
+        // A zero direction normalizes to NaN, which the horizon takes care of as well.
+        if sun_direction.y.is_nan() || sun_direction.y < 0.0 {
+            let horizontal = Vec3::new(sun_direction.x, 0.0, sun_direction.z);
+            sun_direction = if horizontal.length_squared() > 1e-12 {
+                horizontal.unit_vector()
+            } else {
+                HORIZON
+            };
+        }
+        let t = params.turbidity;
+        let sun_theta = sun_direction.y.acos();
+
+        // Zenith values and Perez coefficients from the appendix of the paper.
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * sun_theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let polynomial = |c: [[f64; 4]; 3]| {
+            let theta = [sun_theta.powi(3), sun_theta.powi(2), sun_theta, 1.0];
+            let row = |r: [f64; 4]| (0..4).map(|i| r[i] * theta[i]).sum::<f64>();
+            t * t * row(c[0]) + t * row(c[1]) + row(c[2])
+        };
+        let zenith_x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let luminance_channel = Channel::new(
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            zenith_luminance,
+            sun_theta,
+        );
+        let x_channel = Channel::new(
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            zenith_x,
+            sun_theta,
+        );
+        let y_channel = Channel::new(
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+            zenith_y,
+            sun_theta,
+        );
+
+        let sun_radius = (params.sun_angular_diameter / 2.0).to_radians().max(1e-4);
+        let sun_cos_radius = sun_radius.cos();
+        let sun_solid_angle = 2.0 * PI * (1.0 - sun_cos_radius);
+        let sun_illuminance = sun_transmittance(sun_theta, t) * SUN_ILLUMINANCE;
+
+        let mut sky = Sky {
+            sun_direction,
+            luminance: luminance_channel,
+            x: x_channel,
+            y: y_channel,
+            intensity: params.intensity,
+            ground: Color::default(),
+            sun_radiance: sun_illuminance * (LUMINANCE_SCALE / sun_solid_angle),
+            sun_cos_radius,
+            sun_frame: Onb::new_from_w(&sun_direction),
+            table: EnvironmentMap::new(1, 1, vec![Color::default()]),
+            sun_probability: 0.0,
+        };
+
+        // Tabulate the upper half of the sky, which also gives the light reaching the ground.
+        let solid_angle = |row: usize| {
+            let theta = PI * (row as f64 + 0.5) / TABLE_HEIGHT as f64;
+            2.0 * PI * PI * theta.sin() / (TABLE_WIDTH * TABLE_HEIGHT) as f64
+        };
+        let mut pixels = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+        let mut irradiance = sun_illuminance * (sun_direction.y * LUMINANCE_SCALE);
+        for row in 0..TABLE_HEIGHT / 2 {
+            let theta = PI * (row as f64 + 0.5) / TABLE_HEIGHT as f64;
+            for column in 0..TABLE_WIDTH {
+                let phi = 2.0 * PI * ((column as f64 + 0.5) / TABLE_WIDTH as f64 - 0.5);
+                let direction = Vec3::new(
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                    -theta.sin() * phi.cos(),
+                );
+                let radiance = sky.sky_radiance(&direction);
+                irradiance += radiance * (direction.y * solid_angle(row));
+                pixels.push(radiance);
+            }
+        }
+        sky.ground = params.ground_albedo * irradiance / PI;
+        pixels.resize(TABLE_WIDTH * TABLE_HEIGHT, sky.ground);
+
+        let sky_power: f64 = pixels
+            .iter()
+            .enumerate()
+            .map(|(index, pixel)| luminance(pixel) * solid_angle(index / TABLE_WIDTH))
+            .sum();
+        let sun_power = luminance(&sky.sun_radiance) * sun_solid_angle;
+        sky.sun_probability = if sun_direction.y > 0.0 {
+            num::clamp(sun_power / (sun_power + sky_power), 0.1, 0.9)
+        } else {
+            0.0
+        };
+        sky.table = EnvironmentMap::new(TABLE_WIDTH, TABLE_HEIGHT, pixels);
+        sky
+    }
+
+    /// Radiance of the sky without the sun, for directions above the horizon.
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let theta = direction.y.acos();
+        let gamma = num::clamp(direction.dot(self.sun_direction), -1.0, 1.0).acos();
+        let luminance = self.luminance.value(theta, gamma).max(0.0);
+        let x = self.x.value(theta, gamma);
+        let y = self.y.value(theta, gamma);
+        if y <= 0.0 {
+            return Color::default();
+        }
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        let rgb = xyz_to_srgb(&xyz) * LUMINANCE_SCALE;
+        Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    fn in_sun(&self, direction: &Vec3) -> bool {
+        direction.dot(self.sun_direction) >= self.sun_cos_radius
+    }
+
+    fn sun_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.sun_cos_radius))
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: &Vec3) -> Color {
+        let direction = direction.unit_vector();
+        if direction.y <= 0.0 {
+            return self.ground * self.intensity;
+        }
+        let mut radiance = self.sky_radiance(&direction);
+        if self.in_sun(&direction) {
+            radiance += self.sun_radiance;
+        }
+        radiance * self.intensity
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let direction = if u1 < self.sun_probability {
+            // Uniform in the cone of the sun.
+            let u1 = u1 / self.sun_probability;
+            let cos_theta = 1.0 - u1 * (1.0 - self.sun_cos_radius);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * u2;
+            self.sun_frame
+                .local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+        } else {
+            let u1 = (u1 - self.sun_probability) / (1.0 - self.sun_probability);
+            self.table.sample(u1.min(1.0 - f64::EPSILON), u2).0
+        };
+        (direction, self.pdf(&direction))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let direction = direction.unit_vector();
+        let sun_pdf = if self.in_sun(&direction) {
+            self.sun_pdf()
+        } else {
+            0.0
+        };
+        self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * self.table.pdf(&direction)
+    }
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+/// Fraction of the sunlight in each channel that makes it through the atmosphere when the
+/// sun is `sun_theta` from the zenith, from Rayleigh and aerosol scattering.
+fn sun_transmittance(sun_theta: f64, turbidity: f64) -> Color {
+    let degrees = sun_theta.to_degrees();
+    if degrees >= 93.0 {
+        return Color::default();
+    }
+    // Relative optical mass of the air along the path to the sun.
+    let mass = 1.0 / (sun_theta.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |lambda: f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * mass).exp();
+        rayleigh * aerosol
+    };
+    Color::new(
+        channel(RGB_WAVELENGTHS[0]),
+        channel(RGB_WAVELENGTHS[1]),
+        channel(RGB_WAVELENGTHS[2]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn sky(sun_direction: Vec3) -> Sky {
+        Sky::new(SkyParameters {
+            sun_direction,
+            ..Default::default()
+        })
+    }
+
+    fn is_finite(c: &Color) -> bool {
+        c.x.is_finite() && c.y.is_finite() && c.z.is_finite()
+    }
+
+    #[test]
+    fn clamps_the_sun_to_the_horizon() {
+        let below = sky(Vec3::new(0.0, -1.0, 2.0));
+        assert!((below.sun_direction - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+
+        for direction in &[Vec3::new(0.0, -1.0, 0.0), Vec3::default()] {
+            let sky = sky(*direction);
+            assert!((sky.sun_direction - HORIZON).length() < 1e-12);
+            for d in &[
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.3, 0.2, -1.0),
+                Vec3::new(0.0, -1.0, 0.0),
+            ] {
+                assert!(is_finite(&sky.radiance(d)));
+                assert!(sky.pdf(d).is_finite());
+            }
+            let (d, pdf) = sky.sample(0.4, 0.7);
+            assert!(d.x.is_finite() && pdf.is_finite() && pdf > 0.0);
+        }
+    }
+
+    #[test]
+    fn sample_matches_pdf() {
+        vec3::rng::seed(3);
+        let mut rng = vec3::rng::thread_rng();
+        let sky = sky(SkyParameters::default().sun_direction);
+        let samples = 100_000;
+        let mut inverse_pdf_sum = 0.0;
+        let mut in_sun = 0;
+        for _ in 0..samples {
+            let (direction, pdf) = sky.sample(rng.gen(), rng.gen());
+            assert!((direction.length() - 1.0).abs() < 1e-9);
+            assert!((pdf - sky.pdf(&direction)).abs() <= 1e-9 * pdf);
+            inverse_pdf_sum += 1.0 / pdf;
+            if sky.in_sun(&direction) {
+                in_sun += 1;
+            }
+        }
+        // Every direction can be sampled, so the mean of 1 / pdf is the whole sphere.
+        let sphere = inverse_pdf_sum / samples as f64;
+        assert!((sphere / (4.0 * PI) - 1.0).abs() < 0.02, "{}", sphere);
+        assert!(in_sun as f64 / samples as f64 >= sky.sun_probability);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_away_from_the_sun() {
+        vec3::rng::seed(4);
+        let sky = sky(SkyParameters::default().sun_direction);
+        let samples = 200_000;
+        let sum: f64 = (0..samples)
+            .map(|_| {
+                let direction = Vec3::random_unit_vector();
+                if sky.in_sun(&direction) {
+                    0.0
+                } else {
+                    sky.pdf(&direction)
+                }
+            })
+            .sum();
+        // The sun's cone holds its share plus the little of the table under it.
+        let integral = 4.0 * PI * sum / samples as f64;
+        assert!(
+            (integral - (1.0 - sky.sun_probability)).abs() < 0.02,
+            "{}",
+            integral
+        );
+    }
+}
@@ -0,0 +1,400 @@
+//! Helpers for spectral rendering: sampling wavelengths, turning RGB colors into spectra,
+//! converting spectra back to sRGB through CIE XYZ, and wavelength dependent indices of
+//! refraction. Wavelengths are in nanometres.
+
+use std::ops;
+use std::sync::OnceLock;
+use vec3::{Color, Vec3};
+
+/// Shortest wavelength that is sampled.
+pub const LAMBDA_MIN: f64 = 360.0;
+/// Longest wavelength that is sampled.
+pub const LAMBDA_MAX: f64 = 830.0;
+/// Number of wavelengths carried by each path.
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+/// The wavelengths carried by one path: a uniformly sampled hero wavelength and the others
+/// evenly spaced after it, wrapping around the visible range.
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; WAVELENGTH_SAMPLES],
+    /// Density of each wavelength, zero once it no longer contributes.
+    pub pdf: [f64; WAVELENGTH_SAMPLES],
+}
+
+impl SampledWavelengths {
+    /// Picks the hero wavelength with `u` in [0, 1).
+    pub fn sample_uniform(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let mut lambda = [hero; WAVELENGTH_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate().skip(1) {
+            let offset = i as f64 * range / WAVELENGTH_SAMPLES as f64;
+            *l = hero + offset;
+            if *l > LAMBDA_MAX {
+                *l -= range;
+            }
+        }
+        SampledWavelengths {
+            lambda,
+            pdf: [1.0 / range; WAVELENGTH_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Keeps only the hero wavelength, for when a path goes through an interface whose
+    /// direction depends on the wavelength. The hero then stands for all of them.
+    pub fn terminate_secondary(&mut self) {
+        if self.is_secondary_terminated() {
+            return;
+        }
+        self.pdf[0] /= WAVELENGTH_SAMPLES as f64;
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    /// Converts the radiance carried at each wavelength to linear sRGB.
+    pub fn to_rgb(&self, radiance: &SampledSpectrum) -> Color {
+        let mut xyz = Vec3::default();
+        for i in 0..WAVELENGTH_SAMPLES {
+            if self.pdf[i] > 0.0 {
+                xyz += cie_xyz(self.lambda[i]) * (radiance.0[i] / self.pdf[i]);
+            }
+        }
+        xyz_to_rgb(&(xyz / (WAVELENGTH_SAMPLES as f64 * CIE_Y_INTEGRAL)))
+    }
+}
+
+/// Values of a spectrum at the wavelengths of a `SampledWavelengths`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampledSpectrum(pub [f64; WAVELENGTH_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn new(value: f64) -> Self {
+        SampledSpectrum([value; WAVELENGTH_SAMPLES])
+    }
+
+    /// Upsamples an RGB color with `rgb_to_spectrum`.
+    pub fn from_rgb(rgb: &Color, wavelengths: &SampledWavelengths) -> Self {
+        let mut values = [0.0; WAVELENGTH_SAMPLES];
+        for (value, lambda) in values.iter_mut().zip(wavelengths.lambda.iter()) {
+            *value = rgb_to_spectrum(rgb, *lambda);
+        }
+        SampledSpectrum(values)
+    }
+}
+
+impl ops::Add<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(mut self, rhs: SampledSpectrum) -> SampledSpectrum {
+        self += rhs;
+        self
+    }
+}
+
+impl ops::AddAssign<SampledSpectrum> for SampledSpectrum {
+    fn add_assign(&mut self, rhs: SampledSpectrum) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *a += b;
+        }
+    }
+}
+
+impl ops::Mul<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(mut self, rhs: SampledSpectrum) -> SampledSpectrum {
+        self *= rhs;
+        self
+    }
+}
+
+impl ops::MulAssign<SampledSpectrum> for SampledSpectrum {
+    fn mul_assign(&mut self, rhs: SampledSpectrum) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0.iter()) {
+            *a *= b;
+        }
+    }
+}
+
+impl ops::Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(mut self, rhs: f64) -> SampledSpectrum {
+        for a in self.0.iter_mut() {
+            *a *= rhs;
+        }
+        self
+    }
+}
+
+/// Integral of the CIE Y matching function over the sampled range.
+const CIE_Y_INTEGRAL: f64 = 106.922_074;
+
+/// The CIE 1931 color matching functions, using the multi-lobe Gaussian fit of Wyman,
+/// Sloan and Shirley.
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_low: f64, sigma_high: f64| {
+        let sigma = if lambda < mu { sigma_low } else { sigma_high };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Converts CIE XYZ to linear sRGB, white balanced so a flat spectrum is white.
+pub fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    xyz_to_srgb(xyz) / conversion().white
+}
+
+/// Converts CIE XYZ to linear sRGB with its D65 white point, for colors given as XYZ.
+pub fn xyz_to_srgb(xyz: &Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+/// Value at `lambda` of a smooth spectrum with the given linear RGB color. The spectrum
+/// is a mix of three basis spectra that add up to one, so grey stays flat. Saturated
+/// colors that would need negative amounts of some wavelengths are clamped to zero there.
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let weights = &conversion().rgb_to_basis;
+    let basis = basis_spectra(lambda);
+    let mut value = 0.0;
+    for (row, b) in weights.iter().zip(basis.iter()) {
+        value += b * (row[0] * rgb.x + row[1] * rgb.y + row[2] * rgb.z);
+    }
+    value.max(0.0)
+}
+
+/// Blue, green and red spectra that blend smoothly into each other.
+fn basis_spectra(lambda: f64) -> [f64; 3] {
+    let smoothstep = |a: f64, b: f64| {
+        let t = num::clamp((lambda - a) / (b - a), 0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let blue = 1.0 - smoothstep(465.0, 510.0);
+    let red = smoothstep(565.0, 610.0);
+    [blue, 1.0 - blue - red, red]
+}
+
+/// Numbers derived from the matching functions once.
+struct Conversion {
+    /// Linear sRGB of a flat spectrum before white balancing.
+    white: Color,
+    /// Maps linear RGB to the weights of the blue, green and red basis spectra.
+    rgb_to_basis: [[f64; 3]; 3],
+}
+
+fn conversion() -> &'static Conversion {
+    static CONVERSION: OnceLock<Conversion> = OnceLock::new();
+    CONVERSION.get_or_init(|| {
+        // Integrate the basis spectra and a flat one against the matching functions.
+        let steps = 4700;
+        let dl = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut white = Vec3::default();
+        let mut basis_xyz = [Vec3::default(); 3];
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * dl;
+            let xyz = cie_xyz(lambda) * (dl / CIE_Y_INTEGRAL);
+            white += xyz;
+            for (b, value) in basis_xyz.iter_mut().zip(basis_spectra(lambda).iter()) {
+                *b += xyz * *value;
+            }
+        }
+        let white = xyz_to_srgb(&white);
+
+        // Columns are the white balanced RGB of each basis spectrum.
+        let columns: Vec<Color> = basis_xyz
+            .iter()
+            .map(|xyz| xyz_to_srgb(xyz) / white)
+            .collect();
+        let basis_to_rgb = [
+            [columns[0].x, columns[1].x, columns[2].x],
+            [columns[0].y, columns[1].y, columns[2].y],
+            [columns[0].z, columns[1].z, columns[2].z],
+        ];
+
+        Conversion {
+            white,
+            rgb_to_basis: invert(&basis_to_rgb),
+        }
+    })
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let c00 = cofactor(1, 2, 1, 2);
+    let c01 = -cofactor(1, 2, 0, 2);
+    let c02 = cofactor(1, 2, 0, 1);
+    let determinant = m[0][0] * c00 + m[0][1] * c01 + m[0][2] * c02;
+    let inv = 1.0 / determinant;
+    [
+        [
+            c00 * inv,
+            -cofactor(0, 2, 1, 2) * inv,
+            cofactor(0, 1, 1, 2) * inv,
+        ],
+        [
+            c01 * inv,
+            cofactor(0, 2, 0, 2) * inv,
+            -cofactor(0, 1, 0, 2) * inv,
+        ],
+        [
+            c02 * inv,
+            -cofactor(0, 2, 0, 1) * inv,
+            cofactor(0, 1, 0, 1) * inv,
+        ],
+    ]
+}
+
+/// How the index of refraction of a dielectric changes with the wavelength.
+#[derive(Debug, Clone, Copy)]
+pub enum Dispersion {
+    /// Cauchy's equation `a + b / λ²`, with λ in micrometres.
+    Cauchy { a: f64, b: f64 },
+    /// The Sellmeier equation `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with λ in micrometres.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, the usual crown glass for lenses and prisms.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    /// Index of refraction at `lambda` nanometres.
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let micrometres = lambda / 1000.0;
+        let l2 = micrometres * micrometres;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.sqrt()
+            }
+        }
+    }
+
+    /// Index of refraction at the sodium d line, used when rendering in RGB.
+    pub fn ior_d(&self) -> f64 {
+        self.ior(587.6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color_close(a: Color, b: Color, tolerance: f64) {
+        assert!((a - b).length() < tolerance, "{:?} != {:?}", a, b);
+    }
+
+    /// Averages the color of `rgb` upsampled over stratified hero wavelengths.
+    fn round_trip(rgb: &Color) -> Color {
+        let samples = 10_000;
+        let mut sum = Color::default();
+        for i in 0..samples {
+            let wavelengths = SampledWavelengths::sample_uniform((i as f64 + 0.5) / samples as f64);
+            sum += wavelengths.to_rgb(&SampledSpectrum::from_rgb(rgb, &wavelengths));
+        }
+        sum / samples as f64
+    }
+
+    #[test]
+    fn colors_round_trip_through_spectra() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert_color_close(round_trip(&white), white, 1e-3);
+        let muted = Color::new(0.3, 0.5, 0.4);
+        assert_color_close(round_trip(&muted), muted, 1e-3);
+    }
+
+    #[test]
+    fn secondary_termination_keeps_the_estimate() {
+        let rgb = Color::new(0.5, 0.5, 0.5);
+        let samples = 10_000;
+        let mut sum = Color::default();
+        for i in 0..samples {
+            let mut wavelengths =
+                SampledWavelengths::sample_uniform((i as f64 + 0.5) / samples as f64);
+            wavelengths.terminate_secondary();
+            assert!(wavelengths.is_secondary_terminated());
+            sum += wavelengths.to_rgb(&SampledSpectrum::from_rgb(&rgb, &wavelengths));
+        }
+        assert_color_close(sum / samples as f64, rgb, 1e-3);
+    }
+
+    #[test]
+    fn matching_functions_follow_the_cie_table() {
+        // CIE 1931 2° observer.
+        let table = [
+            (450.0, Vec3::new(0.3362, 0.0380, 1.7721)),
+            (500.0, Vec3::new(0.0049, 0.3230, 0.2720)),
+            (550.0, Vec3::new(0.4334, 0.9950, 0.0087)),
+            (600.0, Vec3::new(1.0622, 0.6310, 0.0008)),
+            (650.0, Vec3::new(0.2835, 0.1070, 0.0000)),
+        ];
+        for (lambda, xyz) in table.iter() {
+            assert_color_close(cie_xyz(*lambda), *xyz, 0.05);
+        }
+
+        let steps = 4700;
+        let dl = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let y: f64 = (0..steps)
+            .map(|i| cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * dl).y * dl)
+            .sum();
+        assert!((y / CIE_Y_INTEGRAL - 1.0).abs() < 1e-6, "{}", y);
+    }
+
+    #[test]
+    fn xyz_to_srgb_maps_the_primaries() {
+        let d65 = Vec3::new(0.950_47, 1.0, 1.088_83);
+        assert_color_close(xyz_to_srgb(&d65), Color::new(1.0, 1.0, 1.0), 1e-4);
+        let red = Vec3::new(0.412_456_4, 0.212_672_9, 0.019_333_9);
+        assert_color_close(xyz_to_srgb(&red), Color::new(1.0, 0.0, 0.0), 1e-4);
+        let green = Vec3::new(0.357_576_1, 0.715_152_2, 0.119_192);
+        assert_color_close(xyz_to_srgb(&green), Color::new(0.0, 1.0, 0.0), 1e-4);
+        let blue = Vec3::new(0.180_437_5, 0.072_175, 0.950_304_1);
+        assert_color_close(xyz_to_srgb(&blue), Color::new(0.0, 0.0, 1.0), 1e-4);
+    }
+
+    #[test]
+    fn bk7_matches_its_catalogue_index() {
+        assert!((Dispersion::BK7.ior_d() - 1.5168).abs() < 1e-4);
+        assert!((Dispersion::BK7.ior(486.1) - 1.5224).abs() < 1e-4);
+        assert!((Dispersion::BK7.ior(656.3) - 1.5143).abs() < 1e-4);
+    }
+
+    #[test]
+    fn dispersion_falls_with_the_wavelength() {
+        let cauchy = Dispersion::Cauchy { a: 1.5, b: 0.004 };
+        for dispersion in [cauchy, Dispersion::BK7].iter() {
+            let mut previous = f64::INFINITY;
+            let mut lambda = LAMBDA_MIN;
+            while lambda <= LAMBDA_MAX {
+                let ior = dispersion.ior(lambda);
+                assert!(ior < previous, "{} at {}", ior, lambda);
+                previous = ior;
+                lambda += 5.0;
+            }
+        }
+        assert!((cauchy.ior(1000.0) - 1.504).abs() < 1e-12);
+    }
+}
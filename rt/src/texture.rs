@@ -1,3 +1,4 @@
+use crate::hit::HitRecord;
 use crate::perlin::Perlin;
 use image;
 use num;
@@ -7,6 +8,11 @@ use vec3::{Color, Point3};
 
 pub trait Texture {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+    /// The value at a hit. Textures that need more of the hit than its u,v and point
+    /// override this.
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value(rec.u, rec.v, &rec.p)
+    }
 }
 
 pub type TexturePtr = Arc<dyn Texture + Sync + Send>;
@@ -55,9 +61,19 @@ impl Texture for CheckerPattern {
     fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
         let sines = (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
         if sines < 0.0 {
-            return self.odd.value(u, v, p);
+            self.odd.value(u, v, p)
         } else {
-            return self.even.value(u, v, p);
+            self.even.value(u, v, p)
+        }
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        let p = rec.p;
+        let sines = (10.0 * p.x).sin() * (10.0 * p.y).sin() * (10.0 * p.z).sin();
+        if sines < 0.0 {
+            self.odd.value_at(rec)
+        } else {
+            self.even.value_at(rec)
         }
     }
 }
@@ -91,6 +107,12 @@ pub struct ImageTexture {
     bytes_per_scanline: u32,
 }
 
+impl Default for ImageTexture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ImageTexture {
     pub fn new() -> Self {
         ImageTexture {
@@ -102,17 +124,22 @@ impl ImageTexture {
     }
 
     pub fn new_from_filename(path: &Path) -> Self {
-        let image = image::open(path).unwrap();
+        ImageTexture::try_new_from_filename(path).unwrap()
+    }
+
+    /// Like `new_from_filename`, but returns the error instead of panicking.
+    pub fn try_new_from_filename(path: &Path) -> image::ImageResult<Self> {
+        let image = image::open(path)?;
         let image = image.into_rgb();
         let data = image.to_vec();
         let (width, height) = image.dimensions();
         let bytes_per_scanline = width * 3;
-        ImageTexture {
+        Ok(ImageTexture {
             data,
             width,
             height,
             bytes_per_scanline,
-        }
+        })
     }
 }
 
@@ -145,3 +172,18 @@ impl Texture for ImageTexture {
         )
     }
 }
+
+/// The vertex colors of the mesh that was hit, interpolated across each face by the mesh.
+/// One instance serves every mesh. Hits without vertex colors are white.
+pub struct VertexColorTexture;
+
+impl Texture for VertexColorTexture {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        rec.vertex_color
+            .unwrap_or_else(|| self.value(rec.u, rec.v, &rec.p))
+    }
+}
@@ -1,6 +1,7 @@
+use crate::aabb::Aabb;
 use crate::hit::{HitRecord, Hittable, HittablePtr};
 use crate::ray::Ray;
-use vec3::Vec3;
+use vec3::{Mat3, Mat4, Point3, Quat, Vec3};
 
 pub struct Translate {
     ptr: HittablePtr,
@@ -19,11 +20,28 @@ impl Hittable for Translate {
         if let Some(mut rec) = self.ptr.hit(&moved_r, t_min, t_max) {
             rec.p += self.offset;
             rec.set_face_normal(&moved_r, &rec.normal.clone());
-            return Some(rec);
+            Some(rec)
         } else {
-            return None;
+            None
         }
     }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let bbox = self.ptr.bounding_box(t0, t1)?;
+        Some(Aabb::new(
+            bbox.minimum + self.offset,
+            bbox.maximum + self.offset,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.ptr
+            .pdf_value(&(*origin - self.offset), direction, time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        self.ptr.random(&(*origin - self.offset), time)
+    }
 }
 
 pub struct RotateY {
@@ -43,6 +61,24 @@ impl RotateY {
             ptr,
         }
     }
+
+    /// Rotates a world space vector into the child's space.
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    /// Rotates a vector in the child's space back into world space.
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
 }
 
 impl Hittable for RotateY {
@@ -68,12 +104,603 @@ impl Hittable for RotateY {
             normal.x = self.cos_theta * rec.normal.x + self.sin_theta * rec.normal.z;
             normal.z = -self.sin_theta * rec.normal.x + self.cos_theta * rec.normal.z;
 
+            if let Some(t) = rec.tangent {
+                rec.tangent = Some(Vec3::new(
+                    self.cos_theta * t.x + self.sin_theta * t.z,
+                    t.y,
+                    -self.sin_theta * t.x + self.cos_theta * t.z,
+                ));
+            }
+
             rec.p = p;
             rec.set_face_normal(&rotated_r, &normal);
 
-            return Some(rec);
+            Some(rec)
         } else {
+            None
+        }
+    }
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let bbox = self.ptr.bounding_box(t0, t1)?;
+
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+
+        // Rotate all 8 corners of the child's box and enclose them.
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let x = if i == 0 {
+                        bbox.minimum.x
+                    } else {
+                        bbox.maximum.x
+                    };
+                    let y = if j == 0 {
+                        bbox.minimum.y
+                    } else {
+                        bbox.maximum.y
+                    };
+                    let z = if k == 0 {
+                        bbox.minimum.z
+                    } else {
+                        bbox.maximum.z
+                    };
+
+                    let newx = self.cos_theta * x + self.sin_theta * z;
+                    let newz = -self.sin_theta * x + self.cos_theta * z;
+
+                    min = Point3::new(min.x.min(newx), min.y.min(y), min.z.min(newz));
+                    max = Point3::new(max.x.max(newx), max.y.max(y), max.z.max(newz));
+                }
+            }
+        }
+
+        Some(Aabb::new(min, max))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        self.ptr
+            .pdf_value(&self.to_object(origin), &self.to_object(direction), time)
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        self.to_world(&self.ptr.random(&self.to_object(origin), time))
+    }
+}
+
+/// Places an object with an arbitrary affine transform, including non-uniform scaling and
+/// shearing. The object is only referenced, so many transforms can share one mesh.
+pub struct Transform {
+    ptr: HittablePtr,
+    to_world: Mat4,
+    to_object: Mat4,
+    /// Transposed linear part of `to_object`, which carries normals to world space.
+    normal_to_world: Mat3,
+    /// Volume scale from world to object space, for converting light pdfs.
+    object_determinant: f64,
+}
+
+impl Transform {
+    /// Transforms `ptr` by `matrix`, whose last row must be [0, 0, 0, 1]. Returns None when
+    /// the matrix can't be inverted.
+    pub fn new(ptr: HittablePtr, matrix: Mat4) -> Option<Self> {
+        let to_object = matrix.inverse()?;
+        Some(Transform {
+            ptr,
+            to_world: matrix,
+            to_object,
+            normal_to_world: to_object.linear().transpose(),
+            object_determinant: to_object.linear().determinant().abs(),
+        })
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(
+            &self.ptr,
+            &self.to_world,
+            &self.to_object,
+            &self.normal_to_world,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        Some(transform_box(
+            &self.to_world,
+            &self.ptr.bounding_box(t0, t1)?,
+        ))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        pdf_value_transformed(
+            &self.ptr,
+            &self.to_object,
+            self.object_determinant,
+            origin,
+            direction,
+            time,
+        )
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        random_transformed(&self.ptr, &self.to_world, &self.to_object, origin, time)
+    }
+}
+
+/// The pose of an animated object at one moment: scaled, then rotated, then moved.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, translation: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        Keyframe {
+            time,
+            translation,
+            rotation: rotation.normalize(),
+            scale,
+        }
+    }
+
+    /// The pose between `self` and `next` at `time`, moving and scaling linearly and
+    /// rotating along the shortest arc at a constant rate.
+    fn interpolate(&self, next: &Keyframe, time: f64) -> Keyframe {
+        let t = (time - self.time) / (next.time - self.time);
+        Keyframe {
+            time,
+            translation: self.translation * (1.0 - t) + next.translation * t,
+            rotation: self.rotation.slerp(next.rotation, t),
+            scale: self.scale * (1.0 - t) + next.scale * t,
+        }
+    }
+
+    fn world_matrix(&self) -> Mat4 {
+        Mat4::from_linear(
+            self.rotation.to_mat3() * Mat3::scaling(self.scale),
+            self.translation,
+        )
+    }
+
+    fn object_matrix(&self) -> Mat4 {
+        let inverse_scale = Vec3::new(1.0, 1.0, 1.0) / self.scale;
+        let linear = Mat3::scaling(inverse_scale) * self.rotation.conjugate().to_mat3();
+        Mat4::from_linear(linear, -(linear * self.translation))
+    }
+}
+
+/// Moves an object through a sequence of keyframes, placing it by the time of each ray so
+/// it's blurred over the camera's shutter interval. Before the first and after the last
+/// keyframe the object holds still. Emissive objects are sampled as lights in their pose at
+/// the time of the shading ray.
+pub struct AnimatedTransform {
+    ptr: HittablePtr,
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    /// Returns None without keyframes or when a scale factor is zero.
+    pub fn new(ptr: HittablePtr, mut keyframes: Vec<Keyframe>) -> Option<Self> {
+        if keyframes.is_empty()
+            || keyframes
+                .iter()
+                .any(|k| k.scale.x == 0.0 || k.scale.y == 0.0 || k.scale.z == 0.0)
+        {
             return None;
         }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Some(AnimatedTransform { ptr, keyframes })
+    }
+
+    fn pose(&self, time: f64) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+        self.keyframes[next - 1].interpolate(&self.keyframes[next], time)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let pose = self.pose(r.time);
+        let to_object = pose.object_matrix();
+        hit_transformed(
+            &self.ptr,
+            &pose.world_matrix(),
+            &to_object,
+            &to_object.linear().transpose(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    /// Encloses the object at poses sampled through the interval, finely enough that
+    /// rotations don't turn much between samples, padded by how far a point on the object
+    /// can swing outside the segment between two samples.
+    fn bounding_box(&self, t0: f64, t1: f64) -> Option<Aabb> {
+        let bbox = self.ptr.bounding_box(t0, t1)?;
+        let mut times = vec![t0, t1];
+        times.extend(
+            self.keyframes
+                .iter()
+                .map(|k| k.time)
+                .filter(|&time| t0 < time && time < t1),
+        );
+        times.sort_by(f64::total_cmp);
+
+        const MAX_STEP_ANGLE: f64 = std::f64::consts::PI / 32.0;
+        let mut samples = vec![t0];
+        let mut max_step_angle: f64 = 0.0;
+        for pair in times.windows(2) {
+            let angle = self.pose(pair[0]).rotation.dot(self.pose(pair[1]).rotation);
+            let angle = 2.0 * num::clamp(angle.abs(), 0.0, 1.0).acos();
+            let steps = (angle / MAX_STEP_ANGLE).ceil().max(1.0);
+            max_step_angle = max_step_angle.max(angle / steps);
+            let steps = steps as usize;
+            for i in 1..=steps {
+                samples.push(pair[0] + (pair[1] - pair[0]) * i as f64 / steps as f64);
+            }
+        }
+
+        // The farthest any corner of the box gets from the origin the object turns around.
+        let extent = Vec3::new(
+            bbox.minimum.x.abs().max(bbox.maximum.x.abs()),
+            bbox.minimum.y.abs().max(bbox.maximum.y.abs()),
+            bbox.minimum.z.abs().max(bbox.maximum.z.abs()),
+        )
+        .length();
+        let mut result: Option<Aabb> = None;
+        let mut radius: f64 = 0.0;
+        for &time in samples.iter() {
+            let pose = self.pose(time);
+            let posed = transform_box(&pose.world_matrix(), &bbox);
+            result = Some(match result {
+                Some(b) => Aabb::surrounding_box(&b, &posed),
+                None => posed,
+            });
+            let scale = pose
+                .scale
+                .x
+                .abs()
+                .max(pose.scale.y.abs())
+                .max(pose.scale.z.abs());
+            radius = radius.max(scale * extent);
+        }
+        // A point at distance r from the rotation center strays at most r (1 - cos(a / 2))
+        // from the chord when turning by a.
+        let pad = radius * (1.0 - (max_step_angle / 2.0).cos());
+        let b = result?;
+        Some(Aabb::new(b.minimum - pad, b.maximum + pad))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let pose = self.pose(time);
+        let object_determinant = 1.0 / (pose.scale.x * pose.scale.y * pose.scale.z).abs();
+        pdf_value_transformed(
+            &self.ptr,
+            &pose.object_matrix(),
+            object_determinant,
+            origin,
+            direction,
+            time,
+        )
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        let pose = self.pose(time);
+        random_transformed(
+            &self.ptr,
+            &pose.world_matrix(),
+            &pose.object_matrix(),
+            origin,
+            time,
+        )
+    }
+}
+
+/// Hits `ptr` with `r` carried into object space, and brings the hit back into world
+/// space. Normals are carried with `normal_to_world`, the inverse transpose of
+/// `to_world`'s linear part.
+fn hit_transformed(
+    ptr: &HittablePtr,
+    to_world: &Mat4,
+    to_object: &Mat4,
+    normal_to_world: &Mat3,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord> {
+    // The direction isn't renormalized, so distances along both rays match.
+    let object_r = Ray::new(
+        to_object.transform_point(&r.origin),
+        to_object.transform_vector(&r.direction),
+        r.time,
+    );
+    let mut rec = ptr.hit(&object_r, t_min, t_max)?;
+    let outward_normal = if rec.front_face {
+        rec.normal
+    } else {
+        -rec.normal
+    };
+    rec.p = to_world.transform_point(&rec.p);
+    rec.tangent = rec.tangent.map(|t| to_world.transform_vector(&t));
+    let normal = (*normal_to_world * outward_normal).unit_vector();
+    rec.set_face_normal(r, &normal);
+    Some(rec)
+}
+
+/// The density of `ptr` sampling `direction` from `origin`, both carried into object space.
+/// `object_determinant` is the volume scale of `to_object`.
+fn pdf_value_transformed(
+    ptr: &HittablePtr,
+    to_object: &Mat4,
+    object_determinant: f64,
+    origin: &Point3,
+    direction: &Vec3,
+    time: f64,
+) -> f64 {
+    let object_direction = to_object.transform_vector(direction);
+    let pdf = ptr.pdf_value(&to_object.transform_point(origin), &object_direction, time);
+    // Solid angles stretch along with space: a unit direction w maps to one covering
+    // |det| / |w'|^3 times the solid angle, where w' is its image.
+    let stretch = object_direction.length() / direction.length();
+    pdf * object_determinant / (stretch * stretch * stretch)
+}
+
+/// A direction sampled by `ptr` from `origin` in object space, brought back to world space.
+fn random_transformed(
+    ptr: &HittablePtr,
+    to_world: &Mat4,
+    to_object: &Mat4,
+    origin: &Point3,
+    time: f64,
+) -> Vec3 {
+    to_world.transform_vector(&ptr.random(&to_object.transform_point(origin), time))
+}
+
+/// Encloses the 8 transformed corners of `bbox`.
+fn transform_box(matrix: &Mat4, bbox: &Aabb) -> Aabb {
+    let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+    for corner in 0..8 {
+        let pick =
+            |bit: usize, low: f64, high: f64| if corner >> bit & 1 == 0 { low } else { high };
+        let p = matrix.transform_point(&Point3::new(
+            pick(0, bbox.minimum.x, bbox.maximum.x),
+            pick(1, bbox.minimum.y, bbox.maximum.y),
+            pick(2, bbox.minimum.z, bbox.maximum.z),
+        ));
+        min = Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    Aabb::new(min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, MaterialPtr};
+    use crate::objects::{Cube, Sphere};
+    use rand::Rng;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    fn gray() -> MaterialPtr {
+        Arc::new(Lambertian::new_from_color(&Vec3::new(0.5, 0.5, 0.5)))
+    }
+
+    fn unit_sphere() -> HittablePtr {
+        Arc::new(Sphere::new(Point3::default(), 1.0, gray()))
+    }
+
+    /// A unit sphere stretched to twice its height and moved to `center`.
+    fn ellipsoid(center: Vec3) -> Transform {
+        let matrix = Mat4::translation(center) * Mat4::scaling(Vec3::new(1.0, 2.0, 1.0));
+        Transform::new(unit_sphere(), matrix).unwrap()
+    }
+
+    #[test]
+    fn hits_a_stretched_sphere_like_an_ellipsoid() {
+        vec3::rng::seed(1);
+        let center = Vec3::new(1.0, -2.0, 0.5);
+        let ellipsoid = ellipsoid(center);
+        for _ in 0..1000 {
+            let origin = center + Vec3::random_unit_vector() * 5.0;
+            let target = center + Vec3::random_range(-0.7, 0.7);
+            // Unnormalized directions check that t is measured along the world ray.
+            let r = Ray::new(origin, (target - origin) * 0.3, 0.0);
+
+            // Solve |S^-1 (o + t d - c)|^2 = 1 with S = diag(1, 2, 1).
+            let squash = |v: Vec3| Vec3::new(v.x, v.y / 2.0, v.z);
+            let (o, d) = (squash(r.origin - center), squash(r.direction));
+            let (a, half_b, c) = (d.length_squared(), o.dot(d), o.length_squared() - 1.0);
+            let t = (-half_b - (half_b * half_b - a * c).sqrt()) / a;
+
+            let rec = ellipsoid.hit(&r, 0.001, f64::INFINITY).unwrap();
+            assert!((rec.t - t).abs() < 1e-9, "{} != {}", rec.t, t);
+            assert!((rec.p - r.at(t)).length() < 1e-9);
+            let local = rec.p - center;
+            let gradient = Vec3::new(local.x, local.y / 4.0, local.z).unit_vector();
+            assert!((rec.normal - gradient).length() < 1e-9);
+            assert!(rec.front_face);
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        vec3::rng::seed(2);
+        let ellipsoid = ellipsoid(Vec3::new(3.0, 1.0, -2.0));
+        let origin = Point3::new(0.0, 0.5, 0.0);
+        let samples = 400_000;
+        let mut rng = vec3::rng::thread_rng();
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            // Scaled directions check the pdf only depends on the direction.
+            let direction = Vec3::random_unit_vector() * rng.gen_range(0.5, 2.0);
+            sum += ellipsoid.pdf_value(&origin, &direction, 0.0);
+        }
+        let integral = 4.0 * PI * sum / samples as f64;
+        assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+
+        // Sampled directions hit the ellipsoid and have the density it reports.
+        let mut inverse_pdf_sum = 0.0;
+        let mut hits = 0;
+        for _ in 0..samples {
+            let direction = ellipsoid.random(&origin, 0.0);
+            assert!(ellipsoid
+                .hit(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY)
+                .is_some());
+            inverse_pdf_sum += 1.0 / ellipsoid.pdf_value(&origin, &direction, 0.0);
+            let uniform = Vec3::random_unit_vector();
+            if ellipsoid.pdf_value(&origin, &uniform, 0.0) > 0.0 {
+                hits += 1;
+            }
+        }
+        // Both estimate the solid angle the ellipsoid covers.
+        let solid_angle = 4.0 * PI * hits as f64 / samples as f64;
+        let estimate = inverse_pdf_sum / samples as f64;
+        assert!(
+            (estimate / solid_angle - 1.0).abs() < 0.03,
+            "{} {}",
+            estimate,
+            solid_angle
+        );
+    }
+
+    #[test]
+    fn transformed_box_encloses_the_transformed_points() {
+        vec3::rng::seed(3);
+        let bbox = Aabb::new(Point3::new(-1.0, 0.0, 2.0), Point3::new(0.5, 3.0, 2.5));
+        let matrix = Mat4::translation(Vec3::new(4.0, -1.0, 0.0))
+            * Mat4::from_euler(0.4, 1.0, -0.3)
+            * Mat4::scaling(Vec3::new(1.0, -2.0, 0.5));
+        let world = transform_box(&matrix, &bbox);
+        let mut corners_touching = 0;
+        for i in 0..1000 {
+            let p = if i < 8 {
+                let pick =
+                    |bit: usize, low: f64, high: f64| if i >> bit & 1 == 0 { low } else { high };
+                Point3::new(
+                    pick(0, bbox.minimum.x, bbox.maximum.x),
+                    pick(1, bbox.minimum.y, bbox.maximum.y),
+                    pick(2, bbox.minimum.z, bbox.maximum.z),
+                )
+            } else {
+                let mut rng = vec3::rng::thread_rng();
+                Point3::new(
+                    rng.gen_range(bbox.minimum.x, bbox.maximum.x),
+                    rng.gen_range(bbox.minimum.y, bbox.maximum.y),
+                    rng.gen_range(bbox.minimum.z, bbox.maximum.z),
+                )
+            };
+            let q = matrix.transform_point(&p);
+            let inside = |v: f64, low: f64, high: f64| low - 1e-9 <= v && v <= high + 1e-9;
+            assert!(inside(q.x, world.minimum.x, world.maximum.x));
+            assert!(inside(q.y, world.minimum.y, world.maximum.y));
+            assert!(inside(q.z, world.minimum.z, world.maximum.z));
+            let touches =
+                |v: f64, low: f64, high: f64| (v - low).abs() < 1e-9 || (v - high).abs() < 1e-9;
+            if i < 8 && touches(q.x, world.minimum.x, world.maximum.x) {
+                corners_touching += 1;
+            }
+        }
+        // The box is tight: some corners lie on its faces.
+        assert!(corners_touching >= 2);
+    }
+
+    #[test]
+    fn rotating_box_stays_inside_its_bounding_box() {
+        vec3::rng::seed(4);
+        let (p0, p1) = (Point3::new(1.0, -0.5, 2.0), Point3::new(2.5, 0.5, 3.0));
+        let cube: HittablePtr = Arc::new(Cube::new(p0, p1, gray()));
+        let keyframes = vec![
+            Keyframe::new(
+                0.0,
+                Vec3::default(),
+                Quat::identity(),
+                Vec3::new(1.0, 1.0, 1.0),
+            ),
+            Keyframe::new(
+                1.0,
+                Vec3::new(0.0, 1.0, 0.0),
+                Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 3.0),
+                Vec3::new(1.0, 1.0, 1.0),
+            ),
+            Keyframe::new(
+                2.0,
+                Vec3::new(-1.0, 0.0, 0.0),
+                Quat::from_euler(0.5, 2.0, -1.0),
+                Vec3::new(0.5, 2.0, 1.0),
+            ),
+        ];
+        let animated = AnimatedTransform::new(cube, keyframes).unwrap();
+        let bbox = animated.bounding_box(0.0, 2.0).unwrap();
+        let mut rng = vec3::rng::thread_rng();
+        for _ in 0..10_000 {
+            let pose = animated.pose(rng.gen_range(0.0, 2.0));
+            let p = Point3::new(
+                rng.gen_range(p0.x, p1.x),
+                rng.gen_range(p0.y, p1.y),
+                rng.gen_range(p0.z, p1.z),
+            );
+            let q = pose.world_matrix().transform_point(&p);
+            assert!(bbox.minimum.x <= q.x && q.x <= bbox.maximum.x);
+            assert!(bbox.minimum.y <= q.y && q.y <= bbox.maximum.y);
+            assert!(bbox.minimum.z <= q.z && q.z <= bbox.maximum.z);
+        }
+    }
+
+    #[test]
+    fn samples_animated_lights_in_their_pose_at_the_ray_time() {
+        vec3::rng::seed(5);
+        let keyframes = vec![
+            Keyframe::new(
+                0.0,
+                Vec3::new(0.0, 0.0, -5.0),
+                Quat::identity(),
+                Vec3::new(1.0, 1.0, 1.0),
+            ),
+            Keyframe::new(
+                1.0,
+                Vec3::new(4.0, 0.0, -5.0),
+                Quat::from_euler(0.3, 0.2, 0.1),
+                Vec3::new(2.0, 2.0, 2.0),
+            ),
+        ];
+        let animated = AnimatedTransform::new(unit_sphere(), keyframes).unwrap();
+        let origin = Point3::default();
+        for &(time, center, radius) in [
+            (0.0, Point3::new(0.0, 0.0, -5.0), 1.0),
+            (0.5, Point3::new(2.0, 0.0, -5.0), 1.5),
+            (1.0, Point3::new(4.0, 0.0, -5.0), 2.0),
+        ]
+        .iter()
+        {
+            let posed = Sphere::new(center, radius, gray());
+            for _ in 0..100 {
+                let direction = animated.random(&origin, time);
+                let r = Ray::new(origin, direction, time);
+                assert!(animated.hit(&r, 0.001, f64::INFINITY).is_some());
+                let pdf = animated.pdf_value(&origin, &direction, time);
+                let expected = posed.pdf_value(&origin, &direction, time);
+                assert!((pdf / expected - 1.0).abs() < 1e-9, "{} {}", pdf, expected);
+            }
+        }
+        // The sphere has left the direction it started in.
+        let start = Vec3::new(0.0, 0.0, -1.0);
+        assert!(animated.pdf_value(&origin, &start, 0.0) > 0.0);
+        assert_eq!(animated.pdf_value(&origin, &start, 1.0), 0.0);
     }
 }
@@ -0,0 +1,607 @@
+//! Sparse voxel grids for volumes from simulations, and a loader for NRRD files.
+//!
+//! Grids are stored in bricks of 8³ voxels, like the leaf nodes of OpenVDB, and only the
+//! bricks with a nonzero voxel are kept, so a mostly empty simulation box costs little.
+//!
+//! NRRD (<http://teem.sourceforge.net/nrrd/format.html>) is a text header followed by the
+//! samples. The loader reads 3 dimensional files with these fields:
+//!
+//! - `type`: `uint8`, `int8`, `uint16`, `int16`, `uint32`, `int32`, `float` or `double`,
+//!   under any of their NRRD spellings.
+//! - `sizes`: the number of samples along x, y and z. x varies fastest in the data.
+//! - `encoding`: `raw`, `gzip` or `ascii`, and `endian` for multi-byte raw and gzip data.
+//! - `spacings` or axis aligned `space directions`: the distance between samples,
+//!   1 by default.
+//! - `space origin`: the position of the first sample, the origin by default.
+//! - `data file`: a detached data file relative to the header. Without it the data follows
+//!   the blank line ending the header.
+//! - `byte skip`: bytes to skip before raw data, or -1 to read it from the end of the file.
+//!
+//! Samples are densities as they are, negative ones count as zero. A sample stands for the
+//! value at its position and the grid is interpolated trilinearly between them.
+
+use crate::aabb::Aabb;
+use crate::density::Density;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use vec3::{Point3, Vec3};
+
+const BRICK_BITS: i32 = 3;
+const BRICK_SIZE: i32 = 1 << BRICK_BITS;
+const BRICK_MASK: i32 = BRICK_SIZE - 1;
+const BRICK_VOXELS: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE) as usize;
+
+#[derive(Debug)]
+pub enum VoxelError {
+    Io(PathBuf, io::Error),
+    Format(PathBuf, String),
+}
+
+impl fmt::Display for VoxelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VoxelError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            VoxelError::Format(path, message) => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for VoxelError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VoxelError::Io(_, err) => Some(err),
+            VoxelError::Format(..) => None,
+        }
+    }
+}
+
+/// A sparse grid of density samples. Sample `[i, j, k]` sits at `origin + spacing * [i, j,
+/// k]`, and every sample that was never set is zero.
+pub struct SparseGrid {
+    bricks: HashMap<[i32; 3], Box<[f32; BRICK_VOXELS]>>,
+    origin: Point3,
+    spacing: Vec3,
+    /// Smallest and largest index of the nonzero samples.
+    active: Option<([i32; 3], [i32; 3])>,
+    max_value: f64,
+}
+
+impl SparseGrid {
+    /// An empty grid. `spacing` is the distance between samples along each axis.
+    pub fn new(origin: Point3, spacing: Vec3) -> Self {
+        SparseGrid {
+            bricks: HashMap::new(),
+            origin,
+            spacing,
+            active: None,
+            max_value: 0.0,
+        }
+    }
+
+    /// Builds a grid from `resolution` samples with x varying fastest, then y, then z,
+    /// dropping the empty bricks.
+    pub fn new_from_dense(
+        resolution: [usize; 3],
+        values: &[f64],
+        origin: Point3,
+        spacing: Vec3,
+    ) -> Self {
+        assert_eq!(
+            values.len(),
+            resolution[0] * resolution[1] * resolution[2],
+            "values don't match the resolution"
+        );
+        let mut grid = SparseGrid::new(origin, spacing);
+        for (index, &value) in values.iter().enumerate() {
+            if value > 0.0 {
+                let x = index % resolution[0];
+                let y = index / resolution[0] % resolution[1];
+                let z = index / (resolution[0] * resolution[1]);
+                grid.set([x as i32, y as i32, z as i32], value);
+            }
+        }
+        grid
+    }
+
+    /// Sets the sample at `index`. Negative values are stored as zero.
+    pub fn set(&mut self, index: [i32; 3], value: f64) {
+        let value = value.max(0.0);
+        let (brick, offset) = split(index);
+        if value == 0.0 && !self.bricks.contains_key(&brick) {
+            return;
+        }
+        self.bricks
+            .entry(brick)
+            .or_insert_with(|| Box::new([0.0; BRICK_VOXELS]))[offset] = value as f32;
+        if value > 0.0 {
+            self.max_value = self.max_value.max(value);
+            self.active = Some(match self.active {
+                None => (index, index),
+                Some((min, max)) => (
+                    [0, 1, 2].map(|a| min[a].min(index[a])),
+                    [0, 1, 2].map(|a| max[a].max(index[a])),
+                ),
+            });
+        }
+    }
+
+    /// The sample at `index`.
+    pub fn get(&self, index: [i32; 3]) -> f64 {
+        let (brick, offset) = split(index);
+        self.bricks
+            .get(&brick)
+            .map_or(0.0, |voxels| voxels[offset] as f64)
+    }
+
+    /// Number of 8³ bricks stored.
+    pub fn brick_count(&self) -> usize {
+        self.bricks.len()
+    }
+
+    /// Encloses everywhere the density isn't zero, or None for an empty grid.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let (min, max) = self.active?;
+        // Interpolation spreads every sample up to the next one.
+        let corner = |index: [i32; 3], offset: f64| {
+            Point3::new(
+                self.origin.x + (index[0] as f64 + offset) * self.spacing.x,
+                self.origin.y + (index[1] as f64 + offset) * self.spacing.y,
+                self.origin.z + (index[2] as f64 + offset) * self.spacing.z,
+            )
+        };
+        let (a, b) = (corner(min, -1.0), corner(max, 1.0));
+        Some(Aabb::new(
+            Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        ))
+    }
+}
+
+impl Density for SparseGrid {
+    fn density(&self, p: &Point3) -> f64 {
+        let local = [
+            (p.x - self.origin.x) / self.spacing.x,
+            (p.y - self.origin.y) / self.spacing.y,
+            (p.z - self.origin.z) / self.spacing.z,
+        ];
+        let base = local.map(|l| l.floor());
+        let t = [0, 1, 2].map(|a| local[a] - base[a]);
+        if base.iter().any(|b| b.abs() > i32::MAX as f64 / 2.0) {
+            return 0.0;
+        }
+        let base = base.map(|b| b as i32);
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = base;
+            for axis in 0..3 {
+                if corner >> axis & 1 == 1 {
+                    weight *= t[axis];
+                    index[axis] += 1;
+                } else {
+                    weight *= 1.0 - t[axis];
+                }
+            }
+            if weight > 0.0 {
+                value += weight * self.get(index);
+            }
+        }
+        value
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_value
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        SparseGrid::bounding_box(self)
+    }
+}
+
+/// The brick holding `index` and the position of the voxel inside it.
+fn split(index: [i32; 3]) -> ([i32; 3], usize) {
+    let brick = index.map(|i| i >> BRICK_BITS);
+    let [x, y, z] = index.map(|i| (i & BRICK_MASK) as usize);
+    let size = BRICK_SIZE as usize;
+    (brick, (z * size + y) * size + x)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SampleType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "uchar" | "unsigned char" | "uint8" | "uint8_t" => SampleType::U8,
+            "signed char" | "int8" | "int8_t" => SampleType::I8,
+            "ushort" | "unsigned short" | "unsigned short int" | "uint16" | "uint16_t" => {
+                SampleType::U16
+            }
+            "short" | "short int" | "signed short" | "signed short int" | "int16" | "int16_t" => {
+                SampleType::I16
+            }
+            "uint" | "unsigned int" | "uint32" | "uint32_t" => SampleType::U32,
+            "int" | "signed int" | "int32" | "int32_t" => SampleType::I32,
+            "float" => SampleType::F32,
+            "double" => SampleType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            SampleType::U8 | SampleType::I8 => 1,
+            SampleType::U16 | SampleType::I16 => 2,
+            SampleType::U32 | SampleType::I32 | SampleType::F32 => 4,
+            SampleType::F64 => 8,
+        }
+    }
+
+    /// Decodes one sample from little endian bytes.
+    fn decode(self, b: &[u8]) -> f64 {
+        match self {
+            SampleType::U8 => b[0] as f64,
+            SampleType::I8 => b[0] as i8 as f64,
+            SampleType::U16 => u16::from_le_bytes(b.try_into().unwrap()) as f64,
+            SampleType::I16 => i16::from_le_bytes(b.try_into().unwrap()) as f64,
+            SampleType::U32 => u32::from_le_bytes(b.try_into().unwrap()) as f64,
+            SampleType::I32 => i32::from_le_bytes(b.try_into().unwrap()) as f64,
+            SampleType::F32 => f32::from_le_bytes(b.try_into().unwrap()) as f64,
+            SampleType::F64 => f64::from_le_bytes(b.try_into().unwrap()),
+        }
+    }
+}
+
+/// Loads a 3 dimensional NRRD file into a sparse grid.
+pub fn load_nrrd(path: &Path) -> Result<SparseGrid, VoxelError> {
+    let format = |message: String| VoxelError::Format(path.to_path_buf(), message);
+    let bytes = fs::read(path).map_err(|e| VoxelError::Io(path.to_path_buf(), e))?;
+    if !bytes.starts_with(b"NRRD000") {
+        return Err(format("not a NRRD file".to_string()));
+    }
+
+    // The header is text lines up to the first empty one.
+    let mut fields = HashMap::new();
+    let mut position = 0;
+    let mut first = true;
+    while position < bytes.len() {
+        let end = bytes[position..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(bytes.len(), |i| position + i);
+        let line = String::from_utf8_lossy(&bytes[position..end]);
+        let line = line.trim_end_matches('\r');
+        position = end + 1;
+        if first {
+            first = false;
+            continue;
+        }
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with('#') || line.contains(":=") {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(": ")
+            .ok_or_else(|| format(format!("malformed header line '{}'", line)))?;
+        fields.insert(key.to_ascii_lowercase(), value.trim().to_string());
+    }
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| format(format!("missing '{}' field", name)))
+    };
+
+    let sample_type = SampleType::parse(field("type")?)
+        .ok_or_else(|| format(format!("unsupported type '{}'", field("type").unwrap())))?;
+    if field("dimension")? != "3" {
+        return Err(format(
+            "only 3 dimensional volumes are supported".to_string(),
+        ));
+    }
+    let sizes = parse_numbers(field("sizes")?).map_err(format)?;
+    // Sample indices are i32s.
+    if sizes.len() != 3
+        || sizes
+            .iter()
+            .any(|&s| s < 1.0 || s > i32::MAX as f64 || s.fract() != 0.0)
+    {
+        return Err(format("'sizes' needs three positive integers".to_string()));
+    }
+    let resolution = [sizes[0] as usize, sizes[1] as usize, sizes[2] as usize];
+    let count = resolution[0]
+        .checked_mul(resolution[1])
+        .and_then(|count| count.checked_mul(resolution[2]))
+        .ok_or_else(|| format("'sizes' are too large".to_string()))?;
+
+    let spacing = if let Some(directions) = fields.get("space directions") {
+        space_directions(directions).map_err(format)?
+    } else if let Some(spacings) = fields.get("spacings") {
+        let spacings = parse_numbers(spacings).map_err(format)?;
+        if spacings.len() != 3 {
+            return Err(format("'spacings' needs three numbers".to_string()));
+        }
+        Vec3::new(spacings[0], spacings[1], spacings[2])
+    } else {
+        Vec3::new(1.0, 1.0, 1.0)
+    };
+    let origin = match fields.get("space origin") {
+        Some(origin) => {
+            let origin = parse_numbers(origin).map_err(format)?;
+            if origin.len() != 3 {
+                return Err(format("'space origin' needs three numbers".to_string()));
+            }
+            Point3::new(origin[0], origin[1], origin[2])
+        }
+        None => Point3::default(),
+    };
+
+    let data = match fields.get("data file").or_else(|| fields.get("datafile")) {
+        Some(file) => {
+            let data_path = path.parent().unwrap_or_else(|| Path::new("")).join(file);
+            fs::read(&data_path).map_err(|e| VoxelError::Io(data_path, e))?
+        }
+        None => bytes[position.min(bytes.len())..].to_vec(),
+    };
+
+    let encoding = field("encoding")?;
+    let values = match encoding {
+        "ascii" | "text" | "txt" => {
+            let text = String::from_utf8_lossy(&data);
+            let values = parse_numbers(&text).map_err(format)?;
+            if values.len() < count {
+                return Err(format("not enough samples".to_string()));
+            }
+            values
+        }
+        "raw" | "gzip" | "gz" => {
+            let data = if encoding == "raw" {
+                data
+            } else {
+                gunzip(&data).map_err(format)?
+            };
+            let size = sample_type.size();
+            let length = count
+                .checked_mul(size)
+                .ok_or_else(|| format("'sizes' are too large".to_string()))?;
+            let skip = match fields.get("byte skip").map(String::as_str) {
+                None => 0,
+                Some("-1") => data.len().saturating_sub(length),
+                Some(skip) => skip
+                    .parse()
+                    .map_err(|_| format(format!("bad byte skip '{}'", skip)))?,
+            };
+            let data = skip
+                .checked_add(length)
+                .and_then(|end| data.get(skip..end))
+                .ok_or_else(|| format("not enough samples".to_string()))?;
+            let big_endian = match fields.get("endian").map(String::as_str) {
+                Some("big") => true,
+                Some("little") => false,
+                None if size == 1 => false,
+                Some(other) => return Err(format(format!("unknown endian '{}'", other))),
+                None => return Err(format("missing 'endian' field".to_string())),
+            };
+            data.chunks(size)
+                .map(|sample| {
+                    if big_endian {
+                        let mut little = sample.to_vec();
+                        little.reverse();
+                        sample_type.decode(&little)
+                    } else {
+                        sample_type.decode(sample)
+                    }
+                })
+                .collect()
+        }
+        other => return Err(format(format!("unsupported encoding '{}'", other))),
+    };
+
+    Ok(SparseGrid::new_from_dense(
+        resolution,
+        &values[..count],
+        origin,
+        spacing,
+    ))
+}
+
+fn parse_numbers(text: &str) -> Result<Vec<f64>, String> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse().map_err(|_| format!("bad number '{}'", s)))
+        .collect()
+}
+
+/// The spacing along each axis from three axis aligned direction vectors.
+fn space_directions(text: &str) -> Result<Vec3, String> {
+    let numbers = parse_numbers(text)?;
+    if numbers.len() != 9 {
+        return Err("'space directions' needs three vectors".to_string());
+    }
+    for (i, n) in numbers.iter().enumerate() {
+        if i % 4 != 0 && *n != 0.0 {
+            return Err("only axis aligned space directions are supported".to_string());
+        }
+    }
+    Ok(Vec3::new(numbers[0], numbers[4], numbers[8]))
+}
+
+/// Decompresses a gzip stream.
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+    const FHCRC: u8 = 2;
+    if data.len() < 10 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err("data isn't gzip compressed".to_string());
+    }
+    let flags = data[3];
+    let mut position = 10;
+    if flags & FEXTRA != 0 {
+        let length = data
+            .get(position..position + 2)
+            .ok_or("truncated gzip header")?;
+        position += 2 + u16::from_le_bytes([length[0], length[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(position..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or("truncated gzip header")?;
+            position += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        position += 2;
+    }
+    let stream = data.get(position..).ok_or("truncated gzip header")?;
+    miniz_oxide::inflate::decompress_to_vec(stream)
+        .map_err(|e| format!("corrupt gzip data: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` to a file of the temporary directory unique to this test.
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rt-voxel-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn nrrd(fields: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!("NRRD0004\n{}\n", fields).into_bytes();
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn interpolates_ascii_samples() {
+        let path = write(
+            "ascii.nrrd",
+            &nrrd(
+                "type: float\ndimension: 3\nsizes: 2 1 1\nencoding: ascii\nspacings: 2 1 1\n\
+                 space origin: (1,0,0)\n",
+                b"1 3\n",
+            ),
+        );
+        let grid = load_nrrd(&path).unwrap();
+        assert_eq!(grid.density(&Point3::new(2.0, 0.0, 0.0)), 2.0);
+        assert_eq!(grid.max_density(), 3.0);
+        let bbox = grid.bounding_box().unwrap();
+        assert_eq!((bbox.minimum.x, bbox.maximum.x), (-1.0, 5.0));
+    }
+
+    #[test]
+    fn reads_big_endian_gzip_samples() {
+        let raw: Vec<u8> = [1u16, 0, 0, 513]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let mut gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        gzip.extend(miniz_oxide::deflate::compress_to_vec(&raw, 6));
+        let path = write(
+            "gzip.nrrd",
+            &nrrd(
+                "type: uint16\ndimension: 3\nsizes: 2 2 1\nencoding: gzip\nendian: big\n",
+                &gzip,
+            ),
+        );
+        let grid = load_nrrd(&path).unwrap();
+        assert_eq!(grid.get([0, 0, 0]), 1.0);
+        assert_eq!(grid.get([1, 1, 0]), 513.0);
+        assert_eq!(grid.brick_count(), 1);
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        let path = write(
+            "overflow.nrrd",
+            &nrrd(
+                "type: double\ndimension: 3\nsizes: 2147483647 2147483647 2147483647\n\
+                 encoding: raw\nendian: little\n",
+                &[0; 8],
+            ),
+        );
+        assert!(matches!(load_nrrd(&path), Err(VoxelError::Format(..))));
+        let path = write(
+            "huge.nrrd",
+            &nrrd(
+                "type: uint8\ndimension: 3\nsizes: 1e300 1 1\nencoding: raw\n",
+                &[0; 8],
+            ),
+        );
+        assert!(matches!(load_nrrd(&path), Err(VoxelError::Format(..))));
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_files() {
+        let cases: &[(&str, &[u8])] = &[
+            (
+                "type: uint8\ndimension: 3\nsizes: 2 2 2\nencoding: raw\n",
+                &[1; 7],
+            ),
+            (
+                "type: uint8\ndimension: 3\nsizes: 1 1 1\nencoding: raw\n\
+                 byte skip: 18446744073709551615\n",
+                &[1],
+            ),
+            (
+                "type: uint16\ndimension: 3\nsizes: 1 1 1\nencoding: raw\n",
+                &[1, 0],
+            ),
+            (
+                "type: uint8\ndimension: 2\nsizes: 1 1\nencoding: raw\n",
+                &[1],
+            ),
+            (
+                "type: uint8\ndimension: 3\nsizes: 1 1 1\nencoding: gzip\n",
+                &[1, 2, 3],
+            ),
+            (
+                "type: uint8\ndimension: 3\nsizes: 1 1 1\nencoding: ascii\n",
+                b"x",
+            ),
+            (
+                "type: complex\ndimension: 3\nsizes: 1 1 1\nencoding: raw\n",
+                &[1],
+            ),
+            ("dimension: 3\nsizes: 1 1 1\nencoding: raw\n", &[1]),
+            ("type: uint8\ndimension: 3\nsizes: 1 1 1\n", &[1]),
+        ];
+        for (i, (fields, data)) in cases.iter().enumerate() {
+            let path = write(&format!("bad{}.nrrd", i), &nrrd(fields, data));
+            assert!(
+                matches!(load_nrrd(&path), Err(VoxelError::Format(..))),
+                "case {}",
+                i
+            );
+        }
+        let path = write("magic.nrrd", b"P6\n1 1\n255\n");
+        assert!(load_nrrd(&path).is_err());
+        assert!(matches!(
+            load_nrrd(Path::new("/nonexistent/volume.nrrd")),
+            Err(VoxelError::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+use image::ImageFormat;
+use rt::{
+    camera::CameraSettings,
+    integrator::{IntegratorPtr, MisPathTracer, NormalIntegrator, PathTracer, SpectralPathTracer},
+    renderer::RenderSettings,
+    scene::{self, Scene},
+    scheduler::TileOrder,
+};
+use std::{error::Error, path::Path, path::PathBuf, sync::Arc};
+use structopt::StructOpt;
+use vec3::{Point3, Vec3};
+
+/// Scenes compiled into the binary, selectable by name. Relative paths inside them are
+/// resolved against the `scenes` directory of the source tree.
+const BUILTIN_SCENES: &[(&str, &str)] = &[
+    ("cornell_box", include_str!("../scenes/cornell_box.toml")),
+    (
+        "cornell_smoke",
+        include_str!("../scenes/cornell_smoke.toml"),
+    ),
+    ("earth", include_str!("../scenes/earth.toml")),
+    ("motion_blur", include_str!("../scenes/motion_blur.toml")),
+    ("outdoor", include_str!("../scenes/outdoor.toml")),
+    ("prism", include_str!("../scenes/prism.toml")),
+    ("random_scene", include_str!("../scenes/random_scene.toml")),
+    ("simple_light", include_str!("../scenes/simple_light.toml")),
+    ("spotlight", include_str!("../scenes/spotlight.toml")),
+    (
+        "two_perlin_spheres",
+        include_str!("../scenes/two_perlin_spheres.toml"),
+    ),
+    ("two_spheres", include_str!("../scenes/two_spheres.toml")),
+    ("volumes", include_str!("../scenes/volumes.toml")),
+];
+
+const DEFAULT_WIDTH: u32 = 3840;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "raytracing-in-one-weekend",
+    about = "Renders a scene to an image file.",
+    after_help = "Built-in scenes: cornell_box, cornell_smoke, earth, motion_blur, outdoor, prism, \
+                  random_scene, simple_light, spotlight, two_perlin_spheres, two_spheres, volumes"
+)]
+pub struct Options {
+    /// Name of a built-in scene or path to a scene file
+    #[structopt(default_value = "cornell_smoke")]
+    pub scene: String,
+
+    /// Image width in pixels, derived from the height and the aspect ratio when omitted
+    #[structopt(short, long)]
+    pub width: Option<u32>,
+
+    /// Image height in pixels, derived from the width and the aspect ratio when omitted
+    #[structopt(short = "H", long)]
+    pub height: Option<u32>,
+
+    /// Samples per pixel
+    #[structopt(short, long, default_value = "200", parse(try_from_str = parse_positive))]
+    pub spp: u32,
+
+    /// Maximum number of bounces per path
+    #[structopt(short = "d", long, default_value = "50", parse(try_from_str = parse_positive))]
+    pub max_depth: u32,
+
+    /// Integrator used to shade rays: mis (path tracing with light sampling), spectral (mis
+    /// with wavelengths, for dispersion), path or normals
+    #[structopt(short, long, default_value = "mis")]
+    pub integrator: String,
+
+    /// Output file
+    #[structopt(short, long, parse(from_os_str), default_value = "target/render.png")]
+    pub output: PathBuf,
+
+    /// Output format (png, jpg, bmp, tga, tiff, gif or ico), guessed from the output file when omitted
+    #[structopt(short, long)]
+    pub format: Option<String>,
+
+    /// Number of worker threads, defaults to the number of logical cores
+    #[structopt(short = "j", long)]
+    pub threads: Option<usize>,
+
+    /// Width and height of the tiles the image is split into
+    #[structopt(long, default_value = "32", parse(try_from_str = parse_positive))]
+    pub tile_size: u32,
+
+    /// Order in which tiles are rendered: scanline, spiral or hilbert
+    #[structopt(long, default_value = "spiral")]
+    pub tile_order: TileOrder,
+
+    /// Seed for the random number generators, which makes renders reproducible
+    #[structopt(long)]
+    pub seed: Option<u64>,
+
+    /// Camera position, as x,y,z
+    #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_vec3))]
+    pub lookfrom: Option<Point3>,
+
+    /// Point the camera looks at, as x,y,z
+    #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_vec3))]
+    pub lookat: Option<Point3>,
+
+    /// Camera up direction, as x,y,z
+    #[structopt(long, allow_hyphen_values = true, parse(try_from_str = parse_vec3))]
+    pub vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[structopt(long)]
+    pub vfov: Option<f64>,
+
+    /// Lens aperture, 0 disables depth of field
+    #[structopt(long)]
+    pub aperture: Option<f64>,
+
+    /// Distance to the plane in focus
+    #[structopt(long)]
+    pub focus_dist: Option<f64>,
+}
+
+impl Options {
+    /// Loads the selected scene and applies the camera overrides to it.
+    pub fn load_scene(&self) -> Result<Scene, Box<dyn Error>> {
+        let builtin = BUILTIN_SCENES.iter().find(|(name, _)| *name == self.scene);
+
+        let mut scene = match builtin {
+            Some((name, source)) => {
+                let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("scenes")
+                    .join(format!("{}.toml", name));
+                scene::parse_scene(source, &path)?
+            }
+            None => {
+                let path = Path::new(&self.scene);
+                if !path.exists() {
+                    let names: Vec<&str> = BUILTIN_SCENES.iter().map(|(name, _)| *name).collect();
+                    return Err(format!(
+                        "'{}' is neither a scene file nor a built-in scene ({})",
+                        self.scene,
+                        names.join(", ")
+                    )
+                    .into());
+                }
+                scene::load_scene(path)?
+            }
+        };
+
+        self.apply_camera_overrides(&mut scene.camera);
+
+        Ok(scene)
+    }
+
+    pub fn render_settings(&self, aspect_ratio: f64) -> RenderSettings {
+        let (width, height) = self.resolution(aspect_ratio);
+        RenderSettings {
+            width,
+            height,
+            samples_per_pixel: self.spp,
+            threads: self.thread_count(),
+            tile_size: self.tile_size,
+            tile_order: self.tile_order,
+            seed: self.seed,
+        }
+    }
+
+    pub fn integrator(&self) -> Result<IntegratorPtr, Box<dyn Error>> {
+        match self.integrator.as_str() {
+            "mis" => Ok(Arc::new(MisPathTracer::new(self.max_depth))),
+            "spectral" => Ok(Arc::new(SpectralPathTracer::new(self.max_depth))),
+            "path" => Ok(Arc::new(PathTracer::new(self.max_depth))),
+            "normals" => Ok(Arc::new(NormalIntegrator)),
+            _ => Err(format!(
+                "unknown integrator '{}', expected mis, spectral, path or normals",
+                self.integrator
+            )
+            .into()),
+        }
+    }
+
+    /// Image size in pixels for a camera with the given aspect ratio.
+    fn resolution(&self, aspect_ratio: f64) -> (u32, u32) {
+        let (width, height) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, (width as f64 / aspect_ratio) as u32),
+            (None, Some(height)) => ((height as f64 * aspect_ratio) as u32, height),
+            (None, None) => (DEFAULT_WIDTH, (DEFAULT_WIDTH as f64 / aspect_ratio) as u32),
+        };
+        (width.max(1), height.max(1))
+    }
+
+    pub fn image_format(&self) -> Result<ImageFormat, Box<dyn Error>> {
+        let format = match &self.format {
+            // Reuses the extension lookup, which is the only one image exposes.
+            Some(format) => ImageFormat::from_path(Path::new("image").with_extension(format))
+                .map_err(|_| format!("unknown image format '{}'", format))?,
+            None => ImageFormat::from_path(&self.output)?,
+        };
+        match format {
+            ImageFormat::Png
+            | ImageFormat::Jpeg
+            | ImageFormat::Bmp
+            | ImageFormat::Tga
+            | ImageFormat::Tiff
+            | ImageFormat::Gif
+            | ImageFormat::Ico => Ok(format),
+            _ => Err(format!("can't write {:?} images", format).into()),
+        }
+    }
+
+    fn thread_count(&self) -> usize {
+        self.threads
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    fn apply_camera_overrides(&self, camera: &mut CameraSettings) {
+        if let Some(lookfrom) = self.lookfrom {
+            camera.lookfrom = lookfrom;
+        }
+        if let Some(lookat) = self.lookat {
+            camera.lookat = lookat;
+        }
+        if let Some(vup) = self.vup {
+            camera.vup = vup;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(aperture) = self.aperture {
+            camera.aperture = aperture;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+    }
+}
+
+fn parse_positive(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("'{}': {}", s, e)),
+    }
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| format!("'{}': {}", s, e))?;
+    match components[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("'{}': expected three comma separated numbers", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, structopt::clap::Error> {
+        Options::from_iter_safe(std::iter::once("rt").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn rejects_zero_and_negative_counts() {
+        for flag in &["--spp", "--max-depth", "--tile-size"] {
+            for value in &["0", "-3", "many"] {
+                let argument = format!("{}={}", flag, value);
+                let error = parse(&[&argument]).err().unwrap();
+                assert_eq!(error.kind, structopt::clap::ErrorKind::ValueValidation);
+            }
+        }
+        let options = parse(&["--spp", "1", "-d", "2", "--tile-size", "3"]).unwrap();
+        assert_eq!(
+            (options.spp, options.max_depth, options.tile_size),
+            (1, 2, 3)
+        );
+    }
+}
@@ -1,10 +1,16 @@
 // TODO write tests
 // TODO write docs
 
-use num;
 use rand::Rng;
 use std::{f64::consts::PI, ops};
 
+pub mod mat;
+pub mod quat;
+pub mod rng;
+
+pub use mat::{Mat3, Mat4};
+pub use quat::Quat;
+
 // TODO implement operation traits on reference to Vec3
 // Clone and Copy is necessary for operations
 #[derive(Debug, Clone, Copy)]
@@ -44,12 +50,12 @@ impl Vec3 {
     }
 
     pub fn random() -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         Vec3::new(rng.gen(), rng.gen(), rng.gen())
     }
 
     pub fn random_range(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         Vec3::new(
             rng.gen_range(min, max),
             rng.gen_range(min, max),
@@ -68,24 +74,37 @@ impl Vec3 {
     }
 
     pub fn random_unit_vector() -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         let a: f64 = rng.gen_range(0.0, 2.0 * PI);
         let z: f64 = rng.gen_range(-1.0, 1.0);
         let r = (1.0 - z * z).sqrt();
         Vec3::new(r * a.cos(), r * a.sin(), z)
     }
 
+    /// A direction around the z axis with a density proportional to the cosine of its angle
+    /// with that axis.
+    pub fn random_cosine_direction() -> Vec3 {
+        let mut rng = rng::thread_rng();
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let z = (1.0 - r2).sqrt();
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        Vec3::new(x, y, z)
+    }
+
     pub fn random_in_hemisphere(normal: &Vec3) -> Vec3 {
         let in_unit_sphere = Vec3::random_in_unit_sphere();
         if in_unit_sphere.dot(*normal) > 0.0 {
-            return in_unit_sphere;
+            in_unit_sphere
         } else {
-            return -in_unit_sphere;
+            -in_unit_sphere
         }
     }
 
     pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng::thread_rng();
         let mut p;
         loop {
             p = Vec3::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0), 0.0);
@@ -97,7 +116,7 @@ impl Vec3 {
     }
 
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
-        return v - n * v.dot(n) * 2.0;
+        v - n * v.dot(n) * 2.0
     }
 
     pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f64) -> Vec3 {
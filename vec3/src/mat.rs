@@ -0,0 +1,460 @@
+//! 3x3 and 4x4 matrices acting on column vectors, stored row by row. Angles are in
+//! radians.
+
+use crate::{Point3, Vec3};
+use std::ops;
+
+/// How small, relative to the scale of the matrix's columns, a determinant or pivot may get
+/// before the matrix counts as singular. Scaling a column scales both by the same factor,
+/// so tiny but well-shaped scales still invert.
+const SINGULAR_TOLERANCE: f64 = 1e-12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3 {
+    pub m: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    pub fn new(m: [[f64; 3]; 3]) -> Mat3 {
+        Mat3 { m }
+    }
+
+    pub fn identity() -> Mat3 {
+        Mat3::scaling(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn from_rows(r0: Vec3, r1: Vec3, r2: Vec3) -> Mat3 {
+        Mat3::new([[r0.x, r0.y, r0.z], [r1.x, r1.y, r1.z], [r2.x, r2.y, r2.z]])
+    }
+
+    pub fn from_columns(c0: Vec3, c1: Vec3, c2: Vec3) -> Mat3 {
+        Mat3::from_rows(c0, c1, c2).transpose()
+    }
+
+    /// Scales by a different factor along each axis.
+    pub fn scaling(factors: Vec3) -> Mat3 {
+        Mat3::new([
+            [factors.x, 0.0, 0.0],
+            [0.0, factors.y, 0.0],
+            [0.0, 0.0, factors.z],
+        ])
+    }
+
+    /// Rotates counterclockwise by `angle` around `axis`, looking down the axis.
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat3 {
+        let a = axis.unit_vector();
+        let (sin, cos) = angle.sin_cos();
+        let c = 1.0 - cos;
+        Mat3::new([
+            [
+                cos + a.x * a.x * c,
+                a.x * a.y * c - a.z * sin,
+                a.x * a.z * c + a.y * sin,
+            ],
+            [
+                a.y * a.x * c + a.z * sin,
+                cos + a.y * a.y * c,
+                a.y * a.z * c - a.x * sin,
+            ],
+            [
+                a.z * a.x * c - a.y * sin,
+                a.z * a.y * c + a.x * sin,
+                cos + a.z * a.z * c,
+            ],
+        ])
+    }
+
+    /// Rotates around x, then y, then z by the given angles.
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Mat3 {
+        Mat3::rotation(Vec3::new(0.0, 0.0, 1.0), z)
+            * Mat3::rotation(Vec3::new(0.0, 1.0, 0.0), y)
+            * Mat3::rotation(Vec3::new(1.0, 0.0, 0.0), x)
+    }
+
+    /// The rotation turning -z towards `direction` and y as close to `up` as possible, the
+    /// way a camera looks. `up` must not be parallel to `direction`.
+    pub fn look_at(direction: Vec3, up: Vec3) -> Mat3 {
+        let w = -direction.unit_vector();
+        let u = up.cross(w).unit_vector();
+        let v = w.cross(u);
+        Mat3::from_columns(u, v, w)
+    }
+
+    pub fn row(&self, i: usize) -> Vec3 {
+        Vec3::new(self.m[i][0], self.m[i][1], self.m[i][2])
+    }
+
+    pub fn column(&self, j: usize) -> Vec3 {
+        Vec3::new(self.m[0][j], self.m[1][j], self.m[2][j])
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        Mat3::from_rows(self.column(0), self.column(1), self.column(2))
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.row(0).dot(self.row(1).cross(self.row(2)))
+    }
+
+    /// The inverse, or None for a singular matrix.
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        let (c0, c1, c2) = (self.column(0), self.column(1), self.column(2));
+        // The determinant is the volume spanned by the columns, which is at most the
+        // product of their lengths.
+        if det.abs() <= SINGULAR_TOLERANCE * c0.length() * c1.length() * c2.length() {
+            return None;
+        }
+        // The rows of the inverse are cross products of the columns.
+        Some(Mat3::from_rows(
+            c1.cross(c2) / det,
+            c2.cross(c0) / det,
+            c0.cross(c1) / det,
+        ))
+    }
+
+    /// Transforms a surface normal, keeping it perpendicular to transformed tangents. This
+    /// multiplies by the inverse transpose up to a positive factor, so the result isn't
+    /// normalized, and works for singular matrices too.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let (c0, c1, c2) = (self.column(0), self.column(1), self.column(2));
+        let cofactor = c1.cross(c2) * n.x + c2.cross(c0) * n.y + c0.cross(c1) * n.z;
+        if self.determinant() < 0.0 {
+            -cofactor
+        } else {
+            cofactor
+        }
+    }
+}
+
+impl Default for Mat3 {
+    fn default() -> Mat3 {
+        Mat3::identity()
+    }
+}
+
+impl ops::Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+
+    /// The transform applying `rhs` first, then `self`.
+    fn mul(self, rhs: Mat3) -> Mat3 {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.row(i).dot(rhs.column(j));
+            }
+        }
+        Mat3::new(m)
+    }
+}
+
+impl ops::Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(
+            self.row(0).dot(rhs),
+            self.row(1).dot(rhs),
+            self.row(2).dot(rhs),
+        )
+    }
+}
+
+impl ops::Mul<f64> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, rhs: f64) -> Mat3 {
+        Mat3::from_rows(self.row(0) * rhs, self.row(1) * rhs, self.row(2) * rhs)
+    }
+}
+
+/// A 4x4 matrix for affine and projective transforms of points and vectors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { m }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::from_linear(Mat3::identity(), Vec3::default())
+    }
+
+    /// The affine transform applying `linear`, then moving by `translation`.
+    pub fn from_linear(linear: Mat3, translation: Vec3) -> Mat4 {
+        let l = &linear.m;
+        Mat4::new([
+            [l[0][0], l[0][1], l[0][2], translation.x],
+            [l[1][0], l[1][1], l[1][2], translation.y],
+            [l[2][0], l[2][1], l[2][2], translation.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4::from_linear(Mat3::identity(), offset)
+    }
+
+    pub fn scaling(factors: Vec3) -> Mat4 {
+        Mat4::from_linear(Mat3::scaling(factors), Vec3::default())
+    }
+
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4 {
+        Mat4::from_linear(Mat3::rotation(axis, angle), Vec3::default())
+    }
+
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Mat4 {
+        Mat4::from_linear(Mat3::from_euler(x, y, z), Vec3::default())
+    }
+
+    /// Places something at `eye` looking along -z at `target`, like `Mat3::look_at`. This
+    /// is the camera to world transform, its inverse is the view matrix.
+    pub fn look_at(eye: Point3, target: Point3, up: Vec3) -> Mat4 {
+        Mat4::from_linear(Mat3::look_at(target - eye, up), eye)
+    }
+
+    /// The upper left 3x3 part, which acts on vectors.
+    pub fn linear(&self) -> Mat3 {
+        let m = &self.m;
+        Mat3::new([
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ])
+    }
+
+    pub fn translation_part(&self) -> Vec3 {
+        Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Mat4::new(t)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        // Laplace expansion along the first row.
+        let m = &self.m;
+        let minor = |skip: usize| {
+            let c = match skip {
+                0 => [1, 2, 3],
+                1 => [0, 2, 3],
+                2 => [0, 1, 3],
+                _ => [0, 1, 2],
+            };
+            Mat3::new([1, 2, 3].map(|i| [m[i][c[0]], m[i][c[1]], m[i][c[2]]])).determinant()
+        };
+        (0..4)
+            .map(|j| {
+                let sign = if j % 2 == 0 { 1.0 } else { -1.0 };
+                sign * m[0][j] * minor(j)
+            })
+            .sum()
+    }
+
+    /// The inverse, found by Gauss-Jordan elimination, or None for a singular matrix.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        // Row operations scale with the columns, so pivots are compared with the largest
+        // entry of their column in the original matrix.
+        let mut column_scale = [0.0f64; 4];
+        for row in a.iter() {
+            for (scale, value) in column_scale.iter_mut().zip(row.iter()) {
+                *scale = scale.max(value.abs());
+            }
+        }
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() <= SINGULAR_TOLERANCE * column_scale[column] {
+                return None;
+            }
+            a.swap(column, pivot);
+            inv.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inv[column][j] *= scale;
+            }
+            for row in 0..4 {
+                let factor = a[row][column];
+                if row != column && factor != 0.0 {
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inv[row][j] -= factor * inv[column][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4::new(inv))
+    }
+
+    /// Transforms a point, dividing by w for projective matrices.
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let q = self.transform_vector(p) + self.translation_part();
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            q
+        } else {
+            q / w
+        }
+    }
+
+    /// Transforms a direction, ignoring the translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.linear() * *v
+    }
+
+    /// Transforms a surface normal with the inverse transpose of the linear part, up to a
+    /// positive factor. See `Mat3::transform_normal`.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        self.linear().transform_normal(n)
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Mat4 {
+        Mat4::identity()
+    }
+}
+
+impl ops::Mul<Mat4> for Mat4 {
+    type Output = Mat4;
+
+    /// The transform applying `rhs` first, then `self`.
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Mat4::new(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn assert_vec_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat4_close(a: &Mat4, b: &Mat4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.m[i][j] - b.m[i][j]).abs() < 1e-9, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn assert_mat3_close(a: &Mat3, b: &Mat3) {
+        assert_mat4_close(
+            &Mat4::from_linear(*a, Vec3::default()),
+            &Mat4::from_linear(*b, Vec3::default()),
+        );
+    }
+
+    fn affine() -> Mat4 {
+        Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::from_euler(0.3, -1.1, 2.0)
+            * Mat4::scaling(Vec3::new(2.0, 0.5, -3.0))
+    }
+
+    #[test]
+    fn mat3_inverse_undoes_the_matrix() {
+        let m = affine().linear();
+        let inverse = m.inverse().unwrap();
+        assert_mat3_close(&(inverse * m), &Mat3::identity());
+        assert_mat3_close(&(m * inverse), &Mat3::identity());
+        assert!((m.determinant() - -3.0).abs() < 1e-9);
+        let singular = Mat3::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert!(singular.inverse().is_none());
+        let flat = Mat3::from_rows(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(2.0, 4.0, 6.0 + 1e-14),
+            Vec3::new(0.0, 1.0, 1.0),
+        );
+        assert!(flat.inverse().is_none());
+    }
+
+    #[test]
+    fn tiny_scales_invert() {
+        for &scale in [1e-4, 1e-9].iter() {
+            let m = Mat3::scaling(Vec3::new(scale, scale, scale)) * affine().linear();
+            let inverse = m.inverse().unwrap();
+            assert_mat3_close(&(inverse * m), &Mat3::identity());
+
+            let m = Mat4::scaling(Vec3::new(scale, scale, scale)) * affine();
+            let inverse = m.inverse().unwrap();
+            assert_mat4_close(&(inverse * m), &Mat4::identity());
+        }
+        let squashed = Mat4::scaling(Vec3::new(1.0, 1e-20, 1.0));
+        assert!(squashed.inverse().is_some());
+        assert!(Mat4::scaling(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn mat4_inverse_undoes_the_matrix() {
+        let m = affine();
+        let inverse = m.inverse().unwrap();
+        assert_mat4_close(&(inverse * m), &Mat4::identity());
+        assert_mat4_close(&(m * inverse), &Mat4::identity());
+        assert!((m.determinant() - m.linear().determinant()).abs() < 1e-9);
+
+        let mut projective = m;
+        projective.m[3] = [0.5, 0.0, 1.0, 2.0];
+        let inverse = projective.inverse().unwrap();
+        assert_mat4_close(&(inverse * projective), &Mat4::identity());
+        let p = Point3::new(0.3, 0.2, -0.7);
+        assert_vec_close(inverse.transform_point(&projective.transform_point(&p)), p);
+
+        let singular = Mat4::scaling(Vec3::new(1.0, 1.0, 0.0));
+        assert!(singular.inverse().is_none());
+        assert_eq!(singular.determinant(), 0.0);
+    }
+
+    #[test]
+    fn rotation_turns_counterclockwise() {
+        let r = Mat3::rotation(Vec3::new(0.0, 0.0, 2.0), PI / 2.0);
+        assert_vec_close(r * Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_mat3_close(&(r * r.transpose()), &Mat3::identity());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let m = affine().linear();
+        let (a, b) = (Vec3::new(1.0, 2.0, 0.5), Vec3::new(-0.3, 0.0, 1.0));
+        let n = m.transform_normal(&a.cross(b));
+        assert!(n.dot(m * a).abs() < 1e-9 && n.dot(m * b).abs() < 1e-9);
+        // The transformed normal keeps pointing to the same side.
+        let c = Vec3::new(0.2, -0.4, 0.9);
+        assert!(a.cross(b).dot(c).signum() == n.dot(m * c).signum());
+    }
+
+    #[test]
+    fn look_at_places_the_eye() {
+        let eye = Point3::new(1.0, 2.0, 3.0);
+        let target = Point3::new(-2.0, 0.0, 1.0);
+        let m = Mat4::look_at(eye, target, Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_close(m.transform_point(&Point3::default()), eye);
+        let forward = m.transform_vector(&Vec3::new(0.0, 0.0, -1.0));
+        assert_vec_close(forward, (target - eye).unit_vector());
+        assert!(m.transform_vector(&Vec3::new(0.0, 1.0, 0.0)).y > 0.0);
+        let view = m.inverse().unwrap();
+        assert_vec_close(view.transform_point(&eye), Point3::default());
+    }
+}
@@ -0,0 +1,291 @@
+//! Quaternions for rotations that interpolate smoothly. Angles are in radians.
+
+use crate::mat::{Mat3, Mat4};
+use crate::Vec3;
+use std::ops;
+
+/// The quaternion `w + xi + yj + zk`. Rotations are unit quaternions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Quat {
+    pub fn new(x: f64, y: f64, z: f64, w: f64) -> Quat {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Quat {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotates counterclockwise by `angle` around `axis`, looking down the axis.
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Quat {
+        let a = axis.unit_vector();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        Quat::new(a.x * sin, a.y * sin, a.z * sin, cos)
+    }
+
+    /// The axis and the angle in [0, π] of a unit quaternion. The axis is arbitrary when
+    /// there's no rotation.
+    pub fn to_axis_angle(&self) -> (Vec3, f64) {
+        let q = if self.w < 0.0 { -*self } else { *self };
+        let sin = q.vector().length();
+        if sin < 1e-12 {
+            return (Vec3::new(1.0, 0.0, 0.0), 0.0);
+        }
+        (q.vector() / sin, 2.0 * sin.atan2(q.w))
+    }
+
+    /// Rotates around x, then y, then z by the given angles, like `Mat3::from_euler`.
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Quat {
+        Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), z)
+            * Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), y)
+            * Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), x)
+    }
+
+    /// The rotation of a rotation matrix, which must be orthonormal with determinant 1.
+    pub fn from_mat3(r: &Mat3) -> Quat {
+        let m = &r.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        // Start from the largest component to stay clear of dividing by small numbers.
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Quat::new(
+                (m[2][1] - m[1][2]) / s,
+                (m[0][2] - m[2][0]) / s,
+                (m[1][0] - m[0][1]) / s,
+                s / 4.0,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quat::new(
+                s / 4.0,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[2][1] - m[1][2]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quat::new(
+                (m[0][1] + m[1][0]) / s,
+                s / 4.0,
+                (m[1][2] + m[2][1]) / s,
+                (m[0][2] - m[2][0]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quat::new(
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                s / 4.0,
+                (m[1][0] - m[0][1]) / s,
+            )
+        };
+        q.normalize()
+    }
+
+    /// The rotation as a matrix.
+    pub fn to_mat3(&self) -> Mat3 {
+        let Quat { x, y, z, w } = self.normalize();
+        Mat3::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ])
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_linear(self.to_mat3(), Vec3::default())
+    }
+
+    /// The x, y and z parts.
+    pub fn vector(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(&self, other: Quat) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Quat {
+        let length = self.length();
+        Quat::new(
+            self.x / length,
+            self.y / length,
+            self.z / length,
+            self.w / length,
+        )
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(&self) -> Quat {
+        let length_squared = self.dot(*self);
+        let c = self.conjugate();
+        Quat::new(
+            c.x / length_squared,
+            c.y / length_squared,
+            c.z / length_squared,
+            c.w / length_squared,
+        )
+    }
+
+    /// Rotates `v` by a unit quaternion.
+    pub fn rotate(&self, v: &Vec3) -> Vec3 {
+        let u = self.vector();
+        let t = u.cross(*v) * 2.0;
+        *v + t * self.w + u.cross(t)
+    }
+
+    /// Spherical linear interpolation between unit quaternions, turning at a constant rate
+    /// along the shorter way from `self` at `t` = 0 to `other` at `t` = 1.
+    pub fn slerp(&self, other: Quat, t: f64) -> Quat {
+        let mut cos = self.dot(other);
+        let other = if cos < 0.0 {
+            cos = -cos;
+            -other
+        } else {
+            other
+        };
+        let (a, b) = if cos > 0.9995 {
+            // Nearly parallel, where the sines vanish and a straight line is just as good.
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat::new(
+            a * self.x + b * other.x,
+            a * self.y + b * other.y,
+            a * self.z + b * other.z,
+            a * self.w + b * other.w,
+        )
+        .normalize()
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Quat {
+        Quat::identity()
+    }
+}
+
+impl ops::Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl ops::Mul<Quat> for Quat {
+    type Output = Quat;
+
+    /// The rotation applying `rhs` first, then `self`.
+    fn mul(self, rhs: Quat) -> Quat {
+        let (a, b) = (self.vector(), rhs.vector());
+        let v = b * self.w + a * rhs.w + a.cross(b);
+        Quat::new(v.x, v.y, v.z, self.w * rhs.w - a.dot(b))
+    }
+}
+
+impl ops::Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        self.rotate(&rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn assert_vec_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    /// Equal as rotations, where q and -q are the same.
+    fn assert_same_rotation(a: Quat, b: Quat) {
+        assert!((a.dot(b).abs() - 1.0).abs() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn matches_the_matrix_rotation() {
+        let axis = Vec3::new(1.0, -2.0, 0.5);
+        let q = Quat::from_axis_angle(axis, 1.2);
+        let v = Vec3::new(0.3, 0.7, -1.1);
+        assert_vec_close(q * v, Mat3::rotation(axis, 1.2) * v);
+        assert_vec_close(
+            Quat::from_euler(0.3, -1.1, 2.0) * v,
+            Mat3::from_euler(0.3, -1.1, 2.0) * v,
+        );
+        assert_vec_close(q.inverse() * (q * v), v);
+    }
+
+    #[test]
+    fn round_trips_through_mat3() {
+        // Each branch of from_mat3: a small rotation and half turns around each axis.
+        let rotations = [
+            Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 0.4),
+            Quat::from_axis_angle(Vec3::new(1.0, 0.1, 0.2), 3.0),
+            Quat::from_axis_angle(Vec3::new(0.1, 1.0, 0.2), 3.0),
+            Quat::from_axis_angle(Vec3::new(0.1, 0.2, 1.0), 3.0),
+        ];
+        for q in rotations.iter() {
+            assert_same_rotation(Quat::from_mat3(&q.to_mat3()), *q);
+        }
+    }
+
+    #[test]
+    fn round_trips_through_axis_angle() {
+        let axis = Vec3::new(0.0, 3.0, 4.0);
+        let (a, angle) = Quat::from_axis_angle(axis, 2.5).to_axis_angle();
+        assert_vec_close(a, axis.unit_vector());
+        assert!((angle - 2.5).abs() < 1e-9);
+        assert_eq!(Quat::identity().to_axis_angle().1, 0.0);
+    }
+
+    #[test]
+    fn slerp_interpolates_the_angle() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quat::from_axis_angle(axis, 0.2);
+        let b = Quat::from_axis_angle(axis, 1.4);
+        assert_same_rotation(a.slerp(b, 0.0), a);
+        assert_same_rotation(a.slerp(b, 1.0), b);
+        assert_same_rotation(a.slerp(b, 0.5), Quat::from_axis_angle(axis, 0.8));
+        assert_same_rotation(a.slerp(b, 0.25), Quat::from_axis_angle(axis, 0.5));
+        // Takes the shorter way when the signs disagree.
+        assert_same_rotation(a.slerp(-b, 0.5), Quat::from_axis_angle(axis, 0.8));
+        // Nearly equal rotations interpolate linearly and stay normalized.
+        let c = Quat::from_axis_angle(axis, 0.2001);
+        assert!((a.slerp(c, 0.5).length() - 1.0).abs() < 1e-12);
+        let half_turn = Quat::from_axis_angle(axis, PI);
+        let (_, angle) = Quat::identity().slerp(half_turn, 0.5).to_axis_angle();
+        assert!((angle - PI / 2.0).abs() < 1e-9);
+    }
+}
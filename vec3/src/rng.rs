@@ -0,0 +1,45 @@
+//! A per-thread random number generator that, unlike `rand::thread_rng`, can be seeded.
+//! Every random sample taken by the renderer goes through `thread_rng`, so seeding each
+//! worker thread makes a render reproducible.
+
+use rand::{rngs::StdRng, Error, RngCore, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static THREAD_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Handle to the generator of the current thread, see `thread_rng`.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadRng {
+    _private: (),
+}
+
+/// Returns a handle to the generator of the current thread. It starts out seeded from the
+/// operating system until `seed` is called on that thread.
+pub fn thread_rng() -> ThreadRng {
+    ThreadRng { _private: () }
+}
+
+/// Reseeds the generator of the current thread.
+pub fn seed(seed: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+impl RngCore for ThreadRng {
+    fn next_u32(&mut self) -> u32 {
+        THREAD_RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        THREAD_RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        THREAD_RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        THREAD_RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}